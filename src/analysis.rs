@@ -0,0 +1,271 @@
+//! Analysis helpers for signatures, used to vet rule sets before loading them into a tree, and to
+//! tidy up the rule sets already loaded.
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use crate::pattern::{Pattern, PatternBuilder};
+use crate::{SignatureDecisionTree, SignatureId, SignatureIndex};
+
+/// Bytes that dominate real binaries (padding, fill and alignment), so signatures starting with
+/// them are tried at far more offsets than their length suggests.
+const COMMON_BYTES: [u8; 4] = [0x00, 0xff, 0x90, 0xcc];
+
+/// Represents the predicted scan-time cost of a signature.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PatternCost {
+	/// The expected number of bytes compared per scanned offset, assuming uniformly random input.
+	pub bytes_per_offset: f64,
+	/// The probability that the signature matches at a random offset.
+	pub match_probability: f64,
+	/// Whether the first non-wildcard byte is one that dominates real binaries, such as `0x00`
+	/// or `0x90`, which makes the signature much more expensive on real data than predicted.
+	pub common_first_byte: bool
+}
+
+/// Estimate the scan-time cost of a signature from its length and mask density. If masks goes
+/// unspecified, it will be assumed to be all ones. A byte whose mask keeps `k` bits matches a
+/// random input byte with probability `2^-k`, so the comparisons expected at each offset are the
+/// sum over the signature of the probability of getting that far.
+/// ```rust
+/// use dectree_rs::analysis::estimate_cost;
+///
+/// let exact = estimate_cost(&[0x55, 0x8b, 0xec], None);
+/// let loose = estimate_cost(&[0x55, 0x00, 0x00], Some(&[0xff, 0x00, 0x00]));
+/// assert!(exact.bytes_per_offset < loose.bytes_per_offset);
+/// assert!(exact.match_probability < loose.match_probability);
+/// assert!(estimate_cost(&[0x00, 0x00, 0x8b], None).common_first_byte);
+/// ```
+pub fn estimate_cost(bytes: &[u8], masks: Option<&[u8]>) -> PatternCost {
+	let mask_at = |index: usize| masks.map_or(0xff, |masks| masks[index]);
+	let mut bytes_per_offset = 0.0;
+	let mut reach = 1.0;
+	for index in 0..bytes.len() {
+		bytes_per_offset += reach;
+		reach *= 0.5f64.powi(mask_at(index).count_ones() as i32);
+	}
+	let common_first_byte = (0..bytes.len())
+		.find(|&index| mask_at(index) != 0x00)
+		.is_some_and(|index| COMMON_BYTES.contains(&(bytes[index] & mask_at(index))));
+	PatternCost {
+		bytes_per_offset,
+		match_probability: reach,
+		common_first_byte
+	}
+}
+
+/// Represents a group of near-duplicate signatures, such as the variants of one rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureCluster {
+	/// The signatures in the cluster, in the order they were added.
+	pub members: Vec<SignatureId>,
+	/// A single pattern matching everything the members match: the bytes they all agree on, with
+	/// wildcards where they differ.
+	pub merged: Pattern,
+	/// The number of bytes `merged` wildcards that aren't wildcards in every member.
+	pub added_wildcards: usize
+}
+
+/// Group the signatures of `tree`, disabled ones included, into clusters of near duplicates. Two
+/// signatures are similar when they have the same length, start with the same `min_prefix` bytes
+/// (compared under their masks) and differ in at most `max_distance` bytes, and clusters are the
+/// groups linked by similar pairs. Only clusters of more than one signature are returned, each
+/// with a merged pattern that could replace its members, ordered by their first member.
+///
+/// Signatures are compared pairwise within each group sharing a length and prefix, so a short
+/// `min_prefix` is slow on large rule sets.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::analysis::cluster_signatures;
+///
+/// let mut tree = SignatureDecisionTree::new();
+/// let first = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec, 0x83, 0xec, 0x10], None, 1).unwrap();
+/// let second = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec, 0x83, 0xec, 0x20], None, 2).unwrap();
+/// tree.add_signature(vec![0x31, 0xc0, 0xc3], None, 3);
+/// let clusters = cluster_signatures(&tree, 1, 2);
+/// assert_eq!(clusters.len(), 1);
+/// assert_eq!(clusters[0].members, vec![first, second]);
+/// assert_eq!(clusters[0].merged.masks(), &[0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+/// ```
+pub fn cluster_signatures<T, S>(tree: &SignatureDecisionTree<T, S>, max_distance: usize, min_prefix: usize) -> Vec<SignatureCluster>
+	where T: Clone, S: BuildHasher {
+	let signatures = tree.signatures.iter()
+		.map(|sig| (&sig.bytes[..], sig.masks.to_vec(sig.bytes.len())))
+		.collect::<Vec<_>>();
+	let mut groups: HashMap<_, Vec<usize>> = HashMap::new();
+	for (index, (bytes, masks)) in signatures.iter().enumerate() {
+		let prefix = min_prefix.min(bytes.len());
+		groups.entry((bytes.len(), &bytes[..prefix], &masks[..prefix])).or_default().push(index);
+	}
+	// Signatures are linked into clusters with a union-find over their indices.
+	let mut parents = (0..signatures.len()).collect::<Vec<_>>();
+	for ((length, _, _), members) in groups.iter().filter(|((length, _, _), _)| *length >= min_prefix) {
+		for (position, &first) in members.iter().enumerate() {
+			for &second in &members[position + 1..] {
+				let distance = (0..*length)
+					.filter(|&i| signatures[first].0[i] != signatures[second].0[i] || signatures[first].1[i] != signatures[second].1[i])
+					.count();
+				if distance <= max_distance {
+					let (first, second) = (root(&mut parents, first), root(&mut parents, second));
+					parents[first.max(second)] = first.min(second);
+				}
+			}
+		}
+	}
+	let mut clusters: Vec<Vec<usize>> = Vec::new();
+	let mut cluster_of = HashMap::new();
+	for index in 0..signatures.len() {
+		let cluster = *cluster_of.entry(root(&mut parents, index)).or_insert_with(|| {
+			clusters.push(Vec::new());
+			clusters.len() - 1
+		});
+		clusters[cluster].push(index);
+	}
+	clusters.into_iter()
+		.filter(|members| members.len() > 1)
+		.map(|members| {
+			let (bytes, masks) = &signatures[members[0]];
+			let mut added_wildcards = 0;
+			let mut builder = PatternBuilder::new();
+			for i in 0..bytes.len() {
+				let agreed = members.iter().all(|&member| signatures[member].0[i] == bytes[i] && signatures[member].1[i] == masks[i]);
+				if agreed {
+					builder = builder.masked(bytes[i], masks[i]);
+				} else {
+					added_wildcards += 1;
+					builder = builder.wildcard(1);
+				}
+			}
+			SignatureCluster {
+				members: members.into_iter().map(|member| SignatureId(member as SignatureIndex)).collect(),
+				merged: builder.build().expect("clustered signatures are never empty"),
+				added_wildcards
+			}
+		})
+		.collect()
+}
+
+/// Represents the mask changes that would make a signature match a sample it missed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WideningSuggestion {
+	/// The widened masks, one per signature byte.
+	pub masks: Vec<u8>,
+	/// The positions of the signature bytes whose masks were widened.
+	pub changed: Vec<usize>,
+	/// The places in the corpus the widened signature would newly match, as the index of the
+	/// buffer and the offset in it. Places the signature already matched aren't included.
+	pub newly_matched: Vec<(usize, usize)>
+}
+
+/// Work out the smallest change to the masks of the signature with the id `id` that makes it match
+/// `sample` at `offset`, for a sample the signature should have matched but didn't. Only the bits
+/// in which the sample differs from the signature are cleared, so the widened masks may need
+/// rounding to whole nibbles or bytes to be written in most rule formats. `corpus` is scanned to
+/// show what else the widened signature would catch.
+///
+/// Returns `None` if the tree has no signature with that id or the sample ends before the
+/// signature would.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::analysis::suggest_widening;
+///
+/// let mut tree = SignatureDecisionTree::new();
+/// let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec, 0x83], None, 1).unwrap();
+/// let corpus: [&[u8]; 1] = [&[0x00, 0x55, 0x8b, 0xed, 0x83]];
+/// let suggestion = suggest_widening(&tree, id, &[0x55, 0x8b, 0xe5, 0x83], 0, &corpus).unwrap();
+/// assert_eq!(suggestion.masks, vec![0xff, 0xff, 0xf6, 0xff]);
+/// assert_eq!(suggestion.changed, vec![2]);
+/// // The widened signature also accepts `ED` in place of `EC`.
+/// assert_eq!(suggestion.newly_matched, vec![(0, 1)]);
+/// ```
+pub fn suggest_widening<T, S>(tree: &SignatureDecisionTree<T, S>, id: SignatureId, sample: &[u8], offset: usize, corpus: &[&[u8]]) -> Option<WideningSuggestion>
+	where T: Clone, S: BuildHasher {
+	let sig = tree.signatures.get(id.0 as usize)?;
+	let window = sample.get(offset..offset.checked_add(sig.bytes.len())?)?;
+	let original = sig.masks.to_vec(sig.bytes.len());
+	let mut masks = original.clone();
+	let mut changed = Vec::new();
+	for (i, (&byte, &expected)) in window.iter().zip(&sig.bytes).enumerate() {
+		let differing = (byte ^ expected) & masks[i];
+		if differing != 0 {
+			masks[i] &= !differing;
+			changed.push(i);
+		}
+	}
+	let matches_at = |buffer: &[u8], masks: &[u8]| buffer.iter().zip(&sig.bytes).zip(masks).all(|((&byte, &expected), &mask)| byte & mask == expected & mask);
+	let mut newly_matched = Vec::new();
+	if !changed.is_empty() {
+		for (index, buffer) in corpus.iter().enumerate() {
+			for start in 0..buffer.len().saturating_sub(sig.bytes.len() - 1) {
+				let window = &buffer[start..start + sig.bytes.len()];
+				if matches_at(window, &masks) && !matches_at(window, &original) {
+					newly_matched.push((index, start));
+				}
+			}
+		}
+	}
+	Some(WideningSuggestion { masks, changed, newly_matched })
+}
+
+/// Find the representative of the cluster holding `index` in a union-find of `parents`, halving
+/// the path to it along the way.
+fn root(parents: &mut [usize], mut index: usize) -> usize {
+	while parents[index] != index {
+		parents[index] = parents[parents[index]];
+		index = parents[index];
+	}
+	index
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{cluster_signatures, estimate_cost, suggest_widening};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_estimate_cost() {
+		let cost = estimate_cost(&[0x55, 0x8b], None);
+		assert_eq!(cost.bytes_per_offset, 1.0 + 1.0 / 256.0);
+		assert_eq!(cost.match_probability, 1.0 / 65536.0);
+		assert!(!cost.common_first_byte);
+		let cost = estimate_cost(&[0x00, 0x50], Some(&[0x00, 0xf0]));
+		assert_eq!(cost.bytes_per_offset, 2.0);
+		assert_eq!(cost.match_probability, 1.0 / 16.0);
+		assert!(!cost.common_first_byte);
+		assert!(estimate_cost(&[0x00, 0x90], Some(&[0x00, 0xff])).common_first_byte);
+		let cost = estimate_cost(&[], None);
+		assert_eq!((cost.bytes_per_offset, cost.match_probability), (0.0, 1.0));
+	}
+
+	#[test]
+	fn test_cluster_signatures() {
+		let mut tree = SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b, 0xec, 0x01], None, 1);
+		tree.add_signature(vec![0x31, 0xc0, 0xc3], None, 2);
+		tree.add_signature(vec![0x55, 0x8b, 0xec, 0x02], None, 3);
+		tree.add_signature(vec![0x55, 0x8b, 0x00, 0x02], Some(vec![0xff, 0xff, 0x00, 0xff]), 4);
+		tree.add_signature(vec![0x55, 0x89, 0xe5, 0x01], None, 5);
+		// The fourth signature is only within reach of the first through the third.
+		let clusters = cluster_signatures(&tree, 1, 2);
+		assert_eq!(clusters.len(), 1);
+		assert_eq!(clusters[0].members.len(), 3);
+		assert_eq!(clusters[0].merged.bytes(), &[0x55, 0x8b, 0x00, 0x00]);
+		assert_eq!(clusters[0].added_wildcards, 2);
+		assert_eq!(cluster_signatures(&tree, 2, 1)[0].members.len(), 4);
+		assert!(cluster_signatures(&tree, 0, 0).is_empty());
+	}
+
+	#[test]
+	fn test_suggest_widening() {
+		let mut tree = SignatureDecisionTree::new();
+		let id = tree.add_signature_with_id(vec![0xe8, 0x10, 0x00, 0xc3], Some(vec![0xff, 0xff, 0x00, 0xff]), 1).unwrap();
+		let corpus: [&[u8]; 2] = [&[0xe8, 0x10, 0x42, 0xc3, 0xe8, 0x30, 0x00, 0xc3], &[0xe8, 0x20, 0x00]];
+		let suggestion = suggest_widening(&tree, id, &[0x90, 0xe8, 0x30, 0x99, 0xc3], 1, &corpus).unwrap();
+		assert_eq!(suggestion.masks, vec![0xff, 0xdf, 0x00, 0xff]);
+		assert_eq!(suggestion.changed, vec![1]);
+		assert_eq!(suggestion.newly_matched, vec![(0, 4)]);
+		assert!(suggest_widening(&tree, id, &[0xe8, 0x10, 0x00, 0xc3], 0, &corpus).unwrap().changed.is_empty());
+		assert_eq!(suggest_widening(&tree, id, &[0xe8, 0x10, 0x00], 0, &corpus), None);
+		assert_eq!(suggest_widening(&tree, id, &[0xe8, 0x10, 0x00, 0xc3], usize::MAX, &corpus), None);
+	}
+}
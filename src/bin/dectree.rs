@@ -0,0 +1,67 @@
+//! Command-line tools for working with signature files.
+//!
+//! ```text
+//! dectree convert <from> <to> <input> [output]
+//! ```
+//!
+//! `convert` translates a rule file between formats (`hex`, `guid`, `yara`, `clamav`, `flirt`,
+//! `peid` or `r2`), reading standard input when `input` is `-` and writing standard output unless
+//! `output` is given. Rules that can't be written in the target format are listed on standard error.
+
+use std::io::Read;
+use std::process::ExitCode;
+
+use dectree_rs::convert::convert;
+use dectree_rs::provenance::ImportFormat;
+
+const USAGE: &str = "usage: dectree convert <from> <to> <input> [output]\nformats: hex, guid, yara, clamav, flirt, peid, r2";
+
+fn main() -> ExitCode {
+	let args = std::env::args().skip(1).collect::<Vec<_>>();
+	let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+		["convert", from, to, input] => run_convert(from, to, input, None),
+		["convert", from, to, input, output] => run_convert(from, to, input, Some(output)),
+		_ => Err(USAGE.to_string())
+	};
+	match result {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(message) => {
+			eprintln!("{message}");
+			ExitCode::FAILURE
+		}
+	}
+}
+
+/// Convert the rule file at `input` from the format named `from` to the one named `to`.
+fn run_convert(from: &str, to: &str, input: &str, output: Option<&str>) -> Result<(), String> {
+	let from = from.parse::<ImportFormat>()?;
+	let to = to.parse::<ImportFormat>()?;
+	let source = if input == "-" {
+		let mut source = String::new();
+		std::io::stdin().read_to_string(&mut source).map_err(|error| format!("stdin: {error}"))?;
+		source
+	} else {
+		std::fs::read_to_string(input).map_err(|error| format!("{input}: {error}"))?
+	};
+	let conversion = convert(&source, from, to).map_err(|error| format!("{input}: {error}"))?;
+	for unconverted in &conversion.unconverted {
+		match unconverted.line {
+			Some(line) => eprintln!("{input}:{line}: can't be written as {to}: {}", unconverted.text),
+			None => eprintln!("{input}: can't be written as {to}: {}", unconverted.text)
+		}
+	}
+	for lossy in &conversion.lossy {
+		let discarded = lossy.discarded.join(", ");
+		match lossy.line {
+			Some(line) => eprintln!("{input}:{line}: written as {to} without {discarded}"),
+			None => eprintln!("{input}: written as {to} without {discarded}")
+		}
+	}
+	match output {
+		Some(output) => std::fs::write(output, conversion.output).map_err(|error| format!("{output}: {error}")),
+		None => {
+			print!("{}", conversion.output);
+			Ok(())
+		}
+	}
+}
@@ -0,0 +1,246 @@
+//! A mutable builder and an immutable, compiled tree.
+//!
+//! `SignatureTreeBuilder` collects signatures, and can have them inserted and removed freely, until
+//! `compile()` freezes it into a `CompiledTree`. Compiling builds every node of the tree once,
+//! top-down, as `SignatureDecisionTree::build_from()` does, and trims the spare capacity of the
+//! tree's storage. The compiled tree has the same layout as any other `SignatureDecisionTree`; what
+//! it adds is that it can't be changed. It dereferences to its `SignatureDecisionTree` for every
+//! query, but offers nothing that changes it, so it can be shared between threads (it is `Send` and
+//! `Sync` whenever its objects are) and persisted as it is.
+//! ```rust
+//! use dectree_rs::compiled::SignatureTreeBuilder;
+//!
+//! let mut builder = SignatureTreeBuilder::new();
+//! builder.insert(vec![0x55, 0x8b, 0xec], None, "frame");
+//! builder.insert(vec![0xcc], None, "breakpoint");
+//! builder.insert(vec![0xc3], None, "ret");
+//! assert_eq!(builder.remove(&[0xcc], None), Some("breakpoint"));
+//! let tree = builder.compile();
+//! assert_eq!(tree.scan([0x55, 0x8b, 0xec, 0xcc, 0xc3]), vec![(0, "frame"), (4, "ret")]);
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::ops::Deref;
+
+use crate::{pattern, DuplicatePolicy, OverlapPolicy, SignatureDecisionTree, WildcardTail};
+
+/// Represents a set of signatures being assembled into a tree.
+pub struct SignatureTreeBuilder<T, S = RandomState> where T: Clone {
+	/// An empty tree holding the settings and hasher the compiled tree is built with.
+	tree: SignatureDecisionTree<T, S>,
+	/// The signatures inserted so far, as their canonical bytes and masks, and their object.
+	signatures: Vec<(Vec<u8>, Option<Vec<u8>>, T)>
+}
+
+impl<T> SignatureTreeBuilder<T> where T: Clone {
+	/// Create a new, empty `SignatureTreeBuilder`, with the settings of a new tree.
+	pub fn new() -> Self {
+		SignatureTreeBuilder::with_hasher(RandomState::new())
+	}
+}
+
+impl<T> Default for SignatureTreeBuilder<T> where T: Clone {
+	fn default() -> Self {
+		SignatureTreeBuilder::new()
+	}
+}
+
+impl<T, S> SignatureTreeBuilder<T, S> where T: Clone, S: BuildHasher {
+	/// Create a new, empty `SignatureTreeBuilder` whose compiled tree uses `hash_builder` to hash
+	/// keys.
+	pub fn with_hasher(hash_builder: S) -> Self {
+		SignatureTreeBuilder {
+			tree: SignatureDecisionTree::with_hasher(hash_builder),
+			signatures: Vec::new()
+		}
+	}
+
+	/// Set the overlap policy of the compiled tree, as `SignatureDecisionTree::set_overlap_policy()`
+	/// does.
+	pub fn overlap_policy(mut self, overlap_policy: OverlapPolicy) -> Self {
+		self.tree.set_overlap_policy(overlap_policy);
+		self
+	}
+
+	/// Set how signatures inserted more than once are compiled, as
+	/// `SignatureDecisionTree::set_duplicate_policy()` does.
+	pub fn duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Self {
+		self.tree.set_duplicate_policy(duplicate_policy);
+		self
+	}
+
+	/// Set the fewest effective bytes a signature needs to be compiled, as
+	/// `SignatureDecisionTree::set_min_signature_length()` does.
+	pub fn min_signature_length(mut self, min_signature_length: usize) -> Self {
+		self.tree.set_min_signature_length(min_signature_length);
+		self
+	}
+
+	/// Set how trailing wildcards are compiled, as `SignatureDecisionTree::set_wildcard_tail()`
+	/// does. Signatures are canonicalized as they are inserted, so this should be set before any
+	/// are: signatures inserted under `WildcardTail::Trim` stay trimmed.
+	pub fn wildcard_tail(mut self, wildcard_tail: WildcardTail) -> Self {
+		self.tree.set_wildcard_tail(wildcard_tail);
+		self
+	}
+
+	/// Get the number of signatures inserted, including any that won't make it into the compiled
+	/// tree.
+	pub fn len(&self) -> usize {
+		self.signatures.len()
+	}
+
+	/// Check whether no signatures have been inserted.
+	pub fn is_empty(&self) -> bool {
+		self.signatures.is_empty()
+	}
+
+	/// Insert a signature, given as `SignatureDecisionTree::add_signature()` takes it. Signatures
+	/// that `add_signature()` would skip are kept until they are removed, and skipped when the tree
+	/// is compiled.
+	pub fn insert(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: T) {
+		// Masks of the wrong length are kept as they are, so the signature is still skipped.
+		let (bytes, masks) = match masks.as_ref().is_some_and(|masks| masks.len() != bytes.len()) {
+			true => (bytes, masks),
+			false => pattern::canonicalize(bytes, masks, self.tree.wildcard_tail())
+		};
+		self.signatures.push((bytes, masks, val));
+	}
+
+	/// Remove the signature inserted with `bytes` and `masks`, comparing patterns as duplicates
+	/// are compared, and return its object. Where the pattern was inserted several times, the
+	/// first one left is removed. Returns `None` if no such signature was inserted.
+	pub fn remove(&mut self, bytes: &[u8], masks: Option<&[u8]>) -> Option<T> {
+		let (bytes, masks) = pattern::canonicalize(bytes.to_vec(), masks.map(<[u8]>::to_vec), self.tree.wildcard_tail());
+		let position = self.signatures.iter().position(|signature| signature.0 == bytes && signature.1 == masks)?;
+		Some(self.signatures.remove(position).2)
+	}
+
+	/// Freeze the signatures into a `CompiledTree`. They get their ids in the order they were
+	/// inserted, leaving out the ones that were removed or skipped.
+	pub fn compile(self) -> CompiledTree<T, S> {
+		let mut tree = self.tree;
+		tree.signatures.reserve_exact(self.signatures.len());
+		for (bytes, masks, val) in self.signatures {
+			let _ = tree.store_signature(bytes, masks, val);
+		}
+		tree.build_nodes();
+		tree.nodes.shrink_to_fit();
+		tree.signatures.shrink_to_fit();
+		tree.sigs_dup.shrink_to_fit();
+		CompiledTree { tree }
+	}
+}
+
+/// Represents a tree that has been compiled and can only be queried.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent, bound(
+	serialize = "T: serde::Serialize",
+	deserialize = "T: serde::Deserialize<'de>, S: BuildHasher + Default"
+)))]
+pub struct CompiledTree<T, S = RandomState> where T: Clone {
+	tree: SignatureDecisionTree<T, S>
+}
+
+impl<T, S> CompiledTree<T, S> where T: Clone, S: BuildHasher {
+	/// Consume the compiled tree, returning it as a `SignatureDecisionTree` that can be changed
+	/// again.
+	pub fn into_tree(self) -> SignatureDecisionTree<T, S> {
+		self.tree
+	}
+
+	/// Find every signature hit in each of `buffers` like `scan_many()`, spreading the buffers over
+	/// up to `threads` threads. Each thread scans a contiguous run of the buffers against the same
+	/// tree, so nothing is copied or built per thread. The results are grouped per buffer, in the
+	/// same order as `buffers`, and are the same as `scan_many()`'s.
+	/// ```rust
+	/// use dectree_rs::compiled::SignatureTreeBuilder;
+	///
+	/// let mut builder = SignatureTreeBuilder::new();
+	/// builder.insert(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let tree = builder.compile();
+	/// let hits = tree.scan_many_parallel(&[&[0x90, 0x55, 0x8b, 0xec], &[0x90], &[0x55, 0x8b, 0xec]], 2);
+	/// assert_eq!(hits, vec![vec![(1, 1)], vec![], vec![(0, 1)]]);
+	/// ```
+	pub fn scan_many_parallel(&self, buffers: &[&[u8]], threads: usize) -> Vec<Vec<(usize, T)>>
+		where T: Send + Sync, S: Sync {
+		if threads <= 1 || buffers.len() <= 1 {
+			return self.tree.scan_many(buffers)
+		}
+		let run = buffers.len().div_ceil(threads);
+		std::thread::scope(|scope| {
+			let handles = buffers.chunks(run)
+				.map(|run| scope.spawn(move || self.tree.scan_many(run)))
+				.collect::<Vec<_>>();
+			handles.into_iter()
+				.flat_map(|handle| handle.join().expect("a scanning thread panicked"))
+				.collect()
+		})
+	}
+}
+
+impl<T, S> Deref for CompiledTree<T, S> where T: Clone {
+	type Target = SignatureDecisionTree<T, S>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.tree
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{CompiledTree, SignatureTreeBuilder};
+	use crate::{DuplicatePolicy, SignatureDecisionTree};
+
+	#[test]
+	fn test_compile() {
+		fn assert_send_sync<X>() where X: Send + Sync {}
+		assert_send_sync::<CompiledTree<String>>();
+		let mut builder = SignatureTreeBuilder::new().duplicate_policy(DuplicatePolicy::Replace);
+		let mut tree = SignatureDecisionTree::new();
+		tree.set_duplicate_policy(DuplicatePolicy::Replace);
+		let signatures = [
+			(vec![0x55, 0x8b, 0xec], None, 1),
+			(vec![0x55, 0x8b, 0x00, 0x83], Some(vec![0xff, 0xff, 0x00, 0xff]), 2),
+			(vec![0x55, 0x8b], Some(vec![0xff]), 3),
+			(vec![0x55, 0x8b, 0xec], Some(vec![0xff, 0xff, 0xff]), 4),
+			(vec![0x55, 0x89, 0xe5], None, 5),
+			(vec![0xc3], None, 6)
+		];
+		for (bytes, masks, val) in signatures {
+			builder.insert(bytes.clone(), masks.clone(), val);
+			tree.add_signature(bytes, masks, val);
+		}
+		assert_eq!(builder.len(), 6);
+		assert_eq!(builder.remove(&[0x55, 0x89, 0xe5], Some(&[0xff, 0xff, 0xff])), Some(5));
+		assert_eq!(builder.remove(&[0x55, 0x89, 0xe5], None), None);
+		builder.insert(vec![0x90, 0x12], Some(vec![0xff, 0x00]), 8);
+		assert_eq!(builder.remove(&[0x90, 0x34], Some(&[0xff, 0x00])), Some(8));
+		let compiled = builder.compile();
+		assert_eq!(compiled.len(), tree.len() - 1);
+		for input in [&[0x55, 0x8b, 0xec][..], &[0x55, 0x8b, 0x90, 0x83], &[0x55, 0x89, 0xe5], &[0xc3]] {
+			let expected = Some(input).filter(|input| input[..] != [0x55, 0x89, 0xe5]).and_then(|input| tree.get_signature(input, None));
+			assert_eq!(compiled.get_signature(input, None), expected);
+		}
+		assert_eq!(compiled.get_signature([0x55, 0x8b, 0xec], None), Some(4));
+		let mut tree = compiled.into_tree();
+		assert!(tree.add_signature(vec![0x90], None, 7));
+	}
+
+	#[test]
+	fn test_scan_many_parallel() {
+		let mut builder = SignatureTreeBuilder::new();
+		builder.insert(vec![0x55, 0x8b, 0xec], None, 1);
+		builder.insert(vec![0xc3], None, 2);
+		let tree = builder.compile();
+		let buffers = (0..37u8).map(|length| [0x55, 0x8b, 0xec, 0xc3].repeat(usize::from(length))).collect::<Vec<_>>();
+		let buffers = buffers.iter().map(Vec::as_slice).collect::<Vec<_>>();
+		let expected = tree.scan_many(&buffers);
+		for threads in [0, 1, 2, 4, 36, 37, 100] {
+			assert_eq!(tree.scan_many_parallel(&buffers, threads), expected);
+		}
+		assert_eq!(tree.scan_many_parallel(&[], 4), Vec::<Vec<(usize, i32)>>::new());
+	}
+}
@@ -0,0 +1,144 @@
+//! Computed constraints on signature bytes.
+//!
+//! Some formats guard their headers with a checksum: a byte that is the sum of the bytes before
+//! it, or a CRC over a range of the header. Such a value can't be written as a plain byte of the
+//! signature, so its position is left as a wildcard and a `Constraint` attached with
+//! `SignatureDecisionTree::set_constraints()` computes what it must hold. Constraints are evaluated
+//! at match time, once the signature's bytes have matched, and a signature whose constraints
+//! don't hold doesn't match at all. Positions are relative to the start of the signature, and
+//! constraints can only cover the signature's own bytes, so a scan never has to look past the end
+//! of a match to decide it.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::constraint::{Computation, Constraint};
+//!
+//! let mut tree = SignatureDecisionTree::new();
+//! // A magic byte, two payload bytes and a checksum byte holding their sum.
+//! let id = tree.add_signature_with_id(vec![0xa5, 0x00, 0x00, 0x00], Some(vec![0xff, 0x00, 0x00, 0x00]), "record").unwrap();
+//! tree.set_constraints(id, vec![Constraint::new(3, Computation::Sum(1..3))]);
+//! assert_eq!(tree.get_signature([0xa5, 0x10, 0x20, 0x30], None), Some("record"));
+//! assert_eq!(tree.get_signature([0xa5, 0x10, 0x20, 0x31], None), None);
+//! ```
+
+use std::ops::Range;
+
+use crate::ByteSource;
+
+/// Represents the values a constraint can compute from the bytes of a match.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Computation {
+	/// The wrapping sum of the bytes in the range, one byte wide.
+	Sum(Range<usize>),
+	/// The XOR of the bytes in the range, one byte wide.
+	Xor(Range<usize>),
+	/// The two's complement of the wrapping sum of the bytes in the range, one byte wide, so that
+	/// the range and the checksum sum to zero.
+	NegatedSum(Range<usize>),
+	/// The CRC-32 (as used by zlib and PNG) of the bytes in the range, four bytes wide and stored
+	/// in little endian byte order.
+	Crc32Le(Range<usize>),
+	/// The CRC-32 of the bytes in the range, stored in big endian byte order.
+	Crc32Be(Range<usize>)
+}
+
+impl Computation {
+	/// Get the number of bytes the computed value takes up.
+	pub fn width(&self) -> usize {
+		match self {
+			Computation::Sum(_) | Computation::Xor(_) | Computation::NegatedSum(_) => 1,
+			Computation::Crc32Le(_) | Computation::Crc32Be(_) => 4
+		}
+	}
+
+	/// Get the range of bytes the value is computed over.
+	pub fn range(&self) -> &Range<usize> {
+		match self {
+			Computation::Sum(range) | Computation::Xor(range) | Computation::NegatedSum(range)
+				| Computation::Crc32Le(range) | Computation::Crc32Be(range) => range
+		}
+	}
+
+	/// Compute the value over `bytes`, the bytes of its range, as it is stored.
+	/// ```rust
+	/// use dectree_rs::constraint::Computation;
+	///
+	/// assert_eq!(Computation::NegatedSum(0..2).compute(&[0x01, 0x02]), vec![0xfd]);
+	/// assert_eq!(Computation::Crc32Le(0..9).compute(b"123456789"), vec![0x26, 0x39, 0xf4, 0xcb]);
+	/// ```
+	pub fn compute(&self, bytes: &[u8]) -> Vec<u8> {
+		match self {
+			Computation::Sum(_) => vec![bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))],
+			Computation::Xor(_) => vec![bytes.iter().fold(0u8, |xor, &byte| xor ^ byte)],
+			Computation::NegatedSum(_) => vec![bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)).wrapping_neg()],
+			Computation::Crc32Le(_) => crc32(bytes).to_le_bytes().to_vec(),
+			Computation::Crc32Be(_) => crc32(bytes).to_be_bytes().to_vec()
+		}
+	}
+}
+
+/// Compute the CRC-32 of `bytes`, with the reflected polynomial `0xedb88320`.
+fn crc32(bytes: &[u8]) -> u32 {
+	!bytes.iter().fold(!0u32, |crc, &byte| {
+		(0..8).fold(crc ^ byte as u32, |crc, _| match crc & 1 {
+			1 => (crc >> 1) ^ 0xedb8_8320,
+			_ => crc >> 1
+		})
+	})
+}
+
+/// Represents a requirement that the bytes at `position` of a match hold a value computed from
+/// other bytes of the match.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Constraint {
+	/// The position of the first byte of the computed value, relative to the start of the
+	/// signature.
+	pub position: usize,
+	/// How the value is computed.
+	pub computation: Computation
+}
+
+impl Constraint {
+	/// Create a new `Constraint` requiring the value computed by `computation` at `position`.
+	pub fn new(position: usize, computation: Computation) -> Self {
+		Constraint { position, computation }
+	}
+
+	/// Get the number of bytes from the start of a match the constraint reads, both the bytes its
+	/// value is computed over and the value itself.
+	pub fn reach(&self) -> usize {
+		self.computation.range().end.max(self.position + self.computation.width())
+	}
+
+	/// Check whether the constraint holds for a match at `offset` of `bytes`.
+	pub(crate) fn holds<B>(&self, bytes: &B, offset: usize) -> bool where B: ByteSource + ?Sized {
+		let range = self.computation.range();
+		if offset + self.reach() > bytes.len() {
+			return false
+		}
+		let covered = range.clone().map(|position| bytes.byte_at(offset + position)).collect::<Vec<_>>();
+		self.computation.compute(&covered)
+			.into_iter()
+			.enumerate()
+			.all(|(index, byte)| bytes.byte_at(offset + self.position + index) == byte)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Computation, Constraint};
+
+	#[test]
+	fn test_constraint() {
+		let header = [0x01, 0x02, 0x03, 0x00, 0xfa];
+		assert!(Constraint::new(3, Computation::Xor(0..3)).holds(&header[..], 0));
+		assert!(Constraint::new(4, Computation::NegatedSum(0..4)).holds(&header[..], 0));
+		assert!(!Constraint::new(4, Computation::Sum(0..4)).holds(&header[..], 0));
+		assert!(!Constraint::new(3, Computation::Crc32Be(0..3)).holds(&header[..], 0));
+		let crc = [b"abc".to_vec(), 0x3524_41c2u32.to_be_bytes().to_vec()].concat();
+		assert!(Constraint::new(3, Computation::Crc32Be(0..3)).holds(&crc[..], 0));
+		assert!(!Constraint::new(3, Computation::Crc32Be(0..3)).holds(&crc[1..], 0));
+	}
+}
@@ -0,0 +1,101 @@
+//! Per-scan context handed to conditions and callbacks.
+//!
+//! A `ScanContext` describes the input being scanned (the file it came from, its detected kind and
+//! the address it is loaded at) and carries any data of the caller's own, so per-target logic in
+//! conditions and hit callbacks doesn't have to be smuggled through global state. Scans with a
+//! context, such as `SignatureDecisionTree::scan_with_context()`, skip the signatures whose
+//! target conflicts with the detected kind, and `ScanContext::address()` maps the offsets of their
+//! matches to addresses.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::context::ScanContext;
+//!
+//! let mut tree = SignatureDecisionTree::new();
+//! tree.add_signature(vec![0x55, 0x8b, 0xec], None, "frame");
+//! // Only report hits inside the range the caller cares about.
+//! let context = ScanContext::new(0x1000..0x1004).file_name("sample.bin").base_address(0x1000);
+//! let buffer = [0x55, 0x8b, 0xec, 0x90, 0x90, 0x55, 0x8b, 0xec];
+//! let hits = tree.scan_with_context(buffer, &context, |context, found| context.user_data.contains(&context.address(found.offset)));
+//! assert_eq!(hits.len(), 1);
+//! assert_eq!(context.address(hits[0].offset), 0x1000);
+//! ```
+
+use std::path::PathBuf;
+
+use crate::target::Target;
+
+/// Represents what is known about an input while it is scanned, along with data of the caller's.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanContext<U = ()> {
+	/// The file the input was read from, if any.
+	pub file_name: Option<PathBuf>,
+	/// The detected kind of the input. Signatures targeted at a conflicting kind are skipped.
+	pub target: Target,
+	/// The address the first byte of the input is loaded at.
+	pub base_address: u64,
+	/// Data of the caller's own, such as the results of earlier analysis.
+	pub user_data: U
+}
+
+impl<U> ScanContext<U> {
+	/// Create a new `ScanContext` carrying `user_data`, for input of unknown kind loaded at address
+	/// zero.
+	pub fn new(user_data: U) -> Self {
+		ScanContext {
+			file_name: None,
+			target: Target::default(),
+			base_address: 0,
+			user_data
+		}
+	}
+
+	/// Set the file the input was read from.
+	pub fn file_name(mut self, file_name: impl Into<PathBuf>) -> Self {
+		self.file_name = Some(file_name.into());
+		self
+	}
+
+	/// Set the detected kind of the input.
+	pub fn target(mut self, target: Target) -> Self {
+		self.target = target;
+		self
+	}
+
+	/// Set the address the first byte of the input is loaded at.
+	pub fn base_address(mut self, base_address: u64) -> Self {
+		self.base_address = base_address;
+		self
+	}
+
+	/// Get the address of the input byte at `offset`.
+	pub fn address(&self, offset: usize) -> u64 {
+		self.base_address.wrapping_add(offset as u64)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ScanContext;
+	use crate::target::{Arch, Target};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_scan_with_context() {
+		let mut tree = SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b], None, 1);
+		let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 2).unwrap();
+		tree.set_target(id, Target::any().arch(Arch::X86));
+		let buffer = [0x55, 0x8b, 0xec, 0x55, 0x8b, 0xec];
+		let context = ScanContext::new(()).base_address(u64::MAX).target(Target::any().arch(Arch::Arm));
+		assert_eq!(context.address(1), 0);
+		let objects = tree.scan_with_context(buffer, &context, |_, _| true).into_iter().map(|found| found.object).collect::<Vec<_>>();
+		assert_eq!(objects, vec![1, 1]);
+		// A failed condition falls back to the next preferred signature.
+		let context = ScanContext::new(vec![2]);
+		let objects = tree.scan_with_context(buffer, &context, |context, found| !context.user_data.contains(&found.object) || found.offset > 0);
+		assert_eq!(objects.into_iter().map(|found| found.object).collect::<Vec<_>>(), vec![1, 2]);
+		let mut addresses = Vec::new();
+		tree.for_each_hit_with_context(buffer, &context.base_address(0x400), |_, _| true, |context, found| addresses.push(context.address(found.offset)));
+		assert_eq!(addresses, vec![0x400, 0x403]);
+	}
+}
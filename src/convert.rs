@@ -0,0 +1,350 @@
+//! Conversion of rule files between the supported formats.
+//!
+//! `convert()` imports a rule file into a tree and exports it again with `formats::export()`, so a
+//! rule converted to its own format comes back unchanged. Plain hex-string and GUID files hold one
+//! signature per line (blank lines and lines starting with `#` are skipped), and since they carry
+//! no names, their signatures are named after the line they are on. Rules the target format can't
+//! express are left out of the output and reported. Rules converted without some of their
+//! constructs, such as a ClamAV offset, a PEiD `ep_only` flag or a YARA condition, are reported as
+//! lossy, since the converted rule can match where the source rule wouldn't.
+//! ```rust
+//! use dectree_rs::convert::convert;
+//! use dectree_rs::provenance::ImportFormat;
+//!
+//! let rules = "rule Frame { strings: $a = { 55 8B EC } $b = { 55 8? EC } condition: any of them }";
+//! let conversion = convert(rules, ImportFormat::Yara, ImportFormat::Peid).unwrap();
+//! assert_eq!(conversion.output, "[Frame]\nsignature = 55 8B EC\nep_only = false\n");
+//! assert_eq!(conversion.unconverted[0].text, "$b = { 55 8? EC }");
+//! assert!(conversion.lossy.is_empty());
+//! ```
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+
+use crate::formats::clamav::{self, ClamAvError};
+use crate::formats::export_representable;
+use crate::formats::flirt::{self, FlirtError};
+use crate::formats::peid::{self, PeidError};
+use crate::formats::yara::{self, YaraError};
+use crate::formats::zignature::{self, ZignatureError};
+use crate::parser::{self, ParseError};
+use crate::provenance::{ImportFormat, Provenance};
+use crate::SignatureDecisionTree;
+
+/// Represents the ways a rule file can fail to convert.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConvertError {
+	/// A line of a hex-string file that couldn't be parsed.
+	HexString {
+		line: usize,
+		error: ParseError
+	},
+	/// A line of a GUID file that couldn't be parsed.
+	Guid {
+		line: usize,
+		error: ParseError
+	},
+	/// The YARA rules couldn't be parsed.
+	Yara(YaraError),
+	/// The ClamAV database couldn't be parsed.
+	ClamAv(ClamAvError),
+	/// The FLIRT pattern file couldn't be parsed.
+	Flirt(FlirtError),
+	/// The PEiD database couldn't be parsed.
+	Peid(PeidError),
+	/// The radare2 zignatures couldn't be parsed.
+	Zignature(ZignatureError)
+}
+
+impl Display for ConvertError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ConvertError::HexString { line, error } | ConvertError::Guid { line, error } => write!(f, "line {line}: {error}"),
+			ConvertError::Yara(error) => write!(f, "{error}"),
+			ConvertError::ClamAv(error) => write!(f, "{error}"),
+			ConvertError::Flirt(error) => write!(f, "{error}"),
+			ConvertError::Peid(error) => write!(f, "{error}"),
+			ConvertError::Zignature(error) => write!(f, "{error}")
+		}
+	}
+}
+
+impl std::error::Error for ConvertError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ConvertError::HexString { error, .. } | ConvertError::Guid { error, .. } => Some(error),
+			ConvertError::Yara(error) => Some(error),
+			ConvertError::ClamAv(error) => Some(error),
+			ConvertError::Flirt(error) => Some(error),
+			ConvertError::Peid(error) => Some(error),
+			ConvertError::Zignature(error) => Some(error)
+		}
+	}
+}
+
+impl From<YaraError> for ConvertError {
+	fn from(error: YaraError) -> Self {
+		ConvertError::Yara(error)
+	}
+}
+
+impl From<ClamAvError> for ConvertError {
+	fn from(error: ClamAvError) -> Self {
+		ConvertError::ClamAv(error)
+	}
+}
+
+impl From<FlirtError> for ConvertError {
+	fn from(error: FlirtError) -> Self {
+		ConvertError::Flirt(error)
+	}
+}
+
+impl From<PeidError> for ConvertError {
+	fn from(error: PeidError) -> Self {
+		ConvertError::Peid(error)
+	}
+}
+
+impl From<ZignatureError> for ConvertError {
+	fn from(error: ZignatureError) -> Self {
+		ConvertError::Zignature(error)
+	}
+}
+
+/// Represents a source rule that couldn't be written in the target format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unconverted {
+	/// The line the rule is on in the source.
+	pub line: Option<usize>,
+	/// The definition that couldn't be converted, as written in the source.
+	pub text: String
+}
+
+/// Represents a source rule that was converted without some of its constructs, which the target
+/// format can't express.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lossy {
+	/// The line the rule is on in the source.
+	pub line: Option<usize>,
+	/// The rule as written in the source.
+	pub text: String,
+	/// The constructs left out, such as `offset EP+0` or `condition: $a and $b`.
+	pub discarded: Vec<String>
+}
+
+/// Represents the outcome of a conversion.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conversion {
+	/// The converted rules.
+	pub output: String,
+	/// The source rules left out of `output`, in source order.
+	pub unconverted: Vec<Unconverted>,
+	/// The source rules in `output` that lost some of their constructs, in source order.
+	pub lossy: Vec<Lossy>
+}
+
+/// Convert the rules in `source`, written in `from`, to `to`. A ClamAV source is read as an `.ndb`
+/// database. Nothing is converted unless the whole source parses.
+pub fn convert(source: &str, from: ImportFormat, to: ImportFormat) -> Result<Conversion, ConvertError> {
+	let mut tree = SignatureDecisionTree::new();
+	let mut conversion = match from {
+		ImportFormat::HexString | ImportFormat::Guid => {
+			load_lines(&mut tree, source, from)?;
+			export_tree(&tree, to)
+		},
+		ImportFormat::Yara => {
+			yara::load_rules(&mut tree, source, None)?;
+			export_tree(&tree, to)
+		},
+		ImportFormat::ClamAv => {
+			clamav::load_ndb(&mut tree, source, None)?;
+			export_tree(&tree, to)
+		},
+		ImportFormat::Flirt => {
+			flirt::load_pat(&mut tree, source, None)?;
+			export_tree(&tree, to)
+		},
+		ImportFormat::Zignature => {
+			zignature::load_zignatures(&mut tree, source, None)?;
+			export_tree(&tree, to)
+		},
+		ImportFormat::Peid => {
+			// PEiD entries are loaded with their ep_only flag, into a tree of their own.
+			let mut tree = SignatureDecisionTree::new();
+			peid::load_userdb(&mut tree, source, None)?;
+			export_tree(&tree, to)
+		}
+	};
+	// A rule converted to its own format is written back as it is, and loses nothing.
+	if from != to {
+		report_lossy(&mut conversion, source, from)?;
+	}
+	Ok(conversion)
+}
+
+/// Export the rules loaded into `tree` to `to`, and report the ones left out.
+fn export_tree<T, S>(tree: &SignatureDecisionTree<T, S>, to: ImportFormat) -> Conversion
+	where T: Clone + Display, S: BuildHasher {
+	let (output, skipped) = export_representable(tree, to);
+	let mut unconverted: Vec<Unconverted> = Vec::new();
+	for provenance in skipped.into_iter().filter_map(|id| tree.provenance(id)) {
+		// Every signature a rule expands into is skipped, but the rule is only reported once.
+		let repeated = unconverted.last().is_some_and(|last| last.line == provenance.line && last.text == provenance.text);
+		if !repeated {
+			unconverted.push(Unconverted { line: provenance.line, text: provenance.text.clone() });
+		}
+	}
+	Conversion { output, unconverted, lossy: Vec::new() }
+}
+
+/// Report the rules of `source`, written in `from`, that were converted without some of their
+/// constructs. Rules left out of the output entirely are only reported as unconverted, and YARA
+/// rules without hex strings and zignatures without bytes, which are never loaded, are reported as
+/// unconverted too.
+fn report_lossy(conversion: &mut Conversion, source: &str, from: ImportFormat) -> Result<(), ConvertError> {
+	let unconverted = conversion.unconverted.iter().filter_map(|rule| rule.line).collect::<HashSet<_>>();
+	match from {
+		ImportFormat::ClamAv => {
+			for signature in clamav::parse_ndb(source)?.into_iter().filter(|signature| !unconverted.contains(&signature.line)) {
+				let mut discarded = Vec::new();
+				if signature.target != "0" {
+					discarded.push(format!("target type {}", signature.target));
+				}
+				if signature.offset != "*" {
+					discarded.push(format!("offset {}", signature.offset));
+				}
+				conversion.lossy.push(Lossy { line: Some(signature.line), text: signature.text, discarded });
+			}
+		},
+		ImportFormat::Peid => {
+			for signature in peid::parse_userdb(source)?.into_iter().filter(|signature| !unconverted.contains(&signature.line)) {
+				let discarded = match signature.packer.ep_only {
+					true => vec!["ep_only = true".to_string()],
+					false => Vec::new()
+				};
+				conversion.lossy.push(Lossy { line: Some(signature.line), text: signature.text, discarded });
+			}
+		},
+		ImportFormat::Yara => {
+			for rule in yara::parse_rules(source)? {
+				if rule.hex_strings.is_empty() {
+					conversion.unconverted.push(Unconverted { line: Some(rule.line), text: rule.text });
+					continue
+				}
+				if rule.hex_strings.iter().all(|string| unconverted.contains(&string.line)) {
+					continue
+				}
+				// Every hex string becomes a rule of its own, so only a condition that any of them
+				// matching satisfies is kept.
+				let condition = rule.condition.split_whitespace().collect::<Vec<_>>().join(" ");
+				let single = match &rule.hex_strings[..] {
+					[string] => ["all of them", "1 of them", string.identifier.as_str()].contains(&condition.as_str()),
+					_ => false
+				};
+				let mut discarded = rule.other_strings;
+				if !single && condition != "any of them" {
+					discarded.push(format!("condition: {condition}"));
+				}
+				conversion.lossy.push(Lossy { line: Some(rule.line), text: rule.text, discarded });
+			}
+			conversion.unconverted.sort_by_key(|rule| rule.line);
+		},
+		ImportFormat::Zignature => {
+			for zignature in zignature::parse_zignatures(source)? {
+				match zignature.pattern {
+					// Zignatures without bytes, such as graph-only ones, are never loaded.
+					None => conversion.unconverted.push(Unconverted { line: Some(zignature.line), text: zignature.text }),
+					Some(_) if unconverted.contains(&zignature.line) => {},
+					Some(_) => conversion.lossy.push(Lossy { line: Some(zignature.line), text: zignature.text, discarded: zignature.other_records })
+				}
+			}
+			conversion.unconverted.sort_by_key(|rule| rule.line);
+		},
+		_ => {}
+	}
+	conversion.lossy.retain(|rule| !rule.discarded.is_empty());
+	Ok(())
+}
+
+/// Add the signature on each line of a hex-string or GUID file to `tree`, named after its line.
+fn load_lines(tree: &mut SignatureDecisionTree<String>, source: &str, format: ImportFormat) -> Result<(), ConvertError> {
+	let lines = source.lines()
+		.enumerate()
+		.map(|(index, text)| (index + 1, text.trim()))
+		.filter(|(_, text)| !text.is_empty() && !text.starts_with('#'));
+	for (line, text) in lines {
+		let (bytes, masks) = match format {
+			ImportFormat::Guid => parser::parse_guid(text)
+				.map(|bytes| (bytes.to_vec(), None))
+				.map_err(|error| ConvertError::Guid { line, error })?,
+			_ => parser::parse_hex_pattern(text)
+				.map(|(bytes, masks)| (bytes, Some(masks)))
+				.map_err(|error| ConvertError::HexString { line, error })?
+		};
+		if let Ok(id) = tree.try_add_signature(bytes, masks, format!("signature_{line}")) {
+			tree.set_provenance(id, Provenance::new(format, text).line(line));
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{convert, ConvertError, Lossy, Unconverted};
+	use crate::formats::yara::YaraError;
+	use crate::parser::ParseError;
+	use crate::provenance::ImportFormat;
+
+	#[test]
+	fn test_convert() {
+		let hex = "# prologues\n55 8B EC\n\n55 8B ?? 83\n";
+		let conversion = convert(hex, ImportFormat::HexString, ImportFormat::ClamAv).unwrap();
+		assert_eq!(conversion.output, "signature_2:0:*:558bec\nsignature_4:0:*:558b??83\n");
+		assert!(conversion.unconverted.is_empty());
+		assert_eq!(convert(hex, ImportFormat::HexString, ImportFormat::HexString).unwrap().output, "55 8B EC\n55 8B ?? 83\n");
+		let conversion = convert("A:0:*:(3?|4?)c3\nB:0:*:c3", ImportFormat::ClamAv, ImportFormat::Peid).unwrap();
+		assert_eq!(conversion.output, "[B]\nsignature = C3\nep_only = false\n");
+		assert_eq!(conversion.unconverted, vec![Unconverted { line: Some(1), text: "A:0:*:(3?|4?)c3".to_string() }]);
+		let conversion = convert("{6b29fc40-ca47-1067-b31d-00dd010662da}", ImportFormat::Guid, ImportFormat::Yara).unwrap();
+		assert!(conversion.output.contains("$s0 = { 40 FC 29 6B 47 CA 67 10 B3 1D 00 DD 01 06 62 DA }"));
+		assert_eq!(convert("55 8G", ImportFormat::HexString, ImportFormat::Yara), Err(ConvertError::HexString { line: 1, error: ParseError::InvalidCharacter { position: 4, character: 'G' } }));
+		let conversion = convert("[Stub]\nsignature = EB ?? 90\nep_only = true\n", ImportFormat::Peid, ImportFormat::ClamAv).unwrap();
+		assert_eq!(conversion.output, "Stub:0:*:eb??90\n");
+		assert_eq!(convert("rule", ImportFormat::Yara, ImportFormat::Guid), Err(ConvertError::Yara(YaraError::UnexpectedEnd { line: 1 })));
+	}
+
+	#[test]
+	fn test_convert_lossy() {
+		let lossy = |line, text: &str, discarded: &[&str]| Lossy { line: Some(line), text: text.to_string(), discarded: discarded.iter().map(|construct| construct.to_string()).collect() };
+		let conversion = convert("X:1:EP+0:558bec\nY:0:*:c3\n", ImportFormat::ClamAv, ImportFormat::Yara).unwrap();
+		assert!(conversion.output.contains("rule X {"));
+		assert_eq!(conversion.lossy, vec![lossy(1, "X:1:EP+0:558bec", &["target type 1", "offset EP+0"])]);
+		assert!(convert("X:1:EP+0:558bec", ImportFormat::ClamAv, ImportFormat::ClamAv).unwrap().lossy.is_empty());
+		let entry = "[P]\nsignature = 60 E8\nep_only = true";
+		let conversion = convert(entry, ImportFormat::Peid, ImportFormat::ClamAv).unwrap();
+		assert_eq!(conversion.output, "P:0:*:60e8\n");
+		assert_eq!(conversion.lossy, vec![lossy(1, entry, &["ep_only = true"])]);
+		let rules = "rule R { strings: $a = \"text\" $b = { C3 } condition: $a and $b and filesize < 100 }\nrule S { strings: $a = { C2 } condition: $a }\nrule T { strings: $a = \"text\" condition: $a }";
+		let conversion = convert(rules, ImportFormat::Yara, ImportFormat::ClamAv).unwrap();
+		assert_eq!(conversion.output, "R:0:*:c3\nS:0:*:c2\n");
+		let rule = rules.lines().next().unwrap();
+		assert_eq!(conversion.lossy, vec![lossy(1, rule, &["$a = \"text\"", "condition: $a and $b and filesize < 100"])]);
+		assert_eq!(conversion.unconverted, vec![Unconverted { line: Some(3), text: rules.lines().nth(2).unwrap().to_string() }]);
+	}
+
+	#[test]
+	fn test_convert_zignatures() {
+		let zignatures = "zs *\nza sym.main b 5589e5..c3:ffffff00ff\nza sym.main o 0x00001139\nza sym.f g cc=1 nbbs=1\n";
+		let conversion = convert(zignatures, ImportFormat::Zignature, ImportFormat::HexString).unwrap();
+		assert_eq!(conversion.output, "55 89 E5 ?? C3\n");
+		assert_eq!(conversion.lossy, vec![Lossy { line: Some(2), text: "za sym.main b 5589e5..c3:ffffff00ff\nza sym.main o 0x00001139".to_string(), discarded: vec!["o 0x00001139".to_string()] }]);
+		assert_eq!(conversion.unconverted, vec![Unconverted { line: Some(4), text: "za sym.f g cc=1 nbbs=1".to_string() }]);
+		let conversion = convert("[Armadillo 1.71 : Silicon Realms]\nsignature = 55 8B ?? 6A\n", ImportFormat::Peid, ImportFormat::Zignature).unwrap();
+		assert_eq!(conversion.output, "za Armadillo_1.71_:_Silicon_Realms b 558b006a:ffff00ff\n");
+		let back = convert(&conversion.output, ImportFormat::Zignature, ImportFormat::Peid).unwrap();
+		assert_eq!(back.output, "[Armadillo_1.71_:_Silicon_Realms]\nsignature = 55 8B ?? 6A\nep_only = false\n");
+		assert!(back.lossy.is_empty() && back.unconverted.is_empty());
+	}
+}
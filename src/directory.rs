@@ -0,0 +1,186 @@
+//! Scanning of directory trees.
+//!
+//! `DirectoryScanner` walks a directory and streams every regular file under it through a
+//! `StreamScanner`. Its report has an entry for every path it came across, in path order: the hits
+//! of each file it scanned, and the reason for each path it didn't, so the report is a complete
+//! account of what was and wasn't inspected. Paths can be excluded outright, files can be capped in
+//! size, and files or directories that can't be read are reported rather than failing the scan.
+//! Symbolic links aren't followed.
+//! ```rust,no_run
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::directory::{DirectoryScanner, Outcome};
+//!
+//! let mut tree = SignatureDecisionTree::new();
+//! tree.add_signature(b"MZ".to_vec(), None, "PE");
+//! let report = DirectoryScanner::new(&tree)
+//!     .exclude("/srv/samples/quarantine")
+//!     .max_file_size(64 * 1024 * 1024)
+//!     .scan("/srv/samples");
+//! for file in report {
+//!     match file.outcome {
+//!         Outcome::Scanned(hits) => println!("{}: {} hits", file.path.display(), hits.len()),
+//!         Outcome::Skipped(reason) => println!("{}: skipped: {reason}", file.path.display())
+//!     }
+//! }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::stream::StreamScanner;
+use crate::SignatureDecisionTree;
+
+/// Represents the reasons a path can be left out of a directory scan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SkipReason {
+	/// The path is, or is under, one of the scanner's excluded paths.
+	Excluded,
+	/// The file or directory couldn't be read.
+	Unreadable(ErrorKind),
+	/// The file is larger than the scanner's `max_file_size()`.
+	TooLarge {
+		size: u64,
+		limit: u64
+	},
+	/// The path is a symbolic link or a special file, such as a device or a socket.
+	NotRegular
+}
+
+impl Display for SkipReason {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SkipReason::Excluded => write!(f, "excluded"),
+			SkipReason::Unreadable(kind) => write!(f, "unreadable ({kind})"),
+			SkipReason::TooLarge { size, limit } => write!(f, "too large ({size} bytes, over the limit of {limit})"),
+			SkipReason::NotRegular => write!(f, "not a regular file")
+		}
+	}
+}
+
+/// Represents what became of a path in a directory scan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome<T> {
+	/// The file was scanned, with these hits, as `StreamScanner::scan()` reports them.
+	Scanned(Vec<(u64, T)>),
+	/// The path was left out of the scan.
+	Skipped(SkipReason)
+}
+
+/// Represents the entry of a directory scan's report for one path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileReport<T> {
+	/// The path, under the directory the scan started from.
+	pub path: PathBuf,
+	/// What became of the path.
+	pub outcome: Outcome<T>
+}
+
+/// Represents a scan of every file under a directory against a tree.
+pub struct DirectoryScanner<'a, T, S = RandomState> where T: Clone {
+	tree: &'a SignatureDecisionTree<T, S>,
+	excluded: Vec<PathBuf>,
+	max_file_size: Option<u64>
+}
+
+impl<'a, T, S> DirectoryScanner<'a, T, S> where T: Clone, S: BuildHasher {
+	/// Create a new `DirectoryScanner` that scans files against `tree`, with nothing excluded and
+	/// no size limit.
+	pub fn new(tree: &'a SignatureDecisionTree<T, S>) -> Self {
+		DirectoryScanner {
+			tree,
+			excluded: Vec::new(),
+			max_file_size: None
+		}
+	}
+
+	/// Exclude `path` from the scan, along with everything under it if it is a directory. Paths
+	/// are compared as they are walked, so they should start with the directory passed to `scan()`.
+	pub fn exclude(mut self, path: impl Into<PathBuf>) -> Self {
+		self.excluded.push(path.into());
+		self
+	}
+
+	/// Skip files larger than `max_file_size` bytes.
+	pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+		self.max_file_size = Some(max_file_size);
+		self
+	}
+
+	/// Scan every file under `root`, returning an entry for each path scanned or skipped, in path
+	/// order. Skipped directories get one entry, and nothing under them is listed.
+	pub fn scan(&self, root: impl AsRef<Path>) -> Vec<FileReport<T>> {
+		let mut report = Vec::new();
+		self.scan_path(root.as_ref().to_path_buf(), &mut report);
+		report
+	}
+
+	/// Scan the file or directory at `path`, adding what became of it to `report`.
+	fn scan_path(&self, path: PathBuf, report: &mut Vec<FileReport<T>>) {
+		let outcome = if self.excluded.iter().any(|excluded| path.starts_with(excluded)) {
+			Outcome::Skipped(SkipReason::Excluded)
+		} else {
+			match std::fs::symlink_metadata(&path) {
+				Err(error) => Outcome::Skipped(SkipReason::Unreadable(error.kind())),
+				Ok(metadata) if metadata.is_dir() => match std::fs::read_dir(&path).and_then(|entries| entries.collect::<Result<Vec<_>, _>>()) {
+					Ok(entries) => {
+						let mut paths = entries.into_iter().map(|entry| entry.path()).collect::<Vec<_>>();
+						paths.sort();
+						for path in paths {
+							self.scan_path(path, report);
+						}
+						return
+					},
+					Err(error) => Outcome::Skipped(SkipReason::Unreadable(error.kind()))
+				},
+				Ok(metadata) if !metadata.is_file() => Outcome::Skipped(SkipReason::NotRegular),
+				Ok(metadata) => match self.max_file_size.filter(|&limit| metadata.len() > limit) {
+					Some(limit) => Outcome::Skipped(SkipReason::TooLarge { size: metadata.len(), limit }),
+					None => match File::open(&path).and_then(|file| StreamScanner::new(self.tree, file).scan()) {
+						Ok(hits) => Outcome::Scanned(hits),
+						Err(error) => Outcome::Skipped(SkipReason::Unreadable(error.kind()))
+					}
+				}
+			}
+		};
+		report.push(FileReport { path, outcome });
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::ErrorKind;
+
+	use super::{DirectoryScanner, FileReport, Outcome, SkipReason};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_directory_scanner() {
+		let root = std::env::temp_dir().join(format!("dectree-directory-{}", std::process::id()));
+		std::fs::create_dir_all(root.join("nested/excluded")).unwrap();
+		std::fs::write(root.join("a.bin"), [0x90, 0x55, 0x8b, 0xec]).unwrap();
+		std::fs::write(root.join("b.bin"), [0x55, 0x8b, 0xec, 0x90, 0x90, 0x90]).unwrap();
+		std::fs::write(root.join("nested/c.bin"), [0xc3]).unwrap();
+		std::fs::write(root.join("nested/excluded/d.bin"), [0x55, 0x8b, 0xec]).unwrap();
+		let mut tree = SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+		let report = DirectoryScanner::new(&tree)
+			.exclude(root.join("nested/excluded"))
+			.max_file_size(5)
+			.scan(&root);
+		let missing = DirectoryScanner::new(&tree).scan(root.join("missing"));
+		std::fs::remove_dir_all(&root).unwrap();
+		assert_eq!(report, vec![
+			FileReport { path: root.join("a.bin"), outcome: Outcome::Scanned(vec![(1, 1)]) },
+			FileReport { path: root.join("b.bin"), outcome: Outcome::Skipped(SkipReason::TooLarge { size: 6, limit: 5 }) },
+			FileReport { path: root.join("nested/c.bin"), outcome: Outcome::Scanned(vec![]) },
+			FileReport { path: root.join("nested/excluded"), outcome: Outcome::Skipped(SkipReason::Excluded) }
+		]);
+		assert_eq!(missing[0].outcome, Outcome::Skipped(SkipReason::Unreadable(ErrorKind::NotFound)));
+		assert_eq!(SkipReason::TooLarge { size: 6, limit: 5 }.to_string(), "too large (6 bytes, over the limit of 5)");
+	}
+}
@@ -0,0 +1,212 @@
+//! Merging signature feeds with unique rule names.
+//!
+//! Feeds are imported independently, so two of them may well name different rules the same. A
+//! `FeedMerger` combines trees loaded from several feeds into one, keeping rule names unique and
+//! deterministic: the first feed to use a name keeps it, a later feed using it for its own rule
+//! gets it prefixed with the feed name (`feed::name`), and if even that is taken a numbered
+//! suffix is added (`feed::name~2`). Every signature of a rule gets the same name, and every
+//! rename, along with every signature dropped as a duplicate of one already merged, is reported
+//! rather than lost silently.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::feeds::FeedMerger;
+//!
+//! let mut first = SignatureDecisionTree::new();
+//! first.add_signature(vec![0x55, 0x8b, 0xec], None, "Prologue".to_string());
+//! let mut second = SignatureDecisionTree::new();
+//! second.add_signature(vec![0x55, 0x89, 0xe5], None, "Prologue".to_string());
+//! let mut merger = FeedMerger::new();
+//! merger.add_feed("vendor", &first);
+//! merger.add_feed("community", &second);
+//! let report = merger.renamed().to_vec();
+//! let tree = merger.into_tree();
+//! assert_eq!(tree.get_signature([0x55, 0x89, 0xe5], None), Some("community::Prologue".to_string()));
+//! assert_eq!(report[0].assigned, "community::Prologue");
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+
+use crate::{DectreeError, SignatureDecisionTree, SignatureId};
+
+/// Represents a rule that was given a new name to keep it apart from a rule of another feed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Renamed {
+	/// The feed the rule came from.
+	pub feed: String,
+	/// The name the feed gave the rule.
+	pub original: String,
+	/// The name the rule was given in the merged tree.
+	pub assigned: String
+}
+
+/// Represents a signature that was left out of the merged tree because an identical one had
+/// already been merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Duplicate {
+	/// The feed the signature came from.
+	pub feed: String,
+	/// The name of the rule the signature belongs to, as given by the feed.
+	pub name: String
+}
+
+/// Represents the merging of several feeds into one tree.
+#[derive(Clone, Debug, Default)]
+pub struct FeedMerger {
+	tree: SignatureDecisionTree<String>,
+	/// The name assigned to each rule, by feed and original name.
+	assigned: HashMap<(String, String), String>,
+	/// Every name assigned so far.
+	taken: HashSet<String>,
+	renamed: Vec<Renamed>,
+	duplicates: Vec<Duplicate>
+}
+
+impl FeedMerger {
+	/// Create a new `FeedMerger` with no feeds.
+	pub fn new() -> Self {
+		FeedMerger::default()
+	}
+
+	/// Merge every signature of `source`, loaded from the feed named `feed`, disabled ones
+	/// included. Signatures keep their provenance, with the feed recorded in it, their target,
+	/// their constraints and whether they are staged. Returns the ids the signatures were given in the merged tree, in the
+	/// order they were added to `source`, with `None` for the ones dropped.
+	pub fn add_feed<S>(&mut self, feed: &str, source: &SignatureDecisionTree<String, S>) -> Vec<Option<SignatureId>>
+		where S: BuildHasher {
+		let mut ids = Vec::with_capacity(source.len());
+		for sig in &source.signatures {
+			let (name, assigned) = self.name_for(feed, &sig.object);
+			let masks = sig.masks.to_vec(sig.bytes.len());
+			// Staged signatures are added with staging on, so they stay staged and are kept apart from
+			// primary signatures with the same pattern.
+			self.tree.set_staging(sig.staged);
+			let added = self.tree.try_add_signature(sig.bytes.clone(), Some(masks), name.clone());
+			self.tree.set_staging(false);
+			let id = match added {
+				Ok(id) => id,
+				Err(error) => {
+					if error == DectreeError::Duplicate {
+						self.duplicates.push(Duplicate { feed: feed.to_string(), name: sig.object.clone() });
+					}
+					ids.push(None);
+					continue
+				}
+			};
+			// A rule's name is only taken once one of its signatures is actually merged.
+			if !assigned {
+				self.assign(feed, &sig.object, name);
+			}
+			self.tree.set_enabled(id, sig.enabled);
+			if let Some(provenance) = &sig.provenance {
+				self.tree.set_provenance(id, provenance.clone().feed(feed));
+			}
+			if let Some(target) = &sig.target {
+				self.tree.set_target(id, target.clone());
+			}
+			self.tree.set_constraints(id, sig.constraints.clone());
+			ids.push(Some(id));
+		}
+		ids
+	}
+
+	/// Get the name for the rule `name` of the feed `feed`, along with whether it has already been
+	/// assigned. A name not assigned yet is the one the rule would get, and is left free until
+	/// `assign()` is called with it.
+	fn name_for(&self, feed: &str, name: &str) -> (String, bool) {
+		if let Some(assigned) = self.assigned.get(&(feed.to_string(), name.to_string())) {
+			return (assigned.clone(), true)
+		}
+		let mut assigned = name.to_string();
+		if self.taken.contains(&assigned) {
+			let prefixed = format!("{feed}::{name}");
+			assigned = prefixed.clone();
+			let mut suffix = 2;
+			while self.taken.contains(&assigned) {
+				assigned = format!("{prefixed}~{suffix}");
+				suffix += 1;
+			}
+		}
+		(assigned, false)
+	}
+
+	/// Assign `assigned` to the rule `name` of the feed `feed`, reporting it if it is a rename.
+	fn assign(&mut self, feed: &str, name: &str, assigned: String) {
+		if assigned != name {
+			self.renamed.push(Renamed { feed: feed.to_string(), original: name.to_string(), assigned: assigned.clone() });
+		}
+		self.taken.insert(assigned.clone());
+		self.assigned.insert((feed.to_string(), name.to_string()), assigned);
+	}
+
+	/// Get the rules renamed so far, in the order they were renamed.
+	pub fn renamed(&self) -> &[Renamed] {
+		&self.renamed
+	}
+
+	/// Get the signatures dropped as duplicates so far, in the order they were dropped.
+	pub fn duplicates(&self) -> &[Duplicate] {
+		&self.duplicates
+	}
+
+	/// Get the merged tree.
+	pub fn tree(&self) -> &SignatureDecisionTree<String> {
+		&self.tree
+	}
+
+	/// Finish merging and take the merged tree.
+	pub fn into_tree(self) -> SignatureDecisionTree<String> {
+		self.tree
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Duplicate, FeedMerger};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_feed_merger() {
+		let mut first = SignatureDecisionTree::new();
+		first.add_signature(vec![0x4d, 0x5a], None, "A".to_string());
+		first.add_signature(vec![0x4d, 0x5a, 0x90], None, "A".to_string());
+		first.add_signature(vec![0x7f, 0x45], None, "feed::A".to_string());
+		let mut second = SignatureDecisionTree::new();
+		second.add_signature(vec![0x4d, 0x5a], None, "B".to_string());
+		second.add_signature(vec![0xca, 0xfe], None, "A".to_string());
+		second.add_signature(vec![0xca, 0xfe, 0xba], None, "A".to_string());
+		let mut merger = FeedMerger::new();
+		assert_eq!(merger.add_feed("first", &first).iter().flatten().count(), 3);
+		let ids = merger.add_feed("feed", &second);
+		assert_eq!(ids[0], None);
+		assert_eq!(merger.duplicates(), &[Duplicate { feed: "feed".to_string(), name: "B".to_string() }]);
+		let names = merger.renamed().iter().map(|renamed| renamed.assigned.as_str()).collect::<Vec<_>>();
+		assert_eq!(names, vec!["feed::A~2"]);
+		assert_eq!(merger.tree().get_signature([0xca, 0xfe, 0xba], None), Some("feed::A~2".to_string()));
+		assert_eq!(merger.tree().get_signature([0x7f, 0x45], None), Some("feed::A".to_string()));
+		// The earlier B was dropped entirely, so the name is still free.
+		let mut third = SignatureDecisionTree::new();
+		third.add_signature(vec![0xd0, 0xcf], None, "B".to_string());
+		merger.add_feed("third", &third);
+		assert_eq!(merger.renamed().len(), 1);
+		assert_eq!(merger.tree().get_signature([0xd0, 0xcf], None), Some("B".to_string()));
+	}
+
+	#[test]
+	fn test_feed_merger_staged() {
+		let mut primary = SignatureDecisionTree::new();
+		primary.add_signature(vec![0x4d, 0x5a], None, "A".to_string());
+		let mut update = SignatureDecisionTree::new();
+		update.set_staging(true);
+		update.add_signature(vec![0x4d, 0x5a], None, "A".to_string());
+		update.add_signature(vec![0x7f, 0x45], None, "E".to_string());
+		let mut merger = FeedMerger::new();
+		merger.add_feed("primary", &primary);
+		let ids = merger.add_feed("update", &update);
+		assert!(merger.duplicates().is_empty());
+		assert!(ids.iter().all(|id| id.is_some_and(|id| merger.tree().is_staged(id))));
+		assert!(!merger.tree().staging());
+		assert_eq!(merger.tree().scan([0x4d, 0x5a, 0x7f, 0x45]), vec![(0, "A".to_string())]);
+		assert_eq!(merger.tree().scan_staged([0x7f, 0x45])[0].object, "E");
+	}
+}
@@ -0,0 +1,184 @@
+//! A bounded-capacity signature matcher for targets without an allocator.
+//!
+//! `FixedSignatureTree` stores at most `N` signatures of at most `L` bytes each in fixed-size arrays,
+//! so neither inserting nor querying ever touches the heap. It is meant for small rule sets on
+//! embedded devices; larger sets should use `SignatureDecisionTree`.
+//!
+//! The module only uses `core`. With the default `std` feature turned off
+//! (`default-features = false, features = ["heapless"]`), the crate is `no_std` and this module is
+//! all that is left of it, so it can be built for bare-metal targets without an allocator, such as
+//! Cortex-M.
+
+/// Represents the reasons a signature can't be stored in a `FixedSignatureTree`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapacityError {
+	/// The tree already holds its maximum number of signatures.
+	Full,
+	/// The signature is longer than the maximum signature length.
+	TooLong,
+	/// The masks are not the same length as the signature bytes.
+	MaskLength,
+	/// The signature has no bytes.
+	Empty
+}
+
+/// Represents a signature stored inline in a `FixedSignatureTree`.
+#[derive(Clone, Debug)]
+struct FixedSignature<T, const L: usize> {
+	bytes: [u8; L],
+	masks: [u8; L],
+	len: usize,
+	object: T
+}
+
+impl<T, const L: usize> FixedSignature<T, L> {
+	/// Check if the signature matches `bytes` at `offset`.
+	fn matches(&self, bytes: &[u8], offset: usize) -> bool {
+		match bytes.get(offset..) {
+			Some(rest) if rest.len() >= self.len => (0..self.len).all(|i| rest[i] & self.masks[i] == self.bytes[i]),
+			_ => false
+		}
+	}
+}
+
+/// Represents a fixed-capacity signature matcher holding at most `N` signatures of at most `L`
+/// bytes. Like `SignatureDecisionTree`, lookups return the object of the longest matching
+/// signature.
+/// ```rust
+/// use dectree_rs::fixed::FixedSignatureTree;
+///
+/// let mut tree: FixedSignatureTree<u8, 4, 8> = FixedSignatureTree::new();
+/// tree.add_signature(&[0x55, 0x8b, 0xec], None, 1).unwrap();
+/// tree.add_signature(&[0x55, 0x8b], None, 2).unwrap();
+/// assert_eq!(tree.get_signature(&[0x55, 0x8b, 0xec, 0x83], None), Some(&1));
+/// assert_eq!(tree.get_signature(&[0x55, 0x8b, 0x00], None), Some(&2));
+/// ```
+#[derive(Clone, Debug)]
+pub struct FixedSignatureTree<T, const N: usize, const L: usize> {
+	signatures: [Option<FixedSignature<T, L>>; N],
+	len: usize
+}
+
+impl<T, const N: usize, const L: usize> Default for FixedSignatureTree<T, N, L> {
+	fn default() -> Self {
+		FixedSignatureTree::new()
+	}
+}
+
+impl<T, const N: usize, const L: usize> FixedSignatureTree<T, N, L> {
+
+	/// Create a new, empty `FixedSignatureTree`.
+	pub const fn new() -> Self {
+		FixedSignatureTree {
+			signatures: [const { None }; N],
+			len: 0
+		}
+	}
+
+	/// The number of signatures stored in the tree.
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Check if the tree holds no signatures.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Add a signature to the tree.  If masks goes unspecified, it will be assumed to be all
+	/// ones. Bits outside the masks are ignored, as `SignatureDecisionTree` ignores them. Adding a
+	/// bytes and masks pair that is already stored is a no-op.
+	pub fn add_signature(&mut self, bytes: &[u8], masks: Option<&[u8]>, val: T) -> Result<(), CapacityError> {
+		if bytes.is_empty() {
+			return Err(CapacityError::Empty)
+		}
+		if bytes.len() > L {
+			return Err(CapacityError::TooLong)
+		}
+		if masks.is_some_and(|masks| masks.len() != bytes.len()) {
+			return Err(CapacityError::MaskLength)
+		}
+		let mut signature = FixedSignature {
+			bytes: [0; L],
+			masks: [0xff; L],
+			len: bytes.len(),
+			object: val
+		};
+		if let Some(masks) = masks {
+			signature.masks[..masks.len()].copy_from_slice(masks);
+		}
+		// The bytes are stored masked, so they compare equal to masked input.
+		for (index, &byte) in bytes.iter().enumerate() {
+			signature.bytes[index] = byte & signature.masks[index];
+		}
+		// Detect and skip duplicate additions...
+		let is_duplicate = self.signatures[..self.len].iter().flatten().any(|sig| {
+			sig.len == signature.len && sig.bytes == signature.bytes && sig.masks == signature.masks
+		});
+		if is_duplicate {
+			return Ok(())
+		}
+		if self.len == N {
+			return Err(CapacityError::Full)
+		}
+		self.signatures[self.len] = Some(signature);
+		self.len += 1;
+		Ok(())
+	}
+
+	/// Check if a signature is in the tree.
+	pub fn is_signature(&self, bytes: &[u8], offset: Option<i32>) -> bool {
+		self.get_signature(bytes, offset).is_some()
+	}
+
+	/// Get the object associated with the longest signature matching `bytes` at `offset`.
+	pub fn get_signature(&self, bytes: &[u8], offset: Option<i32>) -> Option<&T> {
+		let offset = offset.unwrap_or_default().max(0) as usize;
+		let mut best: Option<&FixedSignature<T, L>> = None;
+		for sig in self.signatures[..self.len].iter().flatten() {
+			if sig.matches(bytes, offset) && best.is_none_or(|best| sig.len > best.len) {
+				best = Some(sig);
+			}
+		}
+		best.map(|sig| &sig.object)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{CapacityError, FixedSignatureTree};
+
+	#[test]
+	fn test_fixed_capacity() {
+		let mut tree: FixedSignatureTree<u32, 2, 4> = FixedSignatureTree::new();
+		assert_eq!(tree.add_signature(&[0x55, 0xe9, 0xd8, 0x01, 0xfe], None, 1), Err(CapacityError::TooLong));
+		assert_eq!(tree.add_signature(&[0x55, 0xe9], Some(&[0xff]), 1), Err(CapacityError::MaskLength));
+		assert_eq!(tree.add_signature(&[0x55, 0x00, 0xd8], Some(&[0xff, 0x00, 0xff]), 1), Ok(()));
+		assert_eq!(tree.add_signature(&[0x55, 0x00, 0xd8], Some(&[0xff, 0x00, 0xff]), 2), Ok(()));
+		assert_eq!(tree.add_signature(&[0x55, 0xe9, 0xd8, 0x01], None, 3), Ok(()));
+		assert_eq!(tree.add_signature(&[0x55], None, 4), Err(CapacityError::Full));
+		assert_eq!(tree.len(), 2);
+		assert_eq!(tree.get_signature(&[0x00, 0x55, 0x12, 0xd8], Some(1)), Some(&1));
+		assert_eq!(tree.get_signature(&[0x55, 0xe9, 0xd8, 0x01], None), Some(&3));
+		assert_eq!(tree.get_signature(&[0x55, 0xe9], None), None);
+		assert!(!tree.is_signature(&[0x55, 0xe9, 0xd8], Some(8)));
+		assert_eq!(tree.add_signature(&[], None, 5), Err(CapacityError::Empty));
+	}
+
+	#[test]
+	fn test_fixed_masked_bytes() {
+		let mut tree: FixedSignatureTree<u32, 4, 4> = FixedSignatureTree::new();
+		assert_eq!(tree.add_signature(&[0x55, 0x12], Some(&[0xff, 0x00]), 1), Ok(()));
+		assert_eq!(tree.add_signature(&[0x8b, 0xec], Some(&[0xff, 0xf0]), 2), Ok(()));
+		// The same patterns written with other bits outside their masks are duplicates.
+		assert_eq!(tree.add_signature(&[0x55, 0x34], Some(&[0xff, 0x00]), 3), Ok(()));
+		assert_eq!(tree.add_signature(&[0x8b, 0xe3], Some(&[0xff, 0xf0]), 4), Ok(()));
+		assert_eq!(tree.len(), 2);
+		assert_eq!(tree.get_signature(&[0x55, 0x12], None), Some(&1));
+		assert_eq!(tree.get_signature(&[0x55, 0x00], None), Some(&1));
+		assert_eq!(tree.get_signature(&[0x8b, 0xec], None), Some(&2));
+		assert_eq!(tree.get_signature(&[0x8b, 0xe0], None), Some(&2));
+		assert_eq!(tree.get_signature(&[0x8b, 0xdc], None), None);
+		assert!(!tree.is_signature(&[0x01], None));
+	}
+}
@@ -0,0 +1,390 @@
+//! Importers for the signature formats of other tools, so existing signature collections can be
+//! loaded into a tree directly. Every signature an importer adds records its provenance.
+//!
+//! `export()` writes a tree back out in any of those formats. Rules imported from the format being
+//! exported are reproduced exactly as written, and every other signature is converted from its
+//! bytes and masks, so the tree can serve as a conversion hub between formats.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+
+use crate::pattern::{Pattern, PatternBuilder};
+use crate::provenance::ImportFormat;
+use crate::{SignatureDecisionTree, SignatureId, SignatureIndex};
+
+pub mod clamav;
+pub mod flirt;
+pub mod peid;
+pub mod yara;
+pub mod zignature;
+
+/// The most signatures a single imported signature may expand into through alternation.
+pub const MAX_ALTERNATIVES: usize = 256;
+
+/// The longest fixed-length jump or skip an imported signature may contain, in bytes. Each skipped
+/// byte becomes a wildcard byte of the signature, so longer ones are rejected rather than
+/// allocated.
+pub const MAX_JUMP: usize = u16::MAX as usize;
+
+/// Represents a piece of a hex signature, as the importers parse them.
+enum Token {
+	/// A byte compared under a mask.
+	Byte {
+		value: u8,
+		mask: u8
+	},
+	/// A choice between sequences of pieces.
+	Alternation(Vec<Vec<Token>>)
+}
+
+/// Represents the ways parsed pieces can fail to make up patterns.
+enum ExpandError {
+	/// The pieces contain no bytes.
+	Empty,
+	/// The alternations expand into more than `MAX_ALTERNATIVES` patterns.
+	TooManyAlternatives
+}
+
+/// Expand `tokens` into the patterns they match, one per combination of alternatives.
+fn expand_patterns(tokens: &[Token]) -> Result<Vec<Pattern>, ExpandError> {
+	expand(tokens)
+		.ok_or(ExpandError::TooManyAlternatives)?
+		.into_iter()
+		.map(|pieces| pieces.into_iter().fold(PatternBuilder::new(), |builder, (value, mask)| builder.masked(value, mask)).build())
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|_| ExpandError::Empty)
+}
+
+/// Expand `tokens` into every sequence of bytes and masks they can match, or `None` if there are
+/// more than `MAX_ALTERNATIVES` of them.
+fn expand(tokens: &[Token]) -> Option<Vec<Vec<(u8, u8)>>> {
+	let mut expansions = vec![Vec::new()];
+	for token in tokens {
+		match token {
+			Token::Byte { value, mask } => {
+				for expansion in expansions.iter_mut() {
+					expansion.push((value & mask, *mask));
+				}
+			},
+			Token::Alternation(alternatives) => {
+				let mut options = Vec::new();
+				for alternative in alternatives {
+					options.extend(expand(alternative)?);
+				}
+				if expansions.len() * options.len() > MAX_ALTERNATIVES {
+					return None
+				}
+				expansions = expansions.iter()
+					.flat_map(|expansion| options.iter().map(move |option| [&expansion[..], &option[..]].concat()))
+					.collect();
+			}
+		}
+	}
+	Some(expansions)
+}
+
+/// Represents the ways a tree can fail to export.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExportError {
+	/// The signature with the id `id` can't be written in the format, such as a signature with
+	/// partial masks in a format without nibble wildcards.
+	Unrepresentable {
+		id: SignatureId
+	}
+}
+
+impl Display for ExportError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ExportError::Unrepresentable { id } => write!(f, "signature {id:?} can't be written in the format")
+		}
+	}
+}
+
+impl std::error::Error for ExportError {}
+
+/// Represents a rule in an export.
+enum Entry {
+	/// A rule reproduced from its provenance.
+	Original(String),
+	/// A rule converted from signatures, with its name and the signatures written in the format.
+	Converted(String, Vec<String>)
+}
+
+/// Write every signature of `tree`, disabled ones included, in `format`. Rules that were imported
+/// from `format` are reproduced exactly as written, once however many signatures they expand into.
+/// Every other signature is converted, with its object as the rule name: each becomes a line of
+/// its own in the line-based formats, an entry of its own in PEiD (without `ep_only`), and the
+/// signatures sharing an object become the strings of one rule in YARA.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::formats::{clamav, export};
+/// use dectree_rs::provenance::ImportFormat;
+///
+/// let database = "Example.A:0:*:558b(ec|e5)\n";
+/// let mut tree = SignatureDecisionTree::new();
+/// clamav::load_ndb(&mut tree, database, None).unwrap();
+/// assert_eq!(export(&tree, ImportFormat::ClamAv).unwrap(), database);
+/// assert_eq!(export(&tree, ImportFormat::Yara).unwrap(), "\
+/// rule Example_A {
+///     strings:
+///         $s0 = { 55 8B EC }
+///         $s1 = { 55 8B E5 }
+///     condition:
+///         any of them
+/// }
+/// ");
+/// ```
+pub fn export<T, S>(tree: &SignatureDecisionTree<T, S>, format: ImportFormat) -> Result<String, ExportError>
+	where T: Clone + Display, S: BuildHasher {
+	let (exported, unrepresentable) = export_representable(tree, format);
+	match unrepresentable.first() {
+		Some(&id) => Err(ExportError::Unrepresentable { id }),
+		None => Ok(exported)
+	}
+}
+
+/// Write every signature of `tree` in `format` like `export()`, but skip the signatures the
+/// format can't express rather than failing. Returns the export along with the ids of the skipped
+/// signatures, in the order they were added.
+pub fn export_representable<T, S>(tree: &SignatureDecisionTree<T, S>, format: ImportFormat) -> (String, Vec<SignatureId>)
+	where T: Clone + Display, S: BuildHasher {
+	let (mut entries, mut unrepresentable) = (Vec::new(), Vec::new());
+	let mut reproduced = HashSet::new();
+	// The entry of each converted YARA rule, by name.
+	let mut rule_entries = HashMap::new();
+	for (index, sig) in tree.signatures.iter().enumerate() {
+		if let Some(provenance) = sig.provenance.as_ref().filter(|provenance| provenance.format == format) {
+			let text = provenance.rule.as_ref().unwrap_or(&provenance.text);
+			if reproduced.insert((&provenance.path, text)) {
+				entries.push(Entry::Original(text.clone()));
+			}
+			continue
+		}
+		let id = SignatureId(index as SignatureIndex);
+		let masks = sig.masks.to_vec(sig.bytes.len());
+		let Some(written) = write_signature(format, &sig.bytes, &masks) else {
+			unrepresentable.push(id);
+			continue
+		};
+		let name = sig.object.to_string();
+		// Only YARA rules gather several signatures, so only they are looked up by name.
+		if format == ImportFormat::Yara {
+			if let Some(Entry::Converted(_, signatures)) = rule_entries.get(&name).map(|&entry| &mut entries[entry]) {
+				signatures.push(written);
+				continue
+			}
+			rule_entries.insert(name.clone(), entries.len());
+		}
+		entries.push(Entry::Converted(name, vec![written]));
+	}
+	// YARA rule names must be unique, and converted names can collide once they are made into
+	// identifiers, or with the names of the rules reproduced as written.
+	let mut identifiers = entries.iter()
+		.filter_map(|entry| match entry {
+			Entry::Original(text) if format == ImportFormat::Yara => yara_rule_name(text),
+			_ => None
+		})
+		.collect::<HashSet<_>>();
+	let rules = entries.into_iter()
+		.map(|entry| match entry {
+			Entry::Original(text) => text,
+			Entry::Converted(name, signatures) if format == ImportFormat::Yara => {
+				convert_rule(format, &yara_identifier(&name, &mut identifiers), &signatures)
+			},
+			Entry::Converted(name, signatures) => convert_rule(format, &rule_name(format, &name), &signatures)
+		})
+		.collect::<Vec<_>>();
+	let separator = match format {
+		ImportFormat::Yara | ImportFormat::Peid => "\n\n",
+		_ => "\n"
+	};
+	let mut exported = rules.join(separator);
+	if !exported.is_empty() {
+		exported.push('\n');
+	}
+	if format == ImportFormat::Flirt {
+		exported.push_str("---\n");
+	}
+	(exported, unrepresentable)
+}
+
+/// Write a signature in the hex syntax of `format`, or `None` if the format can't express it.
+fn write_signature(format: ImportFormat, bytes: &[u8], masks: &[u8]) -> Option<String> {
+	let (nibbles, separator) = match format {
+		ImportFormat::HexString => (true, " "),
+		ImportFormat::Peid => (false, " "),
+		ImportFormat::Flirt => {
+			if bytes.len() > flirt::MAX_LEADING_BYTES {
+				return None
+			}
+			let pieces = bytes.iter().zip(masks)
+				.map(|(&byte, &mask)| match mask {
+					0xff => Some(format!("{byte:02X}")),
+					0x00 => Some("..".to_string()),
+					_ => None
+				})
+				.collect::<Option<String>>()?;
+			return Some(format!("{pieces:.<64} 00 0000 {:04X}", bytes.len()))
+		},
+		ImportFormat::Yara => (true, " "),
+		ImportFormat::ClamAv => (true, ""),
+		ImportFormat::Zignature => {
+			let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+			return Some(format!("{}:{}", hex(bytes), hex(masks)))
+		},
+		ImportFormat::Guid => {
+			if bytes.len() != 16 || masks.iter().any(|&mask| mask != 0xff) {
+				return None
+			}
+			let mut guid = bytes.to_vec();
+			guid[0..4].reverse();
+			guid[4..6].reverse();
+			guid[6..8].reverse();
+			let hex = guid.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+			return Some(format!("{{{}-{}-{}-{}-{}}}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]))
+		}
+	};
+	let pieces = bytes.iter().zip(masks)
+		.map(|(&byte, &mask)| match mask {
+			0xff => Some(format!("{byte:02X}")),
+			0x00 => Some("??".to_string()),
+			0xf0 if nibbles => Some(format!("{:X}?", byte >> 4)),
+			0x0f if nibbles => Some(format!("?{:X}", byte & 0xf)),
+			_ => None
+		})
+		.collect::<Option<Vec<_>>>()?;
+	let written = pieces.join(separator);
+	// ClamAV databases are conventionally written in lowercase.
+	Some(if format == ImportFormat::ClamAv { written.to_lowercase() } else { written })
+}
+
+/// Write a rule named `name` holding `signatures`, already written in the hex syntax of `format`.
+fn convert_rule(format: ImportFormat, name: &str, signatures: &[String]) -> String {
+	match format {
+		ImportFormat::ClamAv => format!("{name}:0:*:{}", signatures[0]),
+		ImportFormat::Flirt => format!("{} :0000 {name}", signatures[0]),
+		ImportFormat::Peid => format!("[{name}]\nsignature = {}\nep_only = false", signatures[0]),
+		ImportFormat::Zignature => format!("za {name} b {}", signatures[0]),
+		ImportFormat::Yara => {
+			let strings = signatures.iter()
+				.enumerate()
+				.map(|(index, signature)| format!("        $s{index} = {{ {signature} }}\n"))
+				.collect::<String>();
+			format!("rule {name} {{\n    strings:\n{strings}    condition:\n        any of them\n}}")
+		},
+		_ => signatures[0].clone()
+	}
+}
+
+/// Make `name` into a rule name `format` can hold, so the rule reads back as it was written.
+/// YARA names are made into identifiers with `yara_identifier()` instead.
+fn rule_name(format: ImportFormat, name: &str) -> String {
+	match format {
+		// Fields are separated by colons in `.ndb` databases and by semicolons in `.ldb` ones, so
+		// only the characters of conventional ClamAV names are kept.
+		ImportFormat::ClamAv => name.chars()
+			.map(|character| if character.is_ascii_alphanumeric() || "._-".contains(character) { character } else { '_' })
+			.collect(),
+		// Names are separated by whitespace, and every public name and zignature needs one.
+		ImportFormat::Flirt | ImportFormat::Zignature if name.is_empty() => "_".to_string(),
+		ImportFormat::Flirt | ImportFormat::Zignature => name.chars().map(|character| if character.is_whitespace() { '_' } else { character }).collect(),
+		// The section header holding the name takes a line of its own.
+		ImportFormat::Peid => name.replace(['\r', '\n'], " "),
+		_ => name.to_string()
+	}
+}
+
+/// Make `name` into a YARA identifier that isn't in `taken`, and add it there. Characters an
+/// identifier can't hold become underscores, and a name already taken gets a numbered suffix.
+fn yara_identifier(name: &str, taken: &mut HashSet<String>) -> String {
+	// Rule names are identifiers, and can't start with a digit.
+	let mut identifier = name.chars().map(|character| if character.is_ascii_alphanumeric() { character } else { '_' }).collect::<String>();
+	if !identifier.starts_with(|character: char| character.is_ascii_alphabetic() || character == '_') {
+		identifier.insert(0, '_');
+	}
+	let unique = (1..)
+		.map(|count| match count {
+			1 => identifier.clone(),
+			_ => format!("{identifier}_{count}")
+		})
+		.find(|candidate| !taken.contains(candidate))
+		.expect("some suffix is free");
+	taken.insert(unique.clone());
+	unique
+}
+
+/// Get the name of the YARA rule written as `text`, if it can be found.
+fn yara_rule_name(text: &str) -> Option<String> {
+	let mut words = text.split(|character: char| character.is_whitespace() || character == ':' || character == '{');
+	words.find(|&word| word == "rule")?;
+	words.find(|word| !word.is_empty()).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{clamav, export, flirt, peid, yara, ExportError};
+	use crate::provenance::ImportFormat;
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_export_round_trip() {
+		let rules = "private rule A : tag {\n\tstrings:\n\t\t$a = { 4D 5A ( 90 | 00 ) } // header\n\t\t$b = { C3 }\n\tcondition:\n\t\tall of them\n}\n\nrule B { strings: $ = { E8 [4] } condition: all of them }\n";
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(yara::load_rules(&mut tree, rules, None), Ok(4));
+		assert_eq!(export(&tree, ImportFormat::Yara).unwrap(), rules);
+		assert_eq!(export(&tree, ImportFormat::ClamAv).unwrap(), "A:0:*:4d5a90\nA:0:*:4d5a00\nA:0:*:c3\nB:0:*:e8????????\n");
+		tree.add_signature_str("55 8b ?? ec", "C".to_string()).unwrap();
+		assert!(export(&tree, ImportFormat::HexString).unwrap().ends_with("C3\nE8 ?? ?? ?? ??\n55 8b ?? ec\n"));
+		assert_eq!(export(&SignatureDecisionTree::<i32>::new(), ImportFormat::Yara), Ok(String::new()));
+	}
+
+	#[test]
+	fn test_export_conversion() {
+		let mut tree = SignatureDecisionTree::new();
+		let guid = [0x40, 0xfc, 0x29, 0x6b, 0x47, 0xca, 0x67, 0x10, 0xb3, 0x1d, 0x00, 0xdd, 0x01, 0x06, 0x62, 0xda];
+		tree.add_signature(guid.to_vec(), None, 1);
+		assert_eq!(export(&tree, ImportFormat::Guid), Ok("{6b29fc40-ca47-1067-b31d-00dd010662da}\n".to_string()));
+		let id = tree.add_signature_with_id(vec![0x55, 0x80], Some(vec![0xff, 0xf0]), 2).unwrap();
+		assert_eq!(export(&tree, ImportFormat::Guid), Err(ExportError::Unrepresentable { id }));
+		assert!(export(&tree, ImportFormat::HexString).unwrap().ends_with("\n55 8?\n"));
+		assert_eq!(export(&tree, ImportFormat::Peid), Err(ExportError::Unrepresentable { id }));
+		assert!(export(&tree, ImportFormat::Yara).unwrap().ends_with("rule _2 {\n    strings:\n        $s0 = { 55 8? }\n    condition:\n        any of them\n}\n"));
+		assert_eq!(export(&tree, ImportFormat::Flirt), Err(ExportError::Unrepresentable { id }));
+		let mut functions = SignatureDecisionTree::new();
+		functions.add_signature(vec![0x31, 0xc0, 0x00, 0xc3], Some(vec![0xff, 0xff, 0x00, 0xff]), "_zero");
+		assert_eq!(export(&functions, ImportFormat::Flirt).unwrap(), format!("31C0..C3{} 00 0000 0004 :0000 _zero\n---\n", "..".repeat(28)));
+		assert_eq!(export(&functions, ImportFormat::Peid).unwrap(), "[_zero]\nsignature = 31 C0 ?? C3\nep_only = false\n");
+		tree.add_signature(vec![0x55, 0x80], Some(vec![0xff, 0x3c]), 3);
+		assert!(export(&tree, ImportFormat::Yara).is_err());
+	}
+
+	#[test]
+	fn test_export_unique_identifiers() {
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(yara::load_rules(&mut tree, "rule Win_A_1_2 { strings: $a = { 90 } condition: $a }", None), Ok(1));
+		tree.add_signature(vec![0x55], None, "Win.A-1".to_string());
+		tree.add_signature(vec![0x56], None, "Win.A.1".to_string());
+		tree.add_signature(vec![0x57], None, "Win A 1".to_string());
+		let exported = export(&tree, ImportFormat::Yara).unwrap();
+		let names = exported.lines().filter_map(|line| line.strip_prefix("rule ")).collect::<Vec<_>>();
+		assert_eq!(names, vec!["Win_A_1_2 { strings: $a = { 90 } condition: $a }", "Win_A_1 {", "Win_A_1_3 {", "Win_A_1_4 {"]);
+	}
+
+	#[test]
+	fn test_export_names() {
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(peid::load_userdb(&mut tree, "[Armadillo 1.71 : Silicon Realms]\nsignature = 55 8B EC 6A FF\nep_only = true\n", None), Ok(1));
+		tree.add_signature(vec![0xc3], None, peid::Packer { name: "Two\nlines".to_string(), ep_only: false });
+		let ndb = export(&tree, ImportFormat::ClamAv).unwrap();
+		assert_eq!(ndb, "Armadillo_1.71___Silicon_Realms:0:*:558bec6aff\nTwo_lines:0:*:c3\n");
+		assert_eq!(clamav::parse_ndb(&ndb).unwrap().len(), 2);
+		let pat = export(&tree, ImportFormat::Flirt).unwrap();
+		let patterns = flirt::parse_pat(&pat).unwrap();
+		assert_eq!(patterns[0].names[0].1, "Armadillo_1.71_:_Silicon_Realms");
+		assert_eq!(patterns[0].pattern.len(), 5);
+		let userdb = export(&tree, ImportFormat::Peid).unwrap();
+		assert_eq!(peid::parse_userdb(&userdb).unwrap()[1].packer.name, "Two lines");
+	}
+}
@@ -0,0 +1,457 @@
+//! Import of body-based ClamAV signatures, from `.ndb` and `.ldb` databases.
+//!
+//! An `.ndb` line is `Name:TargetType:Offset:HexSignature`, optionally followed by the minimum and
+//! maximum engine levels. An `.ldb` line is `Name;TargetDescription;LogicalExpression;Subsig0;...`;
+//! every subsignature is imported on its own and the logical expression is ignored. Blank lines and
+//! lines starting with `#` are skipped.
+//!
+//! Within a hex signature the following are understood:
+//!
+//! - bytes written as two hex digits, and `??` for a wildcard byte,
+//! - nibble wildcards such as `a?` and `?a`, which match on the other half of the byte only,
+//! - fixed-length skips such as `{4}` (or `{4-4}`) of up to `MAX_JUMP` bytes, which become that
+//!   many wildcard bytes,
+//! - alternatives such as `(8bec|89e5)`, which are expanded into one signature per alternative.
+//!
+//! Anything the tree can't represent as a fixed-length signature is rejected with
+//! `ClamAvError::Unsupported`: `*`, skips of varying length such as `{2-4}` or `{-4}`, negated
+//! alternatives `!(...)`, anchored bytes `[n-m]`, boundary markers such as `(B)`, subsignature
+//! modifiers such as `::i` and PCRE subsignatures. Offsets are kept with each signature but not
+//! enforced, since the tree matches wherever it is asked to. `load_ndb()` and `load_ldb()` reject a
+//! database with any such line, while `load_ndb_lenient()` and `load_ldb_lenient()` import every
+//! line that can be represented and return the errors for the rest.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::formats::clamav::load_ndb;
+//!
+//! let database = "# prologues\nWin.Trojan.Example-1:1:*:558b??83ec{4}(e8|e9)\n";
+//! let mut tree = SignatureDecisionTree::new();
+//! assert_eq!(load_ndb(&mut tree, database, None), Ok(2));
+//! let input = [0x55, 0x8b, 0xec, 0x83, 0xec, 0x10, 0x53, 0x56, 0x57, 0xe9];
+//! assert_eq!(tree.get_signature(input, None), Some("Win.Trojan.Example-1".to_string()));
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::pattern::Pattern;
+use crate::provenance::{ImportFormat, Provenance};
+use crate::SignatureDecisionTree;
+use super::{expand_patterns, ExpandError, Token, MAX_ALTERNATIVES, MAX_JUMP};
+
+/// Represents the ways ClamAV signatures can fail to import. Lines are counted from 1, and
+/// positions are byte offsets into the hex signature (or subsignature) on that line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClamAvError {
+	/// A line with fewer fields than its format requires.
+	MissingFields {
+		line: usize
+	},
+	/// A character that doesn't fit the hex signature syntax.
+	InvalidCharacter {
+		line: usize,
+		position: usize,
+		character: char
+	},
+	/// The hex signature on `line` ends in the middle of a byte or construct.
+	UnexpectedEnd {
+		line: usize
+	},
+	/// A construct starting at `position` that can't be represented as a fixed-length signature,
+	/// such as `*` or `{2-4}`.
+	Unsupported {
+		line: usize,
+		position: usize,
+		construct: String
+	},
+	/// A fixed-length skip starting at `position` that is longer than `MAX_JUMP` bytes.
+	SkipTooLong {
+		line: usize,
+		position: usize
+	},
+	/// The hex signature on `line` contains no bytes.
+	EmptySignature {
+		line: usize
+	},
+	/// The hex signature on `line` expands into more than `MAX_ALTERNATIVES` signatures.
+	TooManyAlternatives {
+		line: usize
+	}
+}
+
+impl Display for ClamAvError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ClamAvError::MissingFields { line } => write!(f, "signature on line {line} is missing fields"),
+			ClamAvError::InvalidCharacter { line, position, character } => write!(f, "invalid character {character:?} at position {position} on line {line}"),
+			ClamAvError::UnexpectedEnd { line } => write!(f, "hex signature on line {line} ends early"),
+			ClamAvError::Unsupported { line, position, construct } => write!(f, "unsupported construct {construct:?} at position {position} on line {line}"),
+			ClamAvError::SkipTooLong { line, position } => write!(f, "skip at position {position} on line {line} is longer than {MAX_JUMP} bytes"),
+			ClamAvError::EmptySignature { line } => write!(f, "hex signature on line {line} is empty"),
+			ClamAvError::TooManyAlternatives { line } => write!(f, "hex signature on line {line} expands into more than {MAX_ALTERNATIVES} signatures")
+		}
+	}
+}
+
+impl std::error::Error for ClamAvError {}
+
+/// Represents a body-based ClamAV signature, or one subsignature of a logical signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClamAvSignature {
+	/// The name of the signature.
+	pub name: String,
+	/// The file types the signature applies to, as written: the target type of an `.ndb`
+	/// signature, such as `0` for any file or `1` for PE files, or the target description of an
+	/// `.ldb` signature, such as `Engine:51-255,Target:1`.
+	pub target: String,
+	/// The offset the signature is expected at, as written, such as `*` or `EP+0`.
+	pub offset: String,
+	/// The line the signature is on.
+	pub line: usize,
+	/// The whole line, as written.
+	pub text: String,
+	/// The signatures the hex signature expands into, one per combination of alternatives.
+	pub patterns: Vec<Pattern>
+}
+
+/// Parse every signature of an `.ndb` database.
+/// ```rust
+/// use dectree_rs::formats::clamav::parse_ndb;
+///
+/// let signatures = parse_ndb("Eicar-Part:0:EOF-8:2448{2}48212a\n").unwrap();
+/// assert_eq!((signatures[0].name.as_str(), signatures[0].offset.as_str()), ("Eicar-Part", "EOF-8"));
+/// assert_eq!(signatures[0].patterns[0].masks(), &[0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0xff]);
+/// ```
+pub fn parse_ndb(source: &str) -> Result<Vec<ClamAvSignature>, ClamAvError> {
+	database_lines(source).map(|(line, text)| ndb_signature(line, text)).collect()
+}
+
+/// Parse every signature of an `.ndb` database that can be represented, returning the errors for
+/// the lines that can't alongside them, in line order.
+/// ```rust
+/// use dectree_rs::formats::clamav::parse_ndb_lenient;
+///
+/// let (signatures, errors) = parse_ndb_lenient("A:0:*:4d5a90\nB:0:*:4d5a*c3\n");
+/// assert_eq!(signatures.len(), 1);
+/// assert_eq!(errors[0].to_string(), "unsupported construct \"*\" at position 4 on line 2");
+/// ```
+pub fn parse_ndb_lenient(source: &str) -> (Vec<ClamAvSignature>, Vec<ClamAvError>) {
+	let (mut signatures, mut errors) = (Vec::new(), Vec::new());
+	for (line, text) in database_lines(source) {
+		match ndb_signature(line, text) {
+			Ok(signature) => signatures.push(signature),
+			Err(error) => errors.push(error)
+		}
+	}
+	(signatures, errors)
+}
+
+/// Parse the `.ndb` signature `text`, on `line`.
+fn ndb_signature(line: usize, text: &str) -> Result<ClamAvSignature, ClamAvError> {
+	let fields = text.split(':').collect::<Vec<_>>();
+	let [name, target, offset, hex, ..] = fields[..] else {
+		return Err(ClamAvError::MissingFields { line })
+	};
+	Ok(ClamAvSignature {
+		name: name.to_string(),
+		target: target.to_string(),
+		offset: offset.to_string(),
+		line,
+		text: text.to_string(),
+		patterns: parse_hex_signature(hex, line)?
+	})
+}
+
+/// Parse every subsignature of an `.ldb` database. Each subsignature is returned on its own, with
+/// the name of the logical signature it belongs to.
+/// ```rust
+/// use dectree_rs::formats::clamav::parse_ldb;
+///
+/// let signatures = parse_ldb("Win.Example;Engine:51-255,Target:1;0&1;4d5a90;EP+0:558bec").unwrap();
+/// assert_eq!(signatures.len(), 2);
+/// assert_eq!((signatures[1].offset.as_str(), signatures[1].patterns[0].bytes()), ("EP+0", &[0x55, 0x8b, 0xec][..]));
+/// ```
+pub fn parse_ldb(source: &str) -> Result<Vec<ClamAvSignature>, ClamAvError> {
+	let mut signatures = Vec::new();
+	for (line, text) in database_lines(source) {
+		signatures.extend(ldb_signatures(line, text)?);
+	}
+	Ok(signatures)
+}
+
+/// Parse every subsignature of the logical signatures of an `.ldb` database that can be
+/// represented whole, returning the errors for the lines that can't alongside them, in line order.
+/// A logical signature with any subsignature that can't be represented is left out entirely.
+pub fn parse_ldb_lenient(source: &str) -> (Vec<ClamAvSignature>, Vec<ClamAvError>) {
+	let (mut signatures, mut errors) = (Vec::new(), Vec::new());
+	for (line, text) in database_lines(source) {
+		match ldb_signatures(line, text) {
+			Ok(subsignatures) => signatures.extend(subsignatures),
+			Err(error) => errors.push(error)
+		}
+	}
+	(signatures, errors)
+}
+
+/// Parse every subsignature of the `.ldb` logical signature `text`, on `line`.
+fn ldb_signatures(line: usize, text: &str) -> Result<Vec<ClamAvSignature>, ClamAvError> {
+	let fields = text.split(';').collect::<Vec<_>>();
+	let [name, target, _expression, ref subsignatures @ ..] = fields[..] else {
+		return Err(ClamAvError::MissingFields { line })
+	};
+	if subsignatures.is_empty() {
+		return Err(ClamAvError::MissingFields { line })
+	}
+	let mut signatures = Vec::with_capacity(subsignatures.len());
+	for subsignature in subsignatures {
+		if let Some(start) = subsignature.find("::") {
+			return Err(ClamAvError::Unsupported { line, position: start, construct: subsignature[start..].to_string() })
+		}
+		if let Some(start) = subsignature.find('/') {
+			return Err(ClamAvError::Unsupported { line, position: start, construct: subsignature.to_string() })
+		}
+		let (offset, hex) = subsignature.split_once(':').unwrap_or(("*", subsignature));
+		signatures.push(ClamAvSignature {
+			name: name.to_string(),
+			target: target.to_string(),
+			offset: offset.to_string(),
+			line,
+			text: text.to_string(),
+			patterns: parse_hex_signature(hex, line)?
+		});
+	}
+	Ok(signatures)
+}
+
+/// Get the lines of a database that hold signatures, along with their line numbers.
+fn database_lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+	source.lines()
+		.enumerate()
+		.map(|(index, text)| (index + 1, text.trim()))
+		.filter(|(_, text)| !text.is_empty() && !text.starts_with('#'))
+}
+
+/// Parse the hex signature on `line` into the signatures it expands into.
+fn parse_hex_signature(hex: &str, line: usize) -> Result<Vec<Pattern>, ClamAvError> {
+	let mut position = 0;
+	let tokens = hex_sequence(hex.as_bytes(), &mut position, line)?;
+	if position < hex.len() {
+		return Err(invalid_character(hex, position, line))
+	}
+	expand_patterns(&tokens).map_err(|error| match error {
+		ExpandError::Empty => ClamAvError::EmptySignature { line },
+		ExpandError::TooManyAlternatives => ClamAvError::TooManyAlternatives { line }
+	})
+}
+
+/// Describe the character at `position` of `hex` as an error.
+fn invalid_character(hex: &str, position: usize, line: usize) -> ClamAvError {
+	match hex[position..].chars().next() {
+		Some(character) => ClamAvError::InvalidCharacter { line, position, character },
+		None => ClamAvError::UnexpectedEnd { line }
+	}
+}
+
+/// Parse pieces of a hex signature from `position` until the end of the sequence they form: the
+/// end of an alternative, or of the whole signature.
+fn hex_sequence(hex: &[u8], position: &mut usize, line: usize) -> Result<Vec<Token>, ClamAvError> {
+	let unsupported = |start: usize, end: usize| ClamAvError::Unsupported {
+		line,
+		position: start,
+		construct: String::from_utf8_lossy(&hex[start..end.min(hex.len())]).into_owned()
+	};
+	let mut tokens = Vec::new();
+	loop {
+		let start = *position;
+		match hex.get(start) {
+			None | Some(b'|' | b')') => return Ok(tokens),
+			Some(b'*') => return Err(unsupported(start, start + 1)),
+			Some(b'!') => return Err(unsupported(start, start + 2)),
+			Some(b'[') => {
+				let end = hex[start..].iter().position(|&byte| byte == b']').map_or(hex.len(), |end| start + end + 1);
+				return Err(unsupported(start, end))
+			},
+			Some(b'{') => {
+				let end = hex[start..].iter().position(|&byte| byte == b'}').ok_or(ClamAvError::UnexpectedEnd { line })? + start;
+				let skip = std::str::from_utf8(&hex[start + 1..end]).unwrap_or_default();
+				let (low, high) = skip.split_once('-').unwrap_or((skip, skip));
+				// Numbers too large for a `usize` are still lengths, just too long ones.
+				let length = |bound: &str| (!bound.is_empty() && bound.bytes().all(|byte| byte.is_ascii_digit()))
+					.then(|| bound.parse::<usize>().unwrap_or(usize::MAX));
+				match (length(low), length(high)) {
+					(Some(low), Some(high)) if low == high && low > MAX_JUMP => return Err(ClamAvError::SkipTooLong { line, position: start }),
+					(Some(low), Some(high)) if low == high => {
+						tokens.extend((0..low).map(|_| Token::Byte { value: 0x00, mask: 0x00 }));
+					},
+					_ => return Err(unsupported(start, end + 1))
+				}
+				*position = end + 1;
+			},
+			Some(b'(') => {
+				if let Some([b'B' | b'L' | b'W', b')']) = hex.get(start + 1..start + 3) {
+					return Err(unsupported(start, start + 3))
+				}
+				*position += 1;
+				let mut alternatives = vec![hex_sequence(hex, position, line)?];
+				loop {
+					match hex.get(*position) {
+						Some(b'|') => {
+							*position += 1;
+							alternatives.push(hex_sequence(hex, position, line)?);
+						},
+						Some(b')') => {
+							*position += 1;
+							break
+						},
+						_ => return Err(ClamAvError::UnexpectedEnd { line })
+					}
+				}
+				tokens.push(Token::Alternation(alternatives));
+			},
+			Some(_) => {
+				let (high, high_mask) = nibble(hex, *position, line)?;
+				let (low, low_mask) = nibble(hex, *position + 1, line)?;
+				tokens.push(Token::Byte { value: high << 4 | low, mask: high_mask << 4 | low_mask });
+				*position += 2;
+			}
+		}
+	}
+}
+
+/// Parse the hex digit or `?` at `position` as a nibble and the mask it is compared under.
+fn nibble(hex: &[u8], position: usize, line: usize) -> Result<(u8, u8), ClamAvError> {
+	match hex.get(position) {
+		Some(b'?') => Ok((0x0, 0x0)),
+		Some(byte) if byte.is_ascii_hexdigit() => Ok(((*byte as char).to_digit(16).unwrap_or_default() as u8, 0xf)),
+		Some(_) => Err(invalid_character(std::str::from_utf8(hex).unwrap_or_default(), position, line)),
+		None => Err(ClamAvError::UnexpectedEnd { line })
+	}
+}
+
+/// Add `signatures` to `tree` with their names as the objects, recording where each came from.
+fn load<S>(tree: &mut SignatureDecisionTree<String, S>, signatures: Vec<ClamAvSignature>, path: Option<&Path>) -> usize
+	where S: BuildHasher {
+	let mut added = 0;
+	for signature in signatures {
+		for pattern in signature.patterns {
+			let (bytes, masks) = pattern.into_parts();
+			let Ok(id) = tree.try_add_signature(bytes, Some(masks), signature.name.clone()) else {
+				continue
+			};
+			let mut provenance = Provenance::new(ImportFormat::ClamAv, signature.text.clone()).line(signature.line);
+			if let Some(path) = path {
+				provenance = provenance.path(path);
+			}
+			tree.set_provenance(id, provenance);
+			added += 1;
+		}
+	}
+	added
+}
+
+/// Parse an `.ndb` database and add the signatures it expands into to `tree`, with the signature
+/// names as the objects. Each signature records its line, along with `path` if the database was
+/// read from a file, as its provenance. Nothing is added unless the whole database parses. Returns
+/// the number of signatures added; duplicates and signatures the tree rejects are skipped.
+pub fn load_ndb<S>(tree: &mut SignatureDecisionTree<String, S>, source: &str, path: Option<&Path>) -> Result<usize, ClamAvError>
+	where S: BuildHasher {
+	Ok(load(tree, parse_ndb(source)?, path))
+}
+
+/// Parse an `.ldb` database and add every subsignature to `tree`, with the names of the logical
+/// signatures as the objects, as `load_ndb()` does.
+pub fn load_ldb<S>(tree: &mut SignatureDecisionTree<String, S>, source: &str, path: Option<&Path>) -> Result<usize, ClamAvError>
+	where S: BuildHasher {
+	Ok(load(tree, parse_ldb(source)?, path))
+}
+
+/// Parse an `.ndb` database and add the signatures of every line that can be represented to
+/// `tree`, as `load_ndb()` does, rather than rejecting the whole database over one line. Returns
+/// the number of signatures added, along with the errors for the lines left out.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::formats::clamav::load_ndb_lenient;
+///
+/// let mut tree = SignatureDecisionTree::new();
+/// let (added, errors) = load_ndb_lenient(&mut tree, "A:0:*:4d5a90\nB:0:*:4d5a*c3\n", None);
+/// assert_eq!((added, errors.len()), (1, 1));
+/// ```
+pub fn load_ndb_lenient<S>(tree: &mut SignatureDecisionTree<String, S>, source: &str, path: Option<&Path>) -> (usize, Vec<ClamAvError>)
+	where S: BuildHasher {
+	let (signatures, errors) = parse_ndb_lenient(source);
+	(load(tree, signatures, path), errors)
+}
+
+/// Parse an `.ldb` database and add every subsignature of the logical signatures that can be
+/// represented to `tree`, as `load_ndb_lenient()` does.
+pub fn load_ldb_lenient<S>(tree: &mut SignatureDecisionTree<String, S>, source: &str, path: Option<&Path>) -> (usize, Vec<ClamAvError>)
+	where S: BuildHasher {
+	let (signatures, errors) = parse_ldb_lenient(source);
+	(load(tree, signatures, path), errors)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+	use super::{load_ldb, load_ldb_lenient, load_ndb, load_ndb_lenient, parse_ldb, parse_ndb, ClamAvError};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_parse_ndb() {
+		let signatures = parse_ndb("A:0:*:4d5a(9?|?0){2-2}c3:18:255").unwrap();
+		let parts = signatures[0].patterns.iter().cloned().map(|pattern| pattern.into_parts()).collect::<Vec<_>>();
+		assert_eq!(parts, vec![
+			(vec![0x4d, 0x5a, 0x90, 0x00, 0x00, 0xc3], vec![0xff, 0xff, 0xf0, 0x00, 0x00, 0xff]),
+			(vec![0x4d, 0x5a, 0x00, 0x00, 0x00, 0xc3], vec![0xff, 0xff, 0x0f, 0x00, 0x00, 0xff])
+		]);
+		let unsupported = |position: usize, construct: &str| Err(ClamAvError::Unsupported { line: 1, position, construct: construct.to_string() });
+		assert_eq!(parse_ndb("A:0:*:4d5a*c3"), unsupported(4, "*"));
+		assert_eq!(parse_ndb("A:0:*:4d5a{2-4}c3"), unsupported(4, "{2-4}"));
+		assert_eq!(parse_ndb("A:0:*:4d5a{-4}c3"), unsupported(4, "{-4}"));
+		assert_eq!(parse_ndb("A:0:*:4d5a!(90|c3)"), unsupported(4, "!("));
+		assert_eq!(parse_ndb("A:0:*:4d[1-2]5a"), unsupported(2, "[1-2]"));
+		assert_eq!(parse_ndb("A:0:*:4d5a(B)"), unsupported(4, "(B)"));
+		assert_eq!(parse_ndb("\n# comment\nA:0:*:4d5x"), Err(ClamAvError::InvalidCharacter { line: 3, position: 3, character: 'x' }));
+		assert_eq!(parse_ndb("A:0:*:4d5"), Err(ClamAvError::UnexpectedEnd { line: 1 }));
+		assert_eq!(parse_ndb("A:0:*:4d(5a|90"), Err(ClamAvError::UnexpectedEnd { line: 1 }));
+		assert_eq!(parse_ndb("A:0:*"), Err(ClamAvError::MissingFields { line: 1 }));
+		assert_eq!(parse_ndb("A:0:*:"), Err(ClamAvError::EmptySignature { line: 1 }));
+		assert_eq!(parse_ndb("A:0:*:4d{65535}5a").map(|signatures| signatures[0].patterns[0].len()), Ok(65_537));
+		assert_eq!(parse_ndb("A:0:*:4d{65536}5a"), Err(ClamAvError::SkipTooLong { line: 1, position: 2 }));
+		assert_eq!(parse_ndb("A:0:*:4d5a{4000000000-4000000000}"), Err(ClamAvError::SkipTooLong { line: 1, position: 4 }));
+		assert_eq!(parse_ndb("A:0:*:4d{99999999999999999999999}5a"), Err(ClamAvError::SkipTooLong { line: 1, position: 2 }));
+	}
+
+	#[test]
+	fn test_load_databases() {
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(load_ndb(&mut tree, "A:0:*:4d5a90\nB:0:*:4d5a90\n", Some(Path::new("main.ndb"))), Ok(1));
+		let found = tree.get_match([0x4d, 0x5a, 0x90], None).unwrap();
+		assert_eq!(found.object, "A");
+		assert_eq!(tree.provenance(found.id).unwrap().to_string(), "main.ndb:1 (ClamAV): A:0:*:4d5a90");
+		assert_eq!(load_ldb(&mut tree, "L;Target:1;0|1;7f454c46;0:cafebabe", None), Ok(2));
+		assert_eq!(tree.get_signature([0xca, 0xfe, 0xba, 0xbe], None), Some("L".to_string()));
+		assert_eq!(parse_ldb("L;Target:1;0;4d5a::i"), Err(ClamAvError::Unsupported { line: 1, position: 4, construct: "::i".to_string() }));
+		assert_eq!(parse_ldb("L;Target:1;0;0/abc/"), Err(ClamAvError::Unsupported { line: 1, position: 1, construct: "0/abc/".to_string() }));
+		assert_eq!(parse_ldb("L;Target:1;0"), Err(ClamAvError::MissingFields { line: 1 }));
+	}
+
+	#[test]
+	fn test_load_databases_lenient() {
+		let mut tree = SignatureDecisionTree::new();
+		let database = "A:0:*:4d5a90\nB:0:*:4d5a*c3\nC:0:*:4d5a{2-4}c3\nD:0:*:c3\n";
+		assert_eq!(load_ndb(&mut tree, database, None), Err(ClamAvError::Unsupported { line: 2, position: 4, construct: "*".to_string() }));
+		assert!(tree.is_empty());
+		let (added, errors) = load_ndb_lenient(&mut tree, database, None);
+		assert_eq!(added, 2);
+		assert_eq!(errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(), vec![
+			"unsupported construct \"*\" at position 4 on line 2",
+			"unsupported construct \"{2-4}\" at position 4 on line 3"
+		]);
+		assert_eq!(tree.get_signature([0xc3], None), Some("D".to_string()));
+		let (added, errors) = load_ldb_lenient(&mut tree, "L;Target:1;0&1;7f454c46;4d5a::i\nM;Target:1;0;cafebabe", None);
+		assert_eq!((added, errors.len()), (1, 1));
+		assert_eq!(tree.get_signature([0x7f, 0x45, 0x4c, 0x46], None), None);
+	}
+}
@@ -0,0 +1,275 @@
+//! Import of IDA FLIRT pattern files (`.pat`).
+//!
+//! Each line of a pattern file describes one library function:
+//!
+//! ```text
+//! 558BEC83EC..5356578B7D08........................................ 1F 4A1B 003A :0000 _parse ^0012 _helper 8B45..C9C3
+//! ```
+//!
+//! The first field holds up to 32 leading bytes of the function, with `..` for bytes that vary
+//! (such as relocated addresses). It is followed by the length and CRC16 of the bytes checked after
+//! those, the size of the function, its public names (`:offset name`), the names it references
+//! (`^offset name`) and finally the tail bytes that follow the CRC-checked bytes. The file ends
+//! with a `---` line.
+//!
+//! Each line becomes a signature named after its first public name. The CRC can't be checked by
+//! the tree, so the CRC-checked bytes become wildcards, and the tail bytes, if any, are matched
+//! after them. The first field is always padded to 32 bytes with `..`, so for a function shorter
+//! than that, the padding past the end of the function is dropped.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::formats::flirt::load_pat;
+//!
+//! let patterns = "558BEC83EC..53 00 0000 0010 :0000 _start\n---\n";
+//! let mut tree = SignatureDecisionTree::new();
+//! assert_eq!(load_pat(&mut tree, patterns, None), Ok(1));
+//! assert_eq!(tree.get_signature([0x55, 0x8b, 0xec, 0x83, 0xec, 0x10, 0x53], None), Some("_start".to_string()));
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::pattern::{Pattern, PatternBuilder};
+use crate::provenance::{ImportFormat, Provenance};
+use crate::SignatureDecisionTree;
+
+/// The most leading bytes the first field of a pattern line holds.
+pub const MAX_LEADING_BYTES: usize = 32;
+
+/// Represents the ways a pattern file can fail to import. Lines are counted from 1, and positions
+/// are character indices into the line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlirtError {
+	/// A line without the leading bytes, CRC, size and a public name.
+	MissingFields {
+		line: usize
+	},
+	/// A character that isn't a hex digit or `.` where bytes are expected.
+	InvalidCharacter {
+		line: usize,
+		position: usize,
+		character: char
+	},
+	/// A byte field with an odd number of characters, starting at `position`.
+	IncompleteByte {
+		line: usize,
+		position: usize
+	},
+	/// A leading bytes field with more than `MAX_LEADING_BYTES` bytes.
+	TooManyLeadingBytes {
+		line: usize
+	},
+	/// A CRC length, CRC, size or name offset that isn't a hex number, starting at `position`.
+	InvalidNumber {
+		line: usize,
+		position: usize
+	}
+}
+
+impl Display for FlirtError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			FlirtError::MissingFields { line } => write!(f, "pattern on line {line} is missing fields"),
+			FlirtError::InvalidCharacter { line, position, character } => write!(f, "invalid character {character:?} at position {position} on line {line}"),
+			FlirtError::IncompleteByte { line, position } => write!(f, "incomplete byte in the field at position {position} on line {line}"),
+			FlirtError::TooManyLeadingBytes { line } => write!(f, "pattern on line {line} has more than {MAX_LEADING_BYTES} leading bytes"),
+			FlirtError::InvalidNumber { line, position } => write!(f, "invalid number at position {position} on line {line}")
+		}
+	}
+}
+
+impl std::error::Error for FlirtError {}
+
+/// Represents a function described by a line of a pattern file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlirtPattern {
+	/// The public names of the function and their offsets into it, the first of which names the
+	/// signature.
+	pub names: Vec<(u32, String)>,
+	/// The names the function references and the offsets of the references.
+	pub references: Vec<(u32, String)>,
+	/// The number of bytes after the leading bytes covered by the CRC.
+	pub crc_length: u8,
+	/// The CRC16 of those bytes.
+	pub crc: u16,
+	/// The size of the function in bytes.
+	pub size: u32,
+	/// The line the pattern is on.
+	pub line: usize,
+	/// The whole line, as written.
+	pub text: String,
+	/// The signature the line describes: the leading bytes, wildcards for the CRC-checked bytes
+	/// and the tail bytes.
+	pub pattern: Pattern
+}
+
+/// Parse every line of a pattern file, up to its `---` line.
+/// ```rust
+/// use dectree_rs::formats::flirt::parse_pat;
+///
+/// let patterns = parse_pat("E8........C3 02 1234 000A :0000 _stub ^0001 _target 90\n---\n").unwrap();
+/// assert_eq!(patterns[0].names, vec![(0, "_stub".to_string())]);
+/// // The tail byte follows the 32 leading bytes and the 2 CRC-checked bytes.
+/// assert_eq!(patterns[0].pattern.len(), 35);
+/// assert_eq!(patterns[0].pattern.bytes()[34], 0x90);
+/// ```
+pub fn parse_pat(source: &str) -> Result<Vec<FlirtPattern>, FlirtError> {
+	let mut patterns = Vec::new();
+	for (index, text) in source.lines().enumerate() {
+		let (line, text) = (index + 1, text.trim_end());
+		if text.trim_start() == "---" {
+			break
+		}
+		if text.trim_start().is_empty() {
+			continue
+		}
+		patterns.push(parse_line(text, line)?);
+	}
+	Ok(patterns)
+}
+
+/// Parse a line of a pattern file.
+fn parse_line(text: &str, line: usize) -> Result<FlirtPattern, FlirtError> {
+	// Fields are addressed by where they start in the line, for error positions.
+	let fields = text.split_whitespace()
+		.map(|field| (field.as_ptr() as usize - text.as_ptr() as usize, field))
+		.collect::<Vec<_>>();
+	let [leading, crc_length, crc, size, ref rest @ ..] = fields[..] else {
+		return Err(FlirtError::MissingFields { line })
+	};
+	let number = |(position, field): (usize, &str)| u32::from_str_radix(field, 16).map_err(|_| FlirtError::InvalidNumber { line, position });
+	let (mut builder, leading_length) = parse_bytes(leading, line, PatternBuilder::new())?;
+	if leading_length > MAX_LEADING_BYTES {
+		return Err(FlirtError::TooManyLeadingBytes { line })
+	}
+	let crc_length = u8::try_from(number(crc_length)?).map_err(|_| FlirtError::InvalidNumber { line, position: crc_length.0 })?;
+	let crc = u16::try_from(number(crc)?).map_err(|_| FlirtError::InvalidNumber { line, position: crc.0 })?;
+	let size = number(size)?;
+	let (mut names, mut references, mut tail) = (Vec::new(), Vec::new(), None);
+	let mut rest = rest.iter();
+	while let Some(&(position, field)) = rest.next() {
+		let (list, offset) = match (field.strip_prefix(':'), field.strip_prefix('^')) {
+			(Some(offset), _) => (&mut names, offset.trim_end_matches('@')),
+			(_, Some(offset)) => (&mut references, offset),
+			_ => {
+				tail = Some((position, field));
+				break
+			}
+		};
+		let offset = number((position + 1, offset))?;
+		let &(_, name) = rest.next().ok_or(FlirtError::MissingFields { line })?;
+		list.push((offset, name.to_string()));
+	}
+	if names.is_empty() {
+		return Err(FlirtError::MissingFields { line })
+	}
+	match tail {
+		// The tail follows the CRC-checked bytes, which follow the full 32 leading bytes.
+		Some(tail) => (builder, _) = parse_bytes(tail, line, builder.pad_to(MAX_LEADING_BYTES + crc_length as usize))?,
+		// Leading bytes past the end of the function are only padding.
+		None if size > 0 && (size as usize) < leading_length => {
+			(builder, _) = parse_bytes((leading.0, &leading.1[..size as usize * 2]), line, PatternBuilder::new())?;
+		},
+		None => {}
+	}
+	let pattern = builder.build().map_err(|_| FlirtError::MissingFields { line })?;
+	Ok(FlirtPattern { names, references, crc_length, crc, size, line, text: text.to_string(), pattern })
+}
+
+/// Append the bytes of a field written as hex digits and `..` wildcards, found at `position` in
+/// the line, to `builder`. Returns the builder along with the number of bytes in the field.
+fn parse_bytes(field: (usize, &str), line: usize, mut builder: PatternBuilder) -> Result<(PatternBuilder, usize), FlirtError> {
+	let (position, field) = field;
+	if field.len() % 2 != 0 {
+		return Err(FlirtError::IncompleteByte { line, position })
+	}
+	for (index, pair) in field.as_bytes().chunks(2).enumerate() {
+		let byte = match pair {
+			b".." => None,
+			_ => {
+				let invalid = pair.iter().position(|digit| !digit.is_ascii_hexdigit()).map(|offset| index * 2 + offset);
+				if let Some(offset) = invalid {
+					let character = field[offset..].chars().next().unwrap_or_default();
+					return Err(FlirtError::InvalidCharacter { line, position: position + offset, character })
+				}
+				u8::from_str_radix(std::str::from_utf8(pair).unwrap_or_default(), 16).ok()
+			}
+		};
+		builder = match byte {
+			Some(byte) => builder.byte(byte),
+			None => builder.wildcard(1)
+		};
+	}
+	Ok((builder, field.len() / 2))
+}
+
+/// Parse a pattern file and add the signature of every function in it to `tree`, named after the
+/// function's first public name. Each signature records its line, along with `path` if the
+/// patterns were read from a file, as its provenance. Nothing is added unless the whole file
+/// parses. Returns the number of signatures added; duplicates (common across library versions)
+/// and signatures the tree rejects are skipped.
+pub fn load_pat<S>(tree: &mut SignatureDecisionTree<String, S>, source: &str, path: Option<&Path>) -> Result<usize, FlirtError>
+	where S: BuildHasher {
+	let mut added = 0;
+	for pattern in parse_pat(source)? {
+		let name = pattern.names[0].1.clone();
+		let (bytes, masks) = pattern.pattern.into_parts();
+		let Ok(id) = tree.try_add_signature(bytes, Some(masks), name) else {
+			continue
+		};
+		let mut provenance = Provenance::new(ImportFormat::Flirt, pattern.text).line(pattern.line);
+		if let Some(path) = path {
+			provenance = provenance.path(path);
+		}
+		tree.set_provenance(id, provenance);
+		added += 1;
+	}
+	Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{load_pat, parse_pat, FlirtError};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_parse_pat() {
+		let leading = "558BEC".to_string() + &"..".repeat(29);
+		let patterns = parse_pat(&format!("{leading} 04 ABCD 0040 :0000 _a :0010@ _a_inner ^0004 _b C3\n---\nignored")).unwrap();
+		assert_eq!(patterns.len(), 1);
+		assert_eq!((patterns[0].crc_length, patterns[0].crc, patterns[0].size), (4, 0xabcd, 0x40));
+		assert_eq!(patterns[0].names, vec![(0, "_a".to_string()), (0x10, "_a_inner".to_string())]);
+		assert_eq!(patterns[0].references, vec![(4, "_b".to_string())]);
+		assert_eq!(patterns[0].pattern.len(), 37);
+		assert_eq!(patterns[0].pattern.bytes()[36], 0xc3);
+		assert_eq!(parse_pat("558B 00 0000 0002"), Err(FlirtError::MissingFields { line: 1 }));
+		assert_eq!(parse_pat("\n558X 00 0000 0002 :0000 _a"), Err(FlirtError::InvalidCharacter { line: 2, position: 3, character: 'X' }));
+		assert_eq!(parse_pat("558 00 0000 0002 :0000 _a"), Err(FlirtError::IncompleteByte { line: 1, position: 0 }));
+		assert_eq!(parse_pat("558B 00 00G0 0002 :0000 _a"), Err(FlirtError::InvalidNumber { line: 1, position: 8 }));
+		assert_eq!(parse_pat(&format!("{}{} 00 0000 0002 :0000 _a", leading, "90")), Err(FlirtError::TooManyLeadingBytes { line: 1 }));
+	}
+
+	#[test]
+	fn test_load_pat() {
+		let patterns = "5589E5..C3 00 0000 0005 :0000 _one\n5589E5..C3 00 0000 0005 :0000 _duplicate\n31C0C3 00 0000 0003 :0000 _zero\n---\n";
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(load_pat(&mut tree, patterns, None), Ok(2));
+		assert_eq!(tree.get_signature([0x55, 0x89, 0xe5, 0x42, 0xc3], None), Some("_one".to_string()));
+		let found = tree.get_match([0x31, 0xc0, 0xc3], None).unwrap();
+		assert_eq!(tree.provenance(found.id).and_then(|provenance| provenance.line), Some(3));
+	}
+
+	#[test]
+	fn test_parse_pat_padded() {
+		// As sigmake writes it, with the first field padded to 64 characters.
+		let text = format!("{:.<64} 00 0000 0005 :0000 _short", "5589E5..C3");
+		assert_eq!(text.split(' ').next().map(str::len), Some(64));
+		let patterns = parse_pat(&format!("{text}\n---\n")).unwrap();
+		assert_eq!(patterns[0].pattern.bytes(), &[0x55, 0x89, 0xe5, 0x00, 0xc3]);
+		assert_eq!(patterns[0].pattern.masks(), &[0xff, 0xff, 0xff, 0x00, 0xff]);
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(load_pat(&mut tree, &text, None), Ok(1));
+		assert_eq!(tree.scan([0x90, 0x55, 0x89, 0xe5, 0x42, 0xc3]), vec![(1, "_short".to_string())]);
+	}
+}
@@ -0,0 +1,231 @@
+//! Import of PEiD packer signatures, from `userdb.txt` databases.
+//!
+//! Each entry of a database is a section named after the packer it detects:
+//!
+//! ```text
+//! [UPX 2.90 -> Markus Oberhumer]
+//! signature = 60 BE ?? ?? ?? ?? 8D BE ?? ?? ?? ?? 57 83 CD FF
+//! ep_only = true
+//! ```
+//!
+//! The signature is a hex string with `??` for wildcard bytes, and `ep_only` tells whether it
+//! should only be matched at the entry point of an executable. It defaults to `false` when left
+//! out. Keys are case-insensitive, and blank lines and lines starting with `;` are skipped.
+//!
+//! Every entry becomes a signature whose object is a `Packer`, holding the name and the `ep_only`
+//! flag, so a packer detector can check where a match was found before reporting it.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::formats::peid::{load_userdb, Packer};
+//!
+//! let database = "; Sample\n[Example Packer 1.0]\nsignature = 60 E8 ?? ?? ?? ?? 5D\nep_only = true\n";
+//! let mut tree = SignatureDecisionTree::new();
+//! assert_eq!(load_userdb(&mut tree, database, None), Ok(1));
+//! let packer = tree.get_signature([0x60, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x5d], None).unwrap();
+//! assert_eq!(packer, Packer { name: "Example Packer 1.0".to_string(), ep_only: true });
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::parser::{self, ParseError};
+use crate::pattern::{Pattern, PatternBuilder};
+use crate::provenance::{ImportFormat, Provenance};
+use crate::SignatureDecisionTree;
+
+/// Represents the ways a PEiD database can fail to import. Lines are counted from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeidError {
+	/// A line that is neither a section header nor a `key = value` pair, or a pair outside any
+	/// section.
+	UnexpectedLine {
+		line: usize
+	},
+	/// The section starting on `line` has no signature.
+	MissingSignature {
+		line: usize
+	},
+	/// The signature on `line` isn't a valid hex string.
+	InvalidSignature {
+		line: usize,
+		error: ParseError
+	},
+	/// The `ep_only` value on `line` is neither `true` nor `false`.
+	InvalidEpOnly {
+		line: usize
+	}
+}
+
+impl Display for PeidError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PeidError::UnexpectedLine { line } => write!(f, "unexpected line {line}"),
+			PeidError::MissingSignature { line } => write!(f, "entry on line {line} has no signature"),
+			PeidError::InvalidSignature { line, error } => write!(f, "invalid signature on line {line}: {error}"),
+			PeidError::InvalidEpOnly { line } => write!(f, "invalid ep_only value on line {line}")
+		}
+	}
+}
+
+impl std::error::Error for PeidError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			PeidError::InvalidSignature { error, .. } => Some(error),
+			_ => None
+		}
+	}
+}
+
+/// Represents the packer a PEiD signature detects, as stored in the tree.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Packer {
+	/// The name of the packer, as written in the section header.
+	pub name: String,
+	/// Whether the signature should only be matched at the entry point.
+	pub ep_only: bool
+}
+
+impl Display for Packer {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.name)
+	}
+}
+
+/// Represents an entry of a PEiD database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeidSignature {
+	/// The packer the entry detects.
+	pub packer: Packer,
+	/// The line the entry's section header is on.
+	pub line: usize,
+	/// The whole entry, from its section header to its last key, as written.
+	pub text: String,
+	/// The signature of the entry.
+	pub pattern: Pattern
+}
+
+/// Represents an entry while its section is being read.
+struct Section<'a> {
+	name: &'a str,
+	line: usize,
+	lines: Vec<&'a str>,
+	signature: Option<(usize, &'a str)>,
+	ep_only: bool
+}
+
+impl Section<'_> {
+	/// Finish the entry, now that its section has ended.
+	fn finish(self) -> Result<PeidSignature, PeidError> {
+		let (line, signature) = self.signature.ok_or(PeidError::MissingSignature { line: self.line })?;
+		let (bytes, masks) = parser::parse_hex_pattern(signature).map_err(|error| PeidError::InvalidSignature { line, error })?;
+		let pattern = bytes.iter().zip(masks)
+			.fold(PatternBuilder::new(), |builder, (&byte, mask)| builder.masked(byte, mask))
+			.build()
+			.map_err(|_| PeidError::InvalidSignature { line, error: ParseError::Empty })?;
+		Ok(PeidSignature {
+			packer: Packer { name: self.name.to_string(), ep_only: self.ep_only },
+			line: self.line,
+			text: self.lines.join("\n"),
+			pattern
+		})
+	}
+}
+
+/// Parse every entry of a PEiD database.
+/// ```rust
+/// use dectree_rs::formats::peid::parse_userdb;
+///
+/// let entries = parse_userdb("[Stub]\nSignature = EB ?? 90\n").unwrap();
+/// assert_eq!(entries[0].packer.name, "Stub");
+/// assert!(!entries[0].packer.ep_only);
+/// assert_eq!(entries[0].pattern.masks(), &[0xff, 0x00, 0xff]);
+/// ```
+pub fn parse_userdb(source: &str) -> Result<Vec<PeidSignature>, PeidError> {
+	let (mut entries, mut section) = (Vec::new(), None::<Section>);
+	for (index, text) in source.lines().enumerate() {
+		let (line, text) = (index + 1, text.trim());
+		if text.is_empty() || text.starts_with(';') {
+			continue
+		}
+		if let Some(name) = text.strip_prefix('[').and_then(|text| text.strip_suffix(']')) {
+			if let Some(section) = section.replace(Section { name, line, lines: vec![text], signature: None, ep_only: false }) {
+				entries.push(section.finish()?);
+			}
+			continue
+		}
+		let (Some((key, value)), Some(section)) = (text.split_once('='), section.as_mut()) else {
+			return Err(PeidError::UnexpectedLine { line })
+		};
+		let value = value.trim();
+		match key.trim().to_ascii_lowercase().as_str() {
+			"signature" => section.signature = Some((line, value)),
+			"ep_only" => {
+				section.ep_only = match value.to_ascii_lowercase().as_str() {
+					"true" => true,
+					"false" => false,
+					_ => return Err(PeidError::InvalidEpOnly { line })
+				};
+			},
+			_ => return Err(PeidError::UnexpectedLine { line })
+		}
+		section.lines.push(text);
+	}
+	if let Some(section) = section {
+		entries.push(section.finish()?);
+	}
+	Ok(entries)
+}
+
+/// Parse a PEiD database and add the signature of every entry in it to `tree`. Each signature
+/// records its entry, along with `path` if the database was read from a file, as its provenance.
+/// Nothing is added unless the whole database parses. Returns the number of signatures added;
+/// duplicates (common in merged databases) and signatures the tree rejects are skipped.
+pub fn load_userdb<S>(tree: &mut SignatureDecisionTree<Packer, S>, source: &str, path: Option<&Path>) -> Result<usize, PeidError>
+	where S: BuildHasher {
+	let mut added = 0;
+	for entry in parse_userdb(source)? {
+		let (bytes, masks) = entry.pattern.into_parts();
+		let Ok(id) = tree.try_add_signature(bytes, Some(masks), entry.packer) else {
+			continue
+		};
+		let mut provenance = Provenance::new(ImportFormat::Peid, entry.text).line(entry.line);
+		if let Some(path) = path {
+			provenance = provenance.path(path);
+		}
+		tree.set_provenance(id, provenance);
+		added += 1;
+	}
+	Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{load_userdb, parse_userdb, PeidError};
+	use crate::parser::ParseError;
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_parse_userdb() {
+		let entries = parse_userdb("[A]\nsignature = 55 8B EC\nep_only = TRUE\n\n; B\n[B]\nsignature = 6A ??\n").unwrap();
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[0].text, "[A]\nsignature = 55 8B EC\nep_only = TRUE");
+		assert!(entries[0].packer.ep_only);
+		assert_eq!((entries[1].line, entries[1].pattern.bytes()), (6, &[0x6a, 0x00][..]));
+		assert_eq!(parse_userdb("signature = 55"), Err(PeidError::UnexpectedLine { line: 1 }));
+		assert_eq!(parse_userdb("[A]\n[B]\nsignature = 55"), Err(PeidError::MissingSignature { line: 1 }));
+		assert_eq!(parse_userdb("[A]\nsignature = 5G"), Err(PeidError::InvalidSignature { line: 2, error: ParseError::InvalidCharacter { position: 1, character: 'G' } }));
+		assert_eq!(parse_userdb("[A]\nsignature = 55\nep_only = maybe"), Err(PeidError::InvalidEpOnly { line: 3 }));
+	}
+
+	#[test]
+	fn test_load_userdb() {
+		let database = "[A]\nsignature = 55 8B EC\n[A copy]\nsignature = 55 8B EC\n[B]\nsignature = C3\nep_only = false\n";
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(load_userdb(&mut tree, database, None), Ok(2));
+		let found = tree.get_match([0xc3], None).unwrap();
+		assert_eq!(found.object.name, "B");
+		assert_eq!(tree.provenance(found.id).map(|provenance| provenance.text.as_str()), Some("[B]\nsignature = C3\nep_only = false"));
+	}
+}
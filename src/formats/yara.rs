@@ -0,0 +1,550 @@
+//! Import of the hex strings of YARA rules.
+//!
+//! Only hex strings (`$a = { 55 8B ?? EC }`) are imported; text strings, regular expressions,
+//! metadata and conditions are skipped. Within a hex string the following are understood:
+//!
+//! - bytes written as two hex digits, and `??` for a wildcard byte,
+//! - nibble wildcards such as `5?` and `?5`, which match on the other half of the byte only,
+//! - fixed-length jumps such as `[4]` (or `[4-4]`) of up to `MAX_JUMP` bytes, which become that
+//!   many wildcard bytes,
+//! - alternation such as `( 8B EC | 89 E5 )`, possibly nested, which is expanded into one
+//!   signature per alternative.
+//!
+//! Jumps of varying length (`[2-4]`, `[-]`) can't be expressed as a fixed-length signature and are
+//! rejected, as is the `~` operator.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::formats::yara::load_rules;
+//!
+//! let rules = r#"
+//! rule Prologue : x86 {
+//!     meta:
+//!         author = "someone"
+//!     strings:
+//!         $frame = { 55 ( 8B EC | 89 E5 ) 8? EC }
+//!         $text = "prologue"
+//!     condition:
+//!         $frame
+//! }
+//! "#;
+//! let mut tree = SignatureDecisionTree::new();
+//! assert_eq!(load_rules(&mut tree, rules, None), Ok(2));
+//! assert_eq!(tree.get_signature([0x55, 0x89, 0xe5, 0x83, 0xec], None), Some("Prologue".to_string()));
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::pattern::Pattern;
+use crate::provenance::{ImportFormat, Provenance};
+use crate::SignatureDecisionTree;
+use super::{expand_patterns, ExpandError, Token, MAX_ALTERNATIVES, MAX_JUMP};
+
+/// Represents the ways YARA rules can fail to import. Lines are counted from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum YaraError {
+	/// The input ends in the middle of a construct that starts on `line`.
+	UnexpectedEnd {
+		line: usize
+	},
+	/// A character that doesn't fit the syntax at that point, on `line`.
+	UnexpectedCharacter {
+		line: usize,
+		character: char
+	},
+	/// A jump on `line` whose length varies, such as `[2-4]`. Only fixed-length signatures can be
+	/// stored in the tree.
+	VariableJump {
+		line: usize
+	},
+	/// A fixed-length jump on `line` longer than `MAX_JUMP` bytes.
+	JumpTooLong {
+		line: usize
+	},
+	/// A hex string starting on `line` that contains no bytes.
+	EmptyHexString {
+		line: usize
+	},
+	/// A hex string starting on `line` whose alternations expand into more than
+	/// `MAX_ALTERNATIVES` signatures.
+	TooManyAlternatives {
+		line: usize
+	}
+}
+
+impl Display for YaraError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			YaraError::UnexpectedEnd { line } => write!(f, "unexpected end of input in the construct starting on line {line}"),
+			YaraError::UnexpectedCharacter { line, character } => write!(f, "unexpected character {character:?} on line {line}"),
+			YaraError::VariableJump { line } => write!(f, "jump of varying length on line {line}"),
+			YaraError::JumpTooLong { line } => write!(f, "jump on line {line} is longer than {MAX_JUMP} bytes"),
+			YaraError::EmptyHexString { line } => write!(f, "hex string on line {line} is empty"),
+			YaraError::TooManyAlternatives { line } => write!(f, "hex string on line {line} expands into more than {MAX_ALTERNATIVES} signatures")
+		}
+	}
+}
+
+impl std::error::Error for YaraError {}
+
+/// Represents a YARA rule, with its hex strings parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct YaraRule {
+	/// The name of the rule.
+	pub name: String,
+	/// The line the rule starts on.
+	pub line: usize,
+	/// The whole rule as written, including any `private` or `global` modifiers.
+	pub text: String,
+	/// The hex strings of the rule, in the order they are defined.
+	pub hex_strings: Vec<HexString>,
+	/// The text strings and regular expressions of the rule, which aren't imported, as written
+	/// up to their closing delimiter, such as `$a = "text"`.
+	pub other_strings: Vec<String>,
+	/// The condition of the rule as written, without the `condition:` keyword, or an empty
+	/// string if it has none.
+	pub condition: String
+}
+
+/// Represents a hex string defined in a YARA rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HexString {
+	/// The identifier of the string, including its `$`.
+	pub identifier: String,
+	/// The line the definition starts on.
+	pub line: usize,
+	/// The definition as written, such as `$a = { 55 8B ?? EC }`.
+	pub text: String,
+	/// The signatures the hex string expands into, one per combination of alternatives.
+	pub patterns: Vec<Pattern>
+}
+
+/// Represents a position in the input, tracking the line it is on.
+struct Cursor<'a> {
+	source: &'a str,
+	position: usize,
+	line: usize
+}
+
+impl<'a> Cursor<'a> {
+	fn new(source: &'a str) -> Self {
+		Cursor {
+			source,
+			position: 0,
+			line: 1
+		}
+	}
+
+	fn peek(&self) -> Option<u8> {
+		self.source.as_bytes().get(self.position).copied()
+	}
+
+	fn peek_second(&self) -> Option<u8> {
+		self.source.as_bytes().get(self.position + 1).copied()
+	}
+
+	fn bump(&mut self) -> Option<u8> {
+		let byte = self.peek()?;
+		self.position += 1;
+		if byte == b'\n' {
+			self.line += 1;
+		}
+		Some(byte)
+	}
+
+	/// Describe the character at the cursor as an error.
+	fn unexpected(&self, start_line: usize) -> YaraError {
+		match self.source[self.position..].chars().next() {
+			Some(character) => YaraError::UnexpectedCharacter { line: self.line, character },
+			None => YaraError::UnexpectedEnd { line: start_line }
+		}
+	}
+
+	/// Skip whitespace and comments.
+	fn skip_trivia(&mut self) -> Result<(), YaraError> {
+		loop {
+			match (self.peek(), self.peek_second()) {
+				(Some(byte), _) if byte.is_ascii_whitespace() => {
+					self.bump();
+				},
+				(Some(b'/'), Some(b'/')) => {
+					while self.peek().is_some_and(|byte| byte != b'\n') {
+						self.bump();
+					}
+				},
+				(Some(b'/'), Some(b'*')) => {
+					let line = self.line;
+					self.position += 2;
+					while !(self.peek() == Some(b'*') && self.peek_second() == Some(b'/')) {
+						self.bump().ok_or(YaraError::UnexpectedEnd { line })?;
+					}
+					self.position += 2;
+				},
+				_ => return Ok(())
+			}
+		}
+	}
+
+	/// Read an identifier, which may be empty.
+	fn identifier(&mut self) -> &'a str {
+		let start = self.position;
+		while self.peek().is_some_and(|byte| byte.is_ascii_alphanumeric() || byte == b'_') {
+			self.bump();
+		}
+		&self.source[start..self.position]
+	}
+
+	/// Skip a string or regular expression that starts at the cursor and ends with the same
+	/// delimiter, honouring backslash escapes.
+	fn skip_delimited(&mut self) -> Result<(), YaraError> {
+		let line = self.line;
+		let delimiter = self.bump();
+		loop {
+			match self.bump() {
+				None => return Err(YaraError::UnexpectedEnd { line }),
+				Some(b'\\') => {
+					self.bump();
+				},
+				byte if byte == delimiter => return Ok(()),
+				_ => {}
+			}
+		}
+	}
+}
+
+/// Parse every rule in `source`, keeping only their hex strings.
+/// ```rust
+/// use dectree_rs::formats::yara::parse_rules;
+///
+/// let rules = parse_rules("rule Ret { strings: $a = { C3 } $b = { C2 [2] } condition: any of them }").unwrap();
+/// assert_eq!(rules[0].name, "Ret");
+/// assert_eq!(rules[0].hex_strings[1].text, "$b = { C2 [2] }");
+/// assert_eq!(rules[0].hex_strings[1].patterns[0].masks(), &[0xff, 0x00, 0x00]);
+/// ```
+pub fn parse_rules(source: &str) -> Result<Vec<YaraRule>, YaraError> {
+	let mut cursor = Cursor::new(source);
+	let mut rules = Vec::new();
+	// Where the modifiers of the next rule start, if it has any.
+	let mut modifiers = None;
+	loop {
+		cursor.skip_trivia()?;
+		match cursor.peek() {
+			None => return Ok(rules),
+			Some(b'"') => cursor.skip_delimited()?,
+			Some(byte) if byte.is_ascii_alphanumeric() || byte == b'_' => {
+				let (start, line) = (cursor.position, cursor.line);
+				// `import` and `include` are skipped along with their arguments.
+				match cursor.identifier() {
+					"rule" => {
+						let (start, line) = modifiers.take().unwrap_or((start, line));
+						rules.push(parse_rule(&mut cursor, start, line)?);
+					},
+					"private" | "global" => {
+						modifiers.get_or_insert((start, line));
+					},
+					_ => modifiers = None
+				}
+			},
+			Some(_) => return Err(cursor.unexpected(cursor.line))
+		}
+	}
+}
+
+/// Parse the rest of a rule whose `rule` keyword was just read. The rule, with its modifiers,
+/// starts at `start`, on `line`.
+fn parse_rule(cursor: &mut Cursor, start: usize, line: usize) -> Result<YaraRule, YaraError> {
+	cursor.skip_trivia()?;
+	let name = cursor.identifier().to_string();
+	if name.is_empty() {
+		return Err(cursor.unexpected(line))
+	}
+	// Skip the tags, if any.
+	loop {
+		cursor.skip_trivia()?;
+		match cursor.peek() {
+			Some(b'{') => break,
+			Some(b':') => {
+				cursor.bump();
+			},
+			Some(byte) if byte.is_ascii_alphanumeric() || byte == b'_' => {
+				cursor.identifier();
+			},
+			_ => return Err(cursor.unexpected(line))
+		}
+	}
+	cursor.bump();
+	let (mut hex_strings, mut other_strings) = (Vec::new(), Vec::new());
+	// Where the condition starts, once its keyword has been read.
+	let mut condition = None;
+	loop {
+		cursor.skip_trivia()?;
+		match cursor.peek() {
+			None => return Err(YaraError::UnexpectedEnd { line }),
+			Some(b'}') => {
+				let condition = condition.map_or("", |condition| cursor.source[condition..cursor.position].trim()).to_string();
+				cursor.bump();
+				let text = cursor.source[start..cursor.position].to_string();
+				return Ok(YaraRule { name, line, text, hex_strings, other_strings, condition })
+			},
+			Some(byte) if byte.is_ascii_alphabetic() || byte == b'_' => {
+				if cursor.identifier() == "condition" {
+					cursor.skip_trivia()?;
+					if cursor.peek() == Some(b':') {
+						cursor.bump();
+						condition = Some(cursor.position);
+					}
+				}
+			},
+			Some(b'"') => cursor.skip_delimited()?,
+			Some(b'$') => {
+				let (start, string_line) = (cursor.position, cursor.line);
+				cursor.bump();
+				let identifier = format!("${}", cursor.identifier());
+				cursor.skip_trivia()?;
+				// Otherwise it is a reference to the string in the condition.
+				if cursor.peek() != Some(b'=') {
+					continue
+				}
+				cursor.bump();
+				cursor.skip_trivia()?;
+				match cursor.peek() {
+					Some(b'{') => {
+						cursor.bump();
+						let patterns = hex_string(cursor, string_line)?;
+						cursor.bump();
+						let text = cursor.source[start..cursor.position].to_string();
+						hex_strings.push(HexString { identifier, line: string_line, text, patterns });
+					},
+					Some(b'"' | b'/') => {
+						cursor.skip_delimited()?;
+						other_strings.push(cursor.source[start..cursor.position].to_string());
+					},
+					_ => return Err(cursor.unexpected(string_line))
+				}
+			},
+			Some(_) => {
+				cursor.bump();
+			}
+		}
+	}
+}
+
+/// Parse a hex string on its own, without the braces around it, into the signatures it expands
+/// into. Lines are counted from the start of `text`.
+/// ```rust
+/// use dectree_rs::formats::yara::parse_hex_string;
+///
+/// let patterns = parse_hex_string("4D 5A ( 90 | ?0 )").unwrap();
+/// assert_eq!(patterns.len(), 2);
+/// assert_eq!(patterns[1].bytes(), &[0x4d, 0x5a, 0x00]);
+/// assert_eq!(patterns[1].masks(), &[0xff, 0xff, 0x0f]);
+/// ```
+pub fn parse_hex_string(text: &str) -> Result<Vec<Pattern>, YaraError> {
+	let mut cursor = Cursor::new(text);
+	let patterns = hex_string(&mut cursor, 1)?;
+	match cursor.peek() {
+		None => Ok(patterns),
+		Some(_) => Err(cursor.unexpected(1))
+	}
+}
+
+/// Parse the hex string at the cursor, which starts on `line`, up to its closing brace, which is
+/// left in place.
+fn hex_string(cursor: &mut Cursor, line: usize) -> Result<Vec<Pattern>, YaraError> {
+	let tokens = hex_sequence(cursor, line)?;
+	if !matches!(cursor.peek(), None | Some(b'}')) {
+		return Err(cursor.unexpected(line))
+	}
+	expand_patterns(&tokens).map_err(|error| match error {
+		ExpandError::Empty => YaraError::EmptyHexString { line },
+		ExpandError::TooManyAlternatives => YaraError::TooManyAlternatives { line }
+	})
+}
+
+/// Parse pieces of a hex string until the end of the sequence they form: the end of an
+/// alternative, or of the whole string.
+fn hex_sequence(cursor: &mut Cursor, line: usize) -> Result<Vec<Token>, YaraError> {
+	let mut tokens = Vec::new();
+	loop {
+		cursor.skip_trivia()?;
+		match cursor.peek() {
+			None | Some(b'|' | b')' | b'}') => return Ok(tokens),
+			Some(b'(') => {
+				cursor.bump();
+				let mut alternatives = vec![hex_sequence(cursor, line)?];
+				loop {
+					match cursor.peek() {
+						Some(b'|') => {
+							cursor.bump();
+							alternatives.push(hex_sequence(cursor, line)?);
+						},
+						Some(b')') => {
+							cursor.bump();
+							break
+						},
+						_ => return Err(cursor.unexpected(line))
+					}
+				}
+				tokens.push(Token::Alternation(alternatives));
+			},
+			Some(b'[') => {
+				cursor.bump();
+				let length = jump(cursor, line)?;
+				tokens.extend((0..length).map(|_| Token::Byte { value: 0x00, mask: 0x00 }));
+			},
+			Some(_) => {
+				let (high, high_mask) = nibble(cursor, line)?;
+				let (low, low_mask) = nibble(cursor, line)?;
+				tokens.push(Token::Byte { value: high << 4 | low, mask: high_mask << 4 | low_mask });
+			}
+		}
+	}
+}
+
+/// Parse a hex digit or `?` as a nibble and the mask it is compared under.
+fn nibble(cursor: &mut Cursor, line: usize) -> Result<(u8, u8), YaraError> {
+	let nibble = match cursor.peek() {
+		Some(b'?') => (0x0, 0x0),
+		Some(byte) if byte.is_ascii_hexdigit() => ((byte as char).to_digit(16).unwrap_or_default() as u8, 0xf),
+		_ => return Err(cursor.unexpected(line))
+	};
+	cursor.bump();
+	Ok(nibble)
+}
+
+/// Parse the rest of a jump whose `[` was just read, returning its length.
+fn jump(cursor: &mut Cursor, line: usize) -> Result<usize, YaraError> {
+	// Either bound of a range may be left out, which makes its length vary.
+	let number = |cursor: &mut Cursor| {
+		cursor.skip_trivia()?;
+		let start = cursor.position;
+		while cursor.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+			cursor.bump();
+		}
+		// Numbers too large for a `usize` are still lengths, just too long ones.
+		let digits = &cursor.source[start..cursor.position];
+		let number = (!digits.is_empty()).then(|| digits.parse::<usize>().unwrap_or(usize::MAX));
+		cursor.skip_trivia()?;
+		Ok(number)
+	};
+	let start = number(cursor)?;
+	let end = match cursor.peek() {
+		Some(b'-') => {
+			cursor.bump();
+			number(cursor)?
+		},
+		_ => start
+	};
+	if cursor.peek() != Some(b']') {
+		return Err(cursor.unexpected(line))
+	}
+	match (start, end) {
+		(Some(start), Some(end)) if start == end && start > MAX_JUMP => Err(YaraError::JumpTooLong { line: cursor.line }),
+		(Some(start), Some(end)) if start == end => {
+			cursor.bump();
+			Ok(start)
+		},
+		_ => Err(YaraError::VariableJump { line: cursor.line })
+	}
+}
+
+/// Parse every rule in `source` and add the signatures their hex strings expand into to `tree`,
+/// with the name of the rule as the object. Each signature records the definition it came from,
+/// along with `path` if the rules were read from a file, as its provenance. Nothing is added
+/// unless the whole input parses. Returns the number of signatures added; duplicates and
+/// signatures the tree rejects (such as ones shorter than its `min_signature_length()`) are
+/// skipped.
+pub fn load_rules<S>(tree: &mut SignatureDecisionTree<String, S>, source: &str, path: Option<&Path>) -> Result<usize, YaraError>
+	where S: BuildHasher {
+	let mut added = 0;
+	for rule in parse_rules(source)? {
+		for hex_string in rule.hex_strings {
+			for pattern in hex_string.patterns {
+				let (bytes, masks) = pattern.into_parts();
+				let Ok(id) = tree.try_add_signature(bytes, Some(masks), rule.name.clone()) else {
+					continue
+				};
+				let mut provenance = Provenance::new(ImportFormat::Yara, hex_string.text.clone())
+					.line(hex_string.line)
+					.rule(rule.text.clone());
+				if let Some(path) = path {
+					provenance = provenance.path(path);
+				}
+				tree.set_provenance(id, provenance);
+				added += 1;
+			}
+		}
+	}
+	Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+	use super::{load_rules, parse_hex_string, parse_rules, YaraError};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_parse_hex_string() {
+		let patterns = parse_hex_string("E8 [4] ( 5? | ?3 ( C3 | C2 ) ) // trailing comment").unwrap();
+		let parts = patterns.into_iter().map(|pattern| pattern.into_parts()).collect::<Vec<_>>();
+		assert_eq!(parts, vec![
+			(vec![0xe8, 0x00, 0x00, 0x00, 0x00, 0x50], vec![0xff, 0x00, 0x00, 0x00, 0x00, 0xf0]),
+			(vec![0xe8, 0x00, 0x00, 0x00, 0x00, 0x03, 0xc3], vec![0xff, 0x00, 0x00, 0x00, 0x00, 0x0f, 0xff]),
+			(vec![0xe8, 0x00, 0x00, 0x00, 0x00, 0x03, 0xc2], vec![0xff, 0x00, 0x00, 0x00, 0x00, 0x0f, 0xff])
+		]);
+		assert_eq!(parse_hex_string("55 [2-2] 8B").unwrap()[0].len(), 4);
+		assert_eq!(parse_hex_string("55 [2-4] 8B"), Err(YaraError::VariableJump { line: 1 }));
+		assert_eq!(parse_hex_string("55\n[-] 8B"), Err(YaraError::VariableJump { line: 2 }));
+		assert_eq!(parse_hex_string("55 [65535] 8B").map(|patterns| patterns[0].len()), Ok(65_537));
+		assert_eq!(parse_hex_string("55 [65536] 8B"), Err(YaraError::JumpTooLong { line: 1 }));
+		assert_eq!(parse_hex_string("55 [4000000000-4000000000] 8B"), Err(YaraError::JumpTooLong { line: 1 }));
+		assert_eq!(parse_hex_string("55 [99999999999999999999999] 8B"), Err(YaraError::JumpTooLong { line: 1 }));
+		assert_eq!(parse_hex_string("55 8"), Err(YaraError::UnexpectedEnd { line: 1 }));
+		assert_eq!(parse_hex_string("55 ~8B"), Err(YaraError::UnexpectedCharacter { line: 1, character: '~' }));
+		assert_eq!(parse_hex_string("55 ( 8B | EC"), Err(YaraError::UnexpectedEnd { line: 1 }));
+		assert_eq!(parse_hex_string(" "), Err(YaraError::EmptyHexString { line: 1 }));
+		let many = "(00|01|02|03) ".repeat(5);
+		assert_eq!(parse_hex_string(&many), Err(YaraError::TooManyAlternatives { line: 1 }));
+	}
+
+	#[test]
+	fn test_load_rules() {
+		let rules = r#"
+import "pe"
+
+/* { not a rule } */
+private rule Stub : packer {
+	meta:
+		description = "braces } in strings are skipped"
+	strings:
+		$re = /55 \/ 8B/
+		$a = {
+			4D 5A
+			90 00
+		}
+		$b = { 4D 5A } // duplicate of a prefix
+	condition:
+		$a and #b > 0
+}
+
+rule Other { strings: $ = { 4D 5A } condition: all of them }
+"#;
+		let parsed = parse_rules(rules).unwrap();
+		assert_eq!(parsed.iter().map(|rule| (rule.name.as_str(), rule.line, rule.hex_strings.len())).collect::<Vec<_>>(), vec![("Stub", 5, 2), ("Other", 19, 1)]);
+		assert_eq!(parsed[0].hex_strings[0].line, 10);
+		assert_eq!(parsed[1].hex_strings[0].identifier, "$");
+		assert_eq!(parsed[0].other_strings, vec![r"$re = /55 \/ 8B/"]);
+		assert_eq!((parsed[0].condition.as_str(), parsed[1].condition.as_str()), ("$a and #b > 0", "all of them"));
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(load_rules(&mut tree, rules, Some(Path::new("stub.yar"))), Ok(2));
+		let found = tree.get_match([0x4d, 0x5a, 0x90, 0x00], None).unwrap();
+		assert_eq!(found.object, "Stub");
+		let provenance = tree.provenance(found.id).unwrap();
+		assert_eq!(provenance.to_string(), "stub.yar:10 (YARA): $a = {\n\t\t\t4D 5A\n\t\t\t90 00\n\t\t}");
+		assert_eq!(tree.get_signature([0x4d, 0x5a], None), Some("Stub".to_string()));
+		assert_eq!(load_rules(&mut tree, "rule Broken { strings: $a = { 4D 5", None), Err(YaraError::UnexpectedEnd { line: 1 }));
+		assert_eq!(parse_rules("rule { }"), Err(YaraError::UnexpectedCharacter { line: 1, character: '{' }));
+	}
+}
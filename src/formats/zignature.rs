@@ -0,0 +1,220 @@
+//! Import of radare2 zignatures, as written by `z*`.
+//!
+//! A zignature is a set of `za` records sharing a name, each holding one kind of information about
+//! a function:
+//!
+//! ```text
+//! zs *
+//! za sym.main b 554889e54883ec..:ffffffffffffff00
+//! za sym.main o 0x00001139
+//! za sym.main n main
+//! ```
+//!
+//! The `b` record holds the leading bytes of the function in hex, optionally followed by a mask of
+//! the same length after a colon; a `.` in the bytes stands for a nibble that isn't compared. The
+//! other records (the offset `o`, real name `n`, graph metrics `g`, references `r` and so on) can't
+//! be checked by the tree and are kept with the zignature but not imported. Records of one
+//! zignature are written together, so consecutive records with the same name make up one
+//! zignature. `zs` space selections, blank lines and lines starting with `#` are skipped.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::formats::zignature::load_zignatures;
+//!
+//! let zignatures = "zs *\nza sym.main b 554889e5..:ffffff00ff\nza sym.main o 0x00001139\n";
+//! let mut tree = SignatureDecisionTree::new();
+//! assert_eq!(load_zignatures(&mut tree, zignatures, None), Ok(1));
+//! assert_eq!(tree.get_signature([0x55, 0x48, 0x89, 0x00, 0x42], None), Some("sym.main".to_string()));
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::hash::BuildHasher;
+use std::path::Path;
+
+use crate::pattern::{Pattern, PatternBuilder};
+use crate::provenance::{ImportFormat, Provenance};
+use crate::SignatureDecisionTree;
+
+/// Represents the ways zignatures can fail to import. Lines are counted from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ZignatureError {
+	/// A line that is neither a `za` record nor a `zs` space selection.
+	UnexpectedLine {
+		line: usize
+	},
+	/// A `za` record without a name, a type or a value.
+	MissingFields {
+		line: usize
+	},
+	/// A character in the bytes or mask of a `b` record that isn't a hex digit, or a `.` in the
+	/// bytes.
+	InvalidCharacter {
+		line: usize,
+		character: char
+	},
+	/// Bytes or a mask with an odd number of digits, or a mask of a different length than the
+	/// bytes.
+	LengthMismatch {
+		line: usize
+	},
+	/// A `b` record with no bytes.
+	EmptySignature {
+		line: usize
+	}
+}
+
+impl Display for ZignatureError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ZignatureError::UnexpectedLine { line } => write!(f, "unexpected line {line}"),
+			ZignatureError::MissingFields { line } => write!(f, "record on line {line} is missing fields"),
+			ZignatureError::InvalidCharacter { line, character } => write!(f, "invalid character {character:?} in the bytes on line {line}"),
+			ZignatureError::LengthMismatch { line } => write!(f, "bytes and mask on line {line} don't line up"),
+			ZignatureError::EmptySignature { line } => write!(f, "bytes on line {line} are empty")
+		}
+	}
+}
+
+impl std::error::Error for ZignatureError {}
+
+/// Represents a zignature, with its bytes parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Zignature {
+	/// The name of the zignature.
+	pub name: String,
+	/// The line the first record of the zignature is on.
+	pub line: usize,
+	/// Every record of the zignature, as written.
+	pub text: String,
+	/// The records other than the bytes, as written after the name, such as `o 0x00001139`.
+	pub other_records: Vec<String>,
+	/// The signature of the `b` record, or `None` if the zignature has none.
+	pub pattern: Option<Pattern>
+}
+
+/// Parse every zignature of `source`.
+/// ```rust
+/// use dectree_rs::formats::zignature::parse_zignatures;
+///
+/// let zignatures = parse_zignatures("za entry0 b 31ed49\nza entry0 n _start\nza sym.f g cc=1 nbbs=1").unwrap();
+/// assert_eq!(zignatures.len(), 2);
+/// assert_eq!(zignatures[0].other_records, vec!["n _start"]);
+/// assert_eq!(zignatures[1].pattern, None);
+/// ```
+pub fn parse_zignatures(source: &str) -> Result<Vec<Zignature>, ZignatureError> {
+	let mut zignatures: Vec<Zignature> = Vec::new();
+	for (index, text) in source.lines().enumerate() {
+		let (line, text) = (index + 1, text.trim());
+		if text.is_empty() || text.starts_with('#') || text.split_whitespace().next() == Some("zs") {
+			continue
+		}
+		let Some(record) = text.strip_prefix("za ") else {
+			return Err(ZignatureError::UnexpectedLine { line })
+		};
+		let mut fields = record.trim_start().splitn(3, ' ');
+		let (Some(name), Some(kind), Some(value)) = (fields.next(), fields.next(), fields.next()) else {
+			return Err(ZignatureError::MissingFields { line })
+		};
+		let pattern = match kind {
+			"b" => Some(parse_bytes(value.trim(), line)?),
+			_ => None
+		};
+		// A record continues the zignature before it unless the name changes, or the zignature
+		// already has its bytes.
+		let continued = zignatures.last().is_some_and(|last| last.name == name && (pattern.is_none() || last.pattern.is_none()));
+		if !continued {
+			zignatures.push(Zignature { name: name.to_string(), line, text: String::new(), other_records: Vec::new(), pattern: None });
+		}
+		let zignature = zignatures.last_mut().expect("a zignature was just pushed");
+		if !zignature.text.is_empty() {
+			zignature.text.push('\n');
+		}
+		zignature.text.push_str(text);
+		match pattern {
+			Some(pattern) => zignature.pattern = Some(pattern),
+			None => zignature.other_records.push(format!("{kind} {}", value.trim()))
+		}
+	}
+	Ok(zignatures)
+}
+
+/// Parse the value of a `b` record: the bytes, with `.` for nibbles that aren't compared, and
+/// optionally a colon and the mask.
+fn parse_bytes(value: &str, line: usize) -> Result<Pattern, ZignatureError> {
+	let (bytes, masks) = value.split_once(':').map_or((value, None), |(bytes, masks)| (bytes, Some(masks)));
+	if bytes.len() % 2 != 0 || masks.is_some_and(|masks| masks.len() != bytes.len()) {
+		return Err(ZignatureError::LengthMismatch { line })
+	}
+	let nibble = |digit: u8, wildcard: bool| match digit {
+		b'.' if wildcard => Ok((0x0, 0x0)),
+		_ => (digit as char).to_digit(16)
+			.map(|value| (value as u8, 0xf))
+			.ok_or(ZignatureError::InvalidCharacter { line, character: digit as char })
+	};
+	let mut builder = PatternBuilder::new();
+	for (index, pair) in bytes.as_bytes().chunks(2).enumerate() {
+		let ((high, high_mask), (low, low_mask)) = (nibble(pair[0], true)?, nibble(pair[1], true)?);
+		let mut mask = high_mask << 4 | low_mask;
+		if let Some(masks) = masks {
+			let pair = &masks.as_bytes()[index * 2..index * 2 + 2];
+			mask &= nibble(pair[0], false)?.0 << 4 | nibble(pair[1], false)?.0;
+		}
+		builder = builder.masked(high << 4 | low, mask);
+	}
+	builder.build().map_err(|_| ZignatureError::EmptySignature { line })
+}
+
+/// Parse zignatures and add the bytes of every zignature that has them to `tree`, with the
+/// zignature names as the objects. Each signature records its zignature, along with `path` if the
+/// zignatures were read from a file, as its provenance. Nothing is added unless the whole input
+/// parses. Returns the number of signatures added; zignatures without bytes, duplicates and
+/// signatures the tree rejects are skipped.
+pub fn load_zignatures<S>(tree: &mut SignatureDecisionTree<String, S>, source: &str, path: Option<&Path>) -> Result<usize, ZignatureError>
+	where S: BuildHasher {
+	let mut added = 0;
+	for zignature in parse_zignatures(source)? {
+		let Some(pattern) = zignature.pattern else {
+			continue
+		};
+		let (bytes, masks) = pattern.into_parts();
+		let Ok(id) = tree.try_add_signature(bytes, Some(masks), zignature.name) else {
+			continue
+		};
+		let mut provenance = Provenance::new(ImportFormat::Zignature, zignature.text).line(zignature.line);
+		if let Some(path) = path {
+			provenance = provenance.path(path);
+		}
+		tree.set_provenance(id, provenance);
+		added += 1;
+	}
+	Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{load_zignatures, parse_zignatures, ZignatureError};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_parse_zignatures() {
+		let zignatures = parse_zignatures("zs *\n# exported\nza a b 55.9:ffff\nza a o 0x1000\n\nza a b c3\nza b n b\n").unwrap();
+		assert_eq!(zignatures.iter().map(|zignature| (zignature.name.as_str(), zignature.line)).collect::<Vec<_>>(), vec![("a", 3), ("a", 6), ("b", 7)]);
+		assert_eq!(zignatures[0].text, "za a b 55.9:ffff\nza a o 0x1000");
+		let pattern = zignatures[0].pattern.as_ref().unwrap();
+		assert_eq!((pattern.bytes(), pattern.masks()), (&[0x55, 0x09][..], &[0xff, 0x0f][..]));
+		assert_eq!(parse_zignatures("z a b 55"), Err(ZignatureError::UnexpectedLine { line: 1 }));
+		assert_eq!(parse_zignatures("za a b"), Err(ZignatureError::MissingFields { line: 1 }));
+		assert_eq!(parse_zignatures("za a b 5g"), Err(ZignatureError::InvalidCharacter { line: 1, character: 'g' }));
+		assert_eq!(parse_zignatures("za a b 5589:ff"), Err(ZignatureError::LengthMismatch { line: 1 }));
+		assert_eq!(parse_zignatures("za a b 55:.f"), Err(ZignatureError::InvalidCharacter { line: 1, character: '.' }));
+		assert_eq!(parse_zignatures("za a b :"), Err(ZignatureError::EmptySignature { line: 1 }));
+	}
+
+	#[test]
+	fn test_load_zignatures() {
+		let mut tree = SignatureDecisionTree::new();
+		assert_eq!(load_zignatures(&mut tree, "za one b 31c0c3\nza two b 31c0c3\nza three g cc=1\n", None), Ok(1));
+		let found = tree.get_match([0x31, 0xc0, 0xc3], None).unwrap();
+		assert_eq!(found.object, "one");
+		assert_eq!(tree.provenance(found.id).map(|provenance| provenance.text.as_str()), Some("za one b 31c0c3"));
+	}
+}
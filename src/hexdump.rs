@@ -0,0 +1,90 @@
+//! Rendering of matches as annotated hex dumps, for logs and reports.
+//!
+//! Each row shows the offset of its first byte, up to 16 bytes in hex and their printable ASCII.
+//! Rows holding matched bytes are followed by a marker row: `^^` under bytes compared in full,
+//! `~~` under partially masked bytes and `??` under fully wildcarded ones.
+//! ```text
+//! 00000000  90 90 55 8b 42 ec 90 90                          |..U.B...|
+//!                 ^^ ^^ ?? ^^
+//! ```
+
+use std::fmt::Write;
+
+use crate::Match;
+
+/// The number of bytes shown on each row.
+const ROW_LEN: usize = 16;
+
+/// Render `found`, a match in `buffer`, as an annotated hex dump including up to `context` bytes
+/// on either side of the match.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::hexdump::render_match;
+///
+/// let mut tree = SignatureDecisionTree::new();
+/// tree.add_signature_str("55 8B ?? EC", 1).unwrap();
+/// let buffer = [0x90, 0x90, 0x55, 0x8b, 0x42, 0xec, 0x90, 0x90];
+/// let found = tree.get_match(buffer, Some(2)).unwrap();
+/// assert_eq!(render_match(&buffer, &found, 2), concat!(
+///     "00000000  90 90 55 8b 42 ec 90 90                          |..U.B...|\n",
+///     "                ^^ ^^ ?? ^^\n"
+/// ));
+/// ```
+pub fn render_match<T>(buffer: &[u8], found: &Match<T>, context: usize) -> String {
+	let start = found.offset.saturating_sub(context).min(buffer.len());
+	let end = (found.offset + found.length).saturating_add(context).min(buffer.len());
+	let mut dump = String::new();
+	for row_start in (start..end).step_by(ROW_LEN) {
+		let row = &buffer[row_start..end.min(row_start + ROW_LEN)];
+		let _ = write!(dump, "{row_start:08x} ");
+		for byte in row {
+			let _ = write!(dump, " {byte:02x}");
+		}
+		let ascii = row.iter()
+			.map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+			.collect::<String>();
+		let _ = writeln!(dump, "{}  |{ascii}|", "   ".repeat(ROW_LEN - row.len()));
+		let markers = (row_start..row_start + row.len()).map(|offset| {
+			match offset.checked_sub(found.offset).and_then(|index| found.masks.get(index)) {
+				None => "  ",
+				Some(0xff) => "^^",
+				Some(0x00) => "??",
+				Some(_) => "~~"
+			}
+		}).collect::<Vec<_>>().join(" ");
+		if !markers.trim().is_empty() {
+			let _ = writeln!(dump, "          {}", markers.trim_end());
+		}
+	}
+	dump
+}
+
+#[cfg(test)]
+mod tests {
+	use super::render_match;
+	use crate::{Match, SignatureId};
+
+	#[test]
+	fn test_render_match() {
+		let buffer = (0x30..0x54).collect::<Vec<u8>>();
+		let found = Match {
+			id: SignatureId(0),
+			offset: 14,
+			length: 4,
+			bytes: vec![0x3e, 0x3f, 0x00, 0x01],
+			masks: vec![0xff, 0xff, 0x00, 0x0f],
+			object: (),
+			enabled: true
+		};
+		assert_eq!(render_match(&buffer, &found, 10), concat!(
+			"00000004  34 35 36 37 38 39 3a 3b 3c 3d 3e 3f 40 41 42 43  |456789:;<=>?@ABC|\n",
+			"                                        ^^ ^^ ?? ~~\n",
+			"00000014  44 45 46 47 48 49 4a 4b                          |DEFGHIJK|\n",
+		));
+		let found = Match { id: SignatureId(0), offset: 34, length: 2, bytes: vec![0x52, 0x53], masks: vec![0xff, 0xff], object: (), enabled: true };
+		assert_eq!(render_match(&buffer, &found, 4), concat!(
+			"0000001e  4e 4f 50 51 52 53                                |NOPQRS|\n",
+			"                      ^^ ^^\n"
+		));
+	}
+}
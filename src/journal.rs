@@ -0,0 +1,230 @@
+//! An append-only journal of scan matches.
+//!
+//! A `Journal` takes every hit of `StreamScanner::scan_journaled()` as soon as it is found and
+//! writes it out, flushed, before the scan moves on, so if a long scan crashes or is killed, every
+//! hit found up to that point is already on disk. Each hit takes one line holding the time it was
+//! found, in milliseconds since the Unix epoch, the kind of record, the stream offset and the
+//! object, separated by tabs, as in `"1760486400000\tmatch\t4096\tUPX 2.90\n"`.
+//!
+//! Tabs, newlines and backslashes in objects are escaped as `\t`, `\n` and `\\`, so every record
+//! stays on its line. Journal files are opened in append mode, and whole records are never changed.
+//!
+//! Along with its hits, the scan writes `scanned` records, such as
+//! `"1760486400000\tscanned\t67108864\n"`, each time it gets another checkpoint interval further
+//! into the stream, and once more when it finishes. Every offset below a `scanned` record's offset
+//! has been scanned and its hit journaled. After a crash, `resume_offset()` reads the journal back
+//! to find where the scan stopped, and a scanner started there with `StreamScanner::start_offset()`
+//! carries on appending to the same journal, skipping everything already scanned. A journal only
+//! describes one stream, so each stream scanned needs a journal of its own.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::journal::Journal;
+//! use dectree_rs::stream::StreamScanner;
+//!
+//! let mut tree = SignatureDecisionTree::new();
+//! tree.add_signature(vec![0x55, 0x8b, 0xec], None, "prologue");
+//! let input: &[u8] = &[0x90, 0x55, 0x8b, 0xec];
+//! let mut journal = Journal::new(Vec::new());
+//! StreamScanner::new(&tree, input).scan_journaled(&mut journal).unwrap();
+//! let text = String::from_utf8(journal.into_inner()).unwrap();
+//! assert!(text.contains("\tmatch\t1\tprologue\n"));
+//! assert!(text.ends_with("\tscanned\t4\n"));
+//! ```
+
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of bytes scanned between progress records, unless set with `checkpoint_interval()`.
+const DEFAULT_CHECKPOINT_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Represents an append-only journal that scan results are written to as they are found.
+pub struct Journal<W> where W: Write {
+	writer: W,
+	checkpoint_interval: u64,
+	/// The offset of the last progress record written.
+	checkpointed: u64
+}
+
+impl Journal<File> {
+	/// Open the journal file at `path` for appending, creating it if it doesn't exist. If the file
+	/// ends in a record cut short by a crash, the record is dropped so new records start on a line
+	/// of their own.
+	pub fn open<P>(path: P) -> io::Result<Self> where P: AsRef<Path> {
+		let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+		let length = file.seek(SeekFrom::End(0))?;
+		// Look back from the end for the newline ending the last whole record.
+		let (mut complete, mut block) = (length, [0u8; 4096]);
+		while complete > 0 {
+			let start = complete.saturating_sub(block.len() as u64);
+			let block = &mut block[..(complete - start) as usize];
+			file.seek(SeekFrom::Start(start))?;
+			file.read_exact(block)?;
+			match block.iter().rposition(|&byte| byte == b'\n') {
+				Some(position) => {
+					complete = start + position as u64 + 1;
+					break
+				},
+				None => complete = start
+			}
+		}
+		if complete < length {
+			file.set_len(complete)?;
+		}
+		Ok(Journal::new(file))
+	}
+}
+
+impl<W> Journal<W> where W: Write {
+	/// Create a new `Journal` that writes its records to `writer`.
+	pub fn new(writer: W) -> Self {
+		Journal {
+			writer,
+			checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+			checkpointed: 0
+		}
+	}
+
+	/// Set the number of bytes scanned between progress records. A smaller interval rescans less
+	/// after a crash at the cost of a longer journal.
+	///
+	/// # Panics
+	/// Panics if `checkpoint_interval` is zero.
+	pub fn checkpoint_interval(mut self, checkpoint_interval: u64) -> Self {
+		assert!(checkpoint_interval > 0, "checkpoint interval must be non-zero");
+		self.checkpoint_interval = checkpoint_interval;
+		self
+	}
+
+	/// Write a record of `object` matching at stream offset `offset`, stamped with the current
+	/// time, and flush it.
+	pub fn record_match<T>(&mut self, offset: u64, object: &T) -> io::Result<()> where T: Display + ?Sized {
+		writeln!(self.writer, "{}\tmatch\t{offset}\t{}", timestamp(), escape(&object.to_string()))?;
+		self.writer.flush()
+	}
+
+	/// Write a record that every offset below `offset` has been scanned, stamped with the current
+	/// time, and flush it.
+	pub fn record_progress(&mut self, offset: u64) -> io::Result<()> {
+		writeln!(self.writer, "{}\tscanned\t{offset}", timestamp())?;
+		self.checkpointed = offset;
+		self.writer.flush()
+	}
+
+	/// Write a progress record for `offset` if it is a whole checkpoint interval past the last one.
+	pub(crate) fn checkpoint(&mut self, offset: u64) -> io::Result<()> {
+		match offset.saturating_sub(self.checkpointed) >= self.checkpoint_interval {
+			true => self.record_progress(offset),
+			false => Ok(())
+		}
+	}
+
+	/// Get a reference to the underlying writer.
+	pub fn get_ref(&self) -> &W {
+		&self.writer
+	}
+
+	/// Consume the journal, returning the underlying writer.
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+}
+
+/// Read a journal back and find the stream offset its scan got to: everything below it has been
+/// scanned and its hits journaled. That is past the last progress record or the last hit, whichever
+/// is further along, since hits are journaled in offset order. A record cut short by a crash is
+/// ignored, and an empty journal resumes from the start.
+/// ```rust
+/// use dectree_rs::journal::resume_offset;
+///
+/// let journal = "1760486400000\tscanned\t4096\n1760486400001\tmatch\t5000\tret\n1760486400002\tmat";
+/// assert_eq!(resume_offset(journal.as_bytes()).unwrap(), 5001);
+/// ```
+pub fn resume_offset<R>(mut reader: R) -> io::Result<u64> where R: BufRead {
+	let (mut offset, mut line) = (0, Vec::new());
+	loop {
+		line.clear();
+		if reader.read_until(b'\n', &mut line)? == 0 || line.last() != Some(&b'\n') {
+			return Ok(offset)
+		}
+		let line = String::from_utf8_lossy(&line);
+		let mut fields = line.trim_end_matches('\n').split('\t').skip(1);
+		let reached = match (fields.next(), fields.next().and_then(|field| field.parse::<u64>().ok())) {
+			(Some("scanned"), Some(scanned)) => scanned,
+			(Some("match"), Some(matched)) => matched + 1,
+			_ => continue
+		};
+		offset = offset.max(reached);
+	}
+}
+
+/// Get the current time in milliseconds since the Unix epoch, or zero if the clock is set before it.
+fn timestamp() -> u128 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis())
+}
+
+/// Escape the characters that would break a record over several fields or lines.
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for character in text.chars() {
+		match character {
+			'\\' => escaped.push_str("\\\\"),
+			'\t' => escaped.push_str("\\t"),
+			'\n' => escaped.push_str("\\n"),
+			_ => escaped.push(character)
+		}
+	}
+	escaped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{resume_offset, Journal};
+	use crate::SignatureDecisionTree;
+	use crate::stream::StreamScanner;
+
+	#[test]
+	fn test_journal() {
+		let mut tree = SignatureDecisionTree::new();
+		tree.add_signature(vec![0xc3], None, "ret\tnear");
+		tree.add_signature(vec![0x55, 0x8b], None, "push\\mov\n");
+		let input: &[u8] = &[0x55, 0x8b, 0xc3, 0x90, 0xc3];
+		let mut journal = Journal::new(Vec::new());
+		assert_eq!(StreamScanner::new(&tree, input).chunk_size(2).scan_journaled(&mut journal).unwrap(), 5);
+		let text = String::from_utf8(journal.into_inner()).unwrap();
+		let records = text.lines()
+			.map(|line| line.split_once('\t').unwrap())
+			.inspect(|(timestamp, _)| assert!(timestamp.parse::<u128>().unwrap() > 0))
+			.map(|(_, record)| record)
+			.collect::<Vec<_>>();
+		assert_eq!(records, vec!["match\t0\tpush\\\\mov\\n", "match\t2\tret\\tnear", "match\t4\tret\\tnear", "scanned\t5"]);
+	}
+
+	#[test]
+	fn test_resume_offset() {
+		let mut tree = SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b, 0xec], None, "prologue");
+		tree.add_signature(vec![0xc3], None, "ret");
+		let input: &[u8] = &[0x55, 0x8b, 0xec, 0xc3, 0x90, 0x90, 0x55, 0x8b, 0x90, 0xc3, 0x55, 0x8b, 0xec];
+		let hits = |text: &str| text.lines()
+			.filter_map(|line| line.split_once("\tmatch\t"))
+			.map(|(_, hit)| hit.to_string())
+			.collect::<Vec<_>>();
+		let mut journal = Journal::new(Vec::new()).checkpoint_interval(3);
+		StreamScanner::new(&tree, input).chunk_size(2).scan_journaled(&mut journal).unwrap();
+		let text = String::from_utf8(journal.into_inner()).unwrap();
+		assert_eq!(hits(&text), vec!["0\tprologue", "3\tret", "9\tret", "10\tprologue"]);
+		assert_eq!(resume_offset(text.as_bytes()).unwrap(), input.len() as u64);
+		// Cut the journal short anywhere, as a crash would, and resume the scan from it.
+		for cut in 0..text.len() {
+			let offset = resume_offset(&text.as_bytes()[..cut]).unwrap();
+			let kept = text[..cut].rfind('\n').map_or(0, |end| end + 1);
+			let mut journal = Journal::new(text.as_bytes()[..kept].to_vec()).checkpoint_interval(3);
+			let scanner = StreamScanner::new(&tree, &input[offset as usize..]).chunk_size(2).start_offset(offset);
+			assert_eq!(scanner.scan_journaled(&mut journal).unwrap(), input.len() as u64);
+			assert_eq!(hits(&String::from_utf8(journal.into_inner()).unwrap()), hits(&text));
+		}
+	}
+}
@@ -1,257 +1,3566 @@
-#[doc = include_str!("../readme.md")]
+#![cfg_attr(feature = "std", doc = include_str!("../readme.md"))]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "std")]
+use std::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::num::NonZeroU32;
+#[cfg(feature = "std")]
+use std::ops::{ControlFlow, Range};
 
-/// Represents a reference counted reference to a `RefCell<TreeNode<T>>`. This is used for nodes that 
-/// need to be mutated but are shared between multiple references.
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod compiled;
+#[cfg(feature = "std")]
+pub mod constraint;
+#[cfg(feature = "std")]
+pub mod context;
+#[cfg(feature = "std")]
+pub mod convert;
+#[cfg(feature = "std")]
+pub mod directory;
+#[cfg(feature = "std")]
+pub mod feeds;
+#[cfg(feature = "heapless")]
+pub mod fixed;
+#[cfg(feature = "std")]
+pub mod formats;
+#[cfg(feature = "std")]
+pub mod hexdump;
+#[cfg(feature = "journal")]
+pub mod journal;
+#[cfg(feature = "std")]
+pub mod parser;
+#[cfg(feature = "std")]
+pub mod pattern;
+#[cfg(feature = "std")]
+pub mod provenance;
+#[cfg(feature = "std")]
+pub mod regression;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod strings;
+#[cfg(feature = "std")]
+pub mod target;
 
-type RcRefCellTreeNode<T> = Rc<RefCell<TreeNode<T>>>;
+/// Represents the position of a node in the tree's node arena. The root is always the first node and
+/// is never anyone's child, so child slots can use the niche of `NonZeroU32` and stay four bytes wide.
+#[cfg(feature = "std")]
+type NodeIndex = NonZeroU32;
 
-/// Represents a node in the decision tree. This is a recursive structure that can be used to represent
-/// a decision tree where each node is a choice and the leaf nodes are the final decision.
-#[derive(Clone, Debug)]
-struct TreeNode<T> where T: Clone + Default {
+/// Represents the position of a signature in the tree's signature list. Every signature is stored
+/// once, and the nodes on its path refer to it by index.
+#[cfg(feature = "std")]
+type SignatureIndex = u32;
+
+/// Represents a node in the decision tree. Nodes live in a single arena owned by the tree and refer
+/// to their children by index, where each node is a choice and the leaf nodes are the final decision.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct TreeNode {
 	/// The depth of the node in the tree.
 	depth: i32,
+	/// The number of bytes before `depth` carried by the edge leading to the node, after the
+	/// choice made at its parent. Every signature below the node makes the same choices there, so
+	/// long shared runs take one node rather than one per byte.
+	skipped: i32,
 	/// The signatures that are valid at this node.
-	subtree_signatures: Vec<SignatureInfo<T>>,
-	/// The choices that can be made at this node.
-	choices:Vec<Option<RcRefCellTreeNode<T>>>,
+	subtree_signatures: Vec<SignatureIndex>,
+	/// The nodes the choices that can be made at this node lead to.
+	choices: Children,
+	/// The node that signatures with a fully-wildcarded byte at this depth lead to, since they
+	/// accept any input byte rather than one choice.
+	wildcard: Option<NodeIndex>,
 	/// The final decision at this node.
-	term: Vec<SignatureInfo<T>>,
+	term: Vec<SignatureIndex>,
+}
+
+#[cfg(feature = "std")]
+impl TreeNode {
+	/// Get the node `choice` leads to, where `None` is the wildcard choice.
+	fn child(&self, choice: Option<u8>) -> Option<usize> {
+		match choice {
+			Some(choice) => self.choices.get(choice),
+			None => self.wildcard
+		}.map(|child| child.get() as usize)
+	}
+
+	/// Get the depths carried by the edge leading to the node.
+	fn edge(&self) -> Range<usize> {
+		(self.depth - self.skipped) as usize..self.depth as usize
+	}
+
+	/// Get the signatures that pass through the node, whether they end there or go on.
+	fn signatures(&self) -> impl Iterator<Item = SignatureIndex> + Clone + '_ {
+		self.subtree_signatures.iter().chain(&self.term).copied()
+	}
+}
+
+/// The most children a node keeps in a sorted list before switching to a bitmap.
+#[cfg(feature = "std")]
+const MAX_SPARSE_CHILDREN: usize = 16;
+
+/// Represents the children of a node, one for every choice byte that leads somewhere. Most nodes
+/// have one or two, so they are kept in a list sorted by byte, and only nodes with more than
+/// `MAX_SPARSE_CHILDREN` switch to a bitmap of the bytes present along with their nodes in byte
+/// order, where a node is found by counting the bytes below its own.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Children {
+	Sparse(Vec<(u8, NodeIndex)>),
+	Dense(ByteSet, Vec<NodeIndex>)
+}
+
+#[cfg(feature = "std")]
+impl Children {
+	/// Get the node `byte` leads to.
+	fn get(&self, byte: u8) -> Option<NodeIndex> {
+		match self {
+			Children::Sparse(children) => children.binary_search_by_key(&byte, |&(choice, _)| choice)
+				.ok()
+				.map(|position| children[position].1),
+			Children::Dense(bytes, children) => bytes.contains(byte).then(|| children[bytes.rank(byte)])
+		}
+	}
+
+	/// Make `byte` lead to `child`.
+	fn insert(&mut self, byte: u8, child: NodeIndex) {
+		match self {
+			Children::Sparse(children) => match children.binary_search_by_key(&byte, |&(choice, _)| choice) {
+				Ok(position) => children[position].1 = child,
+				Err(position) => children.insert(position, (byte, child))
+			},
+			Children::Dense(bytes, children) => {
+				let position = bytes.rank(byte);
+				match bytes.contains(byte) {
+					true => children[position] = child,
+					false => {
+						bytes.insert(byte);
+						children.insert(position, child);
+					}
+				}
+			}
+		}
+		if let Children::Sparse(children) = self {
+			if children.len() > MAX_SPARSE_CHILDREN {
+				let mut bytes = ByteSet::default();
+				children.iter().for_each(|&(choice, _)| bytes.insert(choice));
+				*self = Children::Dense(bytes, children.iter().map(|&(_, child)| child).collect());
+			}
+		}
+	}
+
+	/// Get the number of children.
+	fn len(&self) -> usize {
+		match self {
+			Children::Sparse(children) => children.len(),
+			Children::Dense(_, children) => children.len()
+		}
+	}
+
+	/// Get the number of bytes allocated on the heap for the children.
+	fn heap_size(&self) -> usize {
+		match self {
+			Children::Sparse(children) => children.capacity() * std::mem::size_of::<(u8, NodeIndex)>(),
+			Children::Dense(_, children) => children.capacity() * std::mem::size_of::<NodeIndex>()
+		}
+	}
 }
 
-impl<T> Default for TreeNode<T> where T: Clone + Default {
+#[cfg(feature = "std")]
+impl Default for Children {
 	fn default() -> Self {
-		TreeNode {
-			depth: 0,
-			subtree_signatures: Vec::new(),
-			choices: vec![None; 256],
-			term: Vec::new()
+		Children::Sparse(Vec::new())
+	}
+}
+
+/// Represents the comparison masks of a signature. Masks that are all ones are stored implicitly,
+/// since most signatures have no wildcards at all, and masks made of long repeated stretches (such
+/// as structure signatures that are mostly wildcards) are run-length encoded.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Mask {
+	/// Every byte of the signature must match exactly.
+	Exact,
+	/// Each byte of the signature is compared under its own mask.
+	Bytes(Vec<u8>),
+	/// The masks as runs of a repeated value, in order.
+	Runs(Vec<MaskRun>)
+}
+
+/// Represents a run of identical masks, ending just before the byte at `end`.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct MaskRun {
+	end: u32,
+	mask: u8
+}
+
+#[cfg(feature = "std")]
+impl Mask {
+	/// Get the number of bytes the masks occupy on the heap.
+	fn heap_size(&self) -> usize {
+		match self {
+			Mask::Exact => 0,
+			Mask::Bytes(masks) => masks.capacity(),
+			Mask::Runs(runs) => runs.capacity() * std::mem::size_of::<MaskRun>()
+		}
+	}
+
+	/// Create a `Mask` from per-byte masks, using the most compact representation for them.
+	fn new(masks: Vec<u8>) -> Self {
+		if masks.iter().all(|&mask| mask == 0xff) {
+			return Mask::Exact
+		}
+		let mut runs: Vec<MaskRun> = Vec::new();
+		for (index, &mask) in masks.iter().enumerate() {
+			match runs.last_mut() {
+				Some(run) if run.mask == mask => run.end = index as u32 + 1,
+				_ => runs.push(MaskRun { end: index as u32 + 1, mask })
+			}
+		}
+		if runs.len() * std::mem::size_of::<MaskRun>() < masks.len() {
+			Mask::Runs(runs)
+		} else {
+			Mask::Bytes(masks)
+		}
+	}
+
+	/// Expand the masks of a signature of `len` bytes back into one mask per byte.
+	fn to_vec(&self, len: usize) -> Vec<u8> {
+		(0..len).map(|index| self.get(index)).collect()
+	}
+
+	/// Get one mask per byte of a signature of `len` bytes, borrowing them when they are stored
+	/// that way.
+	fn as_slice(&self, len: usize) -> Cow<'_, [u8]> {
+		match self {
+			Mask::Bytes(masks) => Cow::Borrowed(masks),
+			_ => Cow::Owned(self.to_vec(len))
+		}
+	}
+
+	/// The mask of the byte at `index`.
+	fn get(&self, index: usize) -> u8 {
+		match self {
+			Mask::Exact => 0xff,
+			Mask::Bytes(masks) => masks[index],
+			Mask::Runs(runs) => runs[runs.partition_point(|run| run.end as usize <= index)].mask
 		}
 	}
 }
 
+/// Represents a set of byte values, used to prefilter scan offsets by the first signature byte and
+/// to index the children of nodes with many of them.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct ByteSet([u64; 4]);
+
+#[cfg(feature = "std")]
+impl ByteSet {
+	fn insert(&mut self, byte: u8) {
+		self.0[byte as usize / 64] |= 1 << (byte % 64);
+	}
+
+	fn contains(&self, byte: u8) -> bool {
+		self.0[byte as usize / 64] & (1 << (byte % 64)) != 0
+	}
+
+	/// Get the number of bytes in the set that are lower than `byte`.
+	fn rank(&self, byte: u8) -> usize {
+		let (word, bit) = (byte as usize / 64, byte % 64);
+		let below = self.0[..word].iter().map(|bits| bits.count_ones() as usize).sum::<usize>();
+		below + (self.0[word] & ((1 << bit) - 1)).count_ones() as usize
+	}
+}
+
 /// Represents signature information. This is used to store the signature bytes, masks, and the object
 /// that is associated with the signature.
+#[cfg(feature = "std")]
 #[derive(Clone, Debug)]
-struct SignatureInfo<T> where T: Clone + Default {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SignatureInfo<T> where T: Clone {
 	bytes: Vec<u8>,
-	masks: Vec<u8>,
-	object: T
+	masks: Mask,
+	object: T,
+	/// Whether matches of the signature are reported. Disabled signatures stay in the tree.
+	enabled: bool,
+	/// Where the signature was imported from, if known.
+	provenance: Option<provenance::Provenance>,
+	/// The kind of input the signature applies to, if limited.
+	target: Option<target::Target>,
+	/// The computed values the bytes of a match must hold, on top of the signature's bytes.
+	constraints: Vec<constraint::Constraint>,
+	/// Whether the signature is staged: its matches are only reported by staged queries, apart
+	/// from the primary results.
+	staged: bool
+}
+
+#[cfg(feature = "std")]
+impl<T> SignatureInfo<T> where T: Clone {
+	/// Get the choice the signature makes at `depth`: its byte there, or `None` if that byte is
+	/// fully wildcarded.
+	fn choice_at(&self, depth: usize) -> Option<u8> {
+		Some(self.bytes[depth]).filter(|_| self.masks.get(depth) != 0x00)
+	}
+
+	/// Compare the signature to `bytes` at `offset`, and find the position of the first byte that
+	/// doesn't match, along with the number of unmasked bytes that matched before it. Returns
+	/// `None` if the signature matches, or if the input runs out before it diverges.
+	fn divergence(&self, bytes: &[u8], offset: usize) -> Option<(usize, usize)> {
+		let mut matched = 0;
+		for (position, &sbyte) in self.bytes.iter().enumerate() {
+			let mask = self.masks.get(position);
+			if bytes.get(offset + position)? & mask != sbyte {
+				return Some((position, matched))
+			}
+			matched += usize::from(mask != 0x00);
+		}
+		None
+	}
+
+	/// Count the choices the signature shares with `other` from `depth` on, up to the end of the
+	/// shorter of the two.
+	fn shared_choices(&self, other: &SignatureInfo<T>, depth: usize) -> usize {
+		(depth..self.bytes.len().min(other.bytes.len()))
+			.take_while(|&depth| self.choice_at(depth) == other.choice_at(depth))
+			.count()
+	}
+
+	/// Check whether every constraint of the signature holds for a match at `offset` of `bytes`.
+	fn constraints_hold<B>(&self, bytes: &B, offset: i32) -> bool where B: ByteSource + ?Sized {
+		self.constraints.iter().all(|constraint| constraint.holds(bytes, offset as usize))
+	}
+
+	/// Describe a match at `offset` of the signature, which has the id `id`.
+	fn to_match(&self, id: SignatureId, offset: usize) -> Match<T> {
+		Match {
+			id,
+			offset,
+			length: self.bytes.len(),
+			bytes: self.bytes.clone(),
+			masks: self.masks.to_vec(self.bytes.len()),
+			object: self.object.clone(),
+			enabled: self.enabled
+		}
+	}
+}
+
+/// A source of bytes that signatures can be matched against. This lets the lookup walk views
+/// of the input (such as a ring buffer) without first copying them into a flat buffer.
+#[cfg(feature = "std")]
+trait ByteSource {
+	/// The number of bytes in the logical stream.
+	fn len(&self) -> usize;
+	/// The byte at `index` in the logical stream.
+	fn byte_at(&self, index: usize) -> u8;
+}
+
+#[cfg(feature = "std")]
+impl ByteSource for [u8] {
+	fn len(&self) -> usize {
+		<[u8]>::len(self)
+	}
+
+	fn byte_at(&self, index: usize) -> u8 {
+		self[index]
+	}
+}
+
+/// Represents a view over a circular buffer. The logical stream starts at `head` and runs up to
+/// (but not including) `tail`, wrapping from the end of the buffer back to its beginning.
+#[cfg(feature = "std")]
+struct RingView<'a> {
+	data: &'a [u8],
+	head: usize,
+	len: usize
+}
+
+#[cfg(feature = "std")]
+impl<'a> RingView<'a> {
+	/// Create a view over `data`. When `head == tail` the buffer is taken to be full.
+	fn new(data: &'a [u8], head: usize, tail: usize) -> Self {
+		let cap = data.len();
+		let len = if cap == 0 {
+			0
+		} else if head % cap == tail % cap {
+			cap
+		} else {
+			(tail % cap + cap - head % cap) % cap
+		};
+		RingView {
+			data,
+			head: if cap == 0 { 0 } else { head % cap },
+			len
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl ByteSource for RingView<'_> {
+	fn len(&self) -> usize {
+		self.len
+	}
+
+	fn byte_at(&self, index: usize) -> u8 {
+		self.data[(self.head + index) % self.data.len()]
+	}
+}
+
+/// Represents a view over a list of non-contiguous slices that are treated as one logical stream.
+#[cfg(feature = "std")]
+struct SlicesView<'a> {
+	slices: &'a [&'a [u8]],
+	/// The logical offset at which each slice starts.
+	starts: Vec<usize>,
+	len: usize
+}
+
+#[cfg(feature = "std")]
+impl<'a> SlicesView<'a> {
+	fn new(slices: &'a [&'a [u8]]) -> Self {
+		let mut starts = Vec::with_capacity(slices.len());
+		let mut len = 0;
+		for slice in slices {
+			starts.push(len);
+			len += slice.len();
+		}
+		SlicesView {
+			slices,
+			starts,
+			len
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl ByteSource for SlicesView<'_> {
+	fn len(&self) -> usize {
+		self.len
+	}
+
+	fn byte_at(&self, index: usize) -> u8 {
+		// Empty slices share their start with the next slice, so take the last slice starting
+		// at or before `index`, which is always the non-empty one containing it.
+		let slice = self.starts.partition_point(|&start| start <= index) - 1;
+		self.slices[slice][index - self.starts[slice]]
+	}
+}
+
+/// Represents a strided view over a buffer: every `stride`th byte starting at `start`.
+#[cfg(feature = "std")]
+struct StrideView<'a> {
+	data: &'a [u8],
+	start: usize,
+	stride: usize
+}
+
+#[cfg(feature = "std")]
+impl ByteSource for StrideView<'_> {
+	fn len(&self) -> usize {
+		if self.start >= self.data.len() {
+			0
+		} else {
+			(self.data.len() - self.start).div_ceil(self.stride)
+		}
+	}
+
+	fn byte_at(&self, index: usize) -> u8 {
+		self.data[self.start + index * self.stride]
+	}
+}
+
+/// Represents a view over a buffer shifted left by `shift` bits (most significant bit first), so
+/// byte `i` of the view is made of the low bits of `data[i]` and the high bits of `data[i + 1]`.
+#[cfg(feature = "std")]
+struct BitShiftView<'a> {
+	data: &'a [u8],
+	shift: u32
+}
+
+#[cfg(feature = "std")]
+impl ByteSource for BitShiftView<'_> {
+	fn len(&self) -> usize {
+		if self.shift == 0 {
+			self.data.len()
+		} else {
+			self.data.len().saturating_sub(1)
+		}
+	}
+
+	fn byte_at(&self, index: usize) -> u8 {
+		if self.shift == 0 {
+			self.data[index]
+		} else {
+			(self.data[index] << self.shift) | (self.data[index + 1] >> (8 - self.shift))
+		}
+	}
+}
+
+/// Represents an endian swap that can be applied to a signature so that it also matches data
+/// written with the opposite byte order.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndianSwap {
+	/// Reverse the bytes of every 16-bit word.
+	Swap16,
+	/// Reverse the bytes of every 32-bit word.
+	Swap32
+}
+
+#[cfg(feature = "std")]
+impl EndianSwap {
+	/// The width of the words being swapped, in bytes.
+	fn width(&self) -> usize {
+		match self {
+			EndianSwap::Swap16 => 2,
+			EndianSwap::Swap32 => 4
+		}
+	}
+
+	/// Swap every word of `bytes`, or return `None` if it isn't a whole number of words.
+	fn apply(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+		let width = self.width();
+		if bytes.is_empty() || !bytes.len().is_multiple_of(width) {
+			return None
+		}
+		Some(bytes.chunks(width).flat_map(|word| word.iter().rev().copied()).collect())
+	}
+}
+
+/// Represents the identity of a signature in a tree, as returned by
+/// `SignatureDecisionTree::add_signature_with_id()`. Ids are handed out in the order signatures are
+/// added and stay valid for the life of the tree.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignatureId(SignatureIndex);
+
+/// Represents a successful lookup, with the signature that matched and where it matched.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match<T> {
+	/// The id of the matching signature, which `SignatureDecisionTree::provenance()` traces back
+	/// to the rule it was imported from.
+	pub id: SignatureId,
+	/// The offset in the input the signature matched at.
+	pub offset: usize,
+	/// The number of input bytes the signature covers.
+	pub length: usize,
+	/// The bytes of the matching signature.
+	pub bytes: Vec<u8>,
+	/// The masks of the matching signature, one per byte.
+	pub masks: Vec<u8>,
+	/// The object associated with the matching signature.
+	pub object: T,
+	/// Whether the matching signature is enabled. Only what-if queries such as
+	/// `SignatureDecisionTree::what_if_matches()` report matches of disabled signatures.
+	pub enabled: bool
+}
+
+/// Represents a place where a signature started to match but then diverged from the input, as
+/// reported by `SignatureDecisionTree::scan_near_misses()`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NearMiss<T> {
+	/// The id of the signature.
+	pub id: SignatureId,
+	/// The offset in the input the signature was anchored at.
+	pub offset: usize,
+	/// The number of unmasked signature bytes that matched before the divergence.
+	pub matched: usize,
+	/// The position in the signature of the first byte that didn't match.
+	pub position: usize,
+	/// The input byte found at `position`.
+	pub found: u8,
+	/// The object associated with the signature.
+	pub object: T
+}
+
+/// Represents the reason an input was rejected by the tree, as returned by
+/// `SignatureDecisionTree::match_failure_reason()`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatchFailure {
+	/// No stored signature continues with the input byte at `depth`. `expected` holds the
+	/// (masked) byte values that would have continued the walk, in ascending order.
+	Diverged {
+		depth: usize,
+		expected: Vec<u8>
+	},
+	/// The input ran out at `depth` while the closest signature still needed `needed` more bytes.
+	OutOfInput {
+		depth: usize,
+		needed: usize
+	},
+	/// The only signature left disagreed with the input at `position`, where the masked input
+	/// byte had to equal `expected` under `mask`.
+	MaskMismatch {
+		position: usize,
+		expected: u8,
+		mask: u8
+	},
+	/// The input holds the bytes of the signature with the id `id`, but the signature is
+	/// disabled.
+	Disabled {
+		id: SignatureId
+	},
+	/// The input holds the bytes of the signature with the id `id`, but the signature is staged,
+	/// so only `scan_staged()` reports it.
+	Staged {
+		id: SignatureId
+	},
+	/// The input holds the bytes of the signature with the id `id`, but one of its constraints
+	/// doesn't hold.
+	ConstraintFailed {
+		id: SignatureId
+	}
+}
+
+/// Represents the error returned when a query runs out of its work budget before finishing.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BudgetExceeded;
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for BudgetExceeded {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "query exceeded its work budget")
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BudgetExceeded {}
+
+/// Represents the reasons a signature can't be added to a tree, as returned by
+/// `SignatureDecisionTree::try_add_signature()`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DectreeError {
+	/// The tree already holds a signature with the same bytes and masks.
+	Duplicate,
+	/// The masks are not the same length as the signature bytes.
+	LengthMismatch {
+		bytes: usize,
+		masks: usize
+	},
+	/// The signature has no bytes, either as given or once its wildcard tail was trimmed.
+	EmptyPattern,
+	/// The signature has fewer effective (not fully wildcarded) bytes than the tree's
+	/// `min_signature_length()`.
+	TooShort {
+		effective: usize,
+		minimum: usize
+	},
+	/// The signature was given as a hex string that couldn't be parsed.
+	Parse(parser::ParseError)
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for DectreeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DectreeError::Duplicate => write!(f, "signature is already in the tree"),
+			DectreeError::LengthMismatch { bytes, masks } => write!(f, "signature has {bytes} bytes but {masks} masks"),
+			DectreeError::EmptyPattern => write!(f, "signature is empty"),
+			DectreeError::TooShort { effective, minimum } => write!(f, "signature has {effective} effective bytes, fewer than the minimum of {minimum}"),
+			DectreeError::Parse(error) => write!(f, "invalid signature pattern: {error}")
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DectreeError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			DectreeError::Parse(error) => Some(error),
+			_ => None
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl From<parser::ParseError> for DectreeError {
+	fn from(error: parser::ParseError) -> Self {
+		DectreeError::Parse(error)
+	}
+}
+
+/// Represents the work a query is still allowed to do, counted in tree nodes visited plus input
+/// bytes compared. `None` means the query is unbounded.
+#[cfg(feature = "std")]
+struct Budget(Option<usize>);
+
+#[cfg(feature = "std")]
+impl Budget {
+	/// Spend one unit of work, failing if none is left.
+	fn spend(&mut self) -> Result<(), BudgetExceeded> {
+		match &mut self.0 {
+			Some(0) => Err(BudgetExceeded),
+			Some(left) => {
+				*left -= 1;
+				Ok(())
+			},
+			None => Ok(())
+		}
+	}
+}
+
+/// Represents how a tree treats signatures that end in fully-wildcarded (`0x00` mask) bytes.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WildcardTail {
+	/// Keep the trailing wildcards, so the input must still be long enough to cover them.
+	#[default]
+	Require,
+	/// Trim the trailing wildcards when the signature is added, so they become optional.
+	Trim
+}
+
+/// Represents the hits of one signature family in a buffer, collapsed into a single entry.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FamilyHit<K, T> {
+	/// The family the hits belong to.
+	pub family: K,
+	/// The number of hits from the family.
+	pub count: usize,
+	/// The offset of the best hit, the one whose matching signature is the longest.
+	pub best_offset: usize,
+	/// The length of the best hit's signature, as a measure of confidence.
+	pub best_length: usize,
+	/// The object of the best hit.
+	pub best: T
+}
+
+/// Represents how a tree resolves overlapping matches, where a shorter signature is a prefix of
+/// a longer one and both match the same input.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OverlapPolicy {
+	/// Prefer the longest (deepest) matching signature.
+	#[default]
+	PreferDeepest,
+	/// Prefer the shortest signature, the first one to terminate while walking the input.
+	PreferTerminal,
+	/// Report every matching signature from `get_signatures()`, longest first. Queries that only
+	/// return one object behave like `PreferDeepest`.
+	ReportAll
+}
+
+/// Represents how a tree handles a signature whose bytes and masks are already in the tree.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DuplicatePolicy {
+	/// Keep the stored object and drop the new one, reporting the id of the stored signature as if
+	/// it had just been added.
+	Ignore,
+	/// Replace the stored object with the new one, keeping the signature's id and everything else
+	/// attached to it.
+	Replace,
+	/// Skip the signature and report it as `DectreeError::Duplicate`.
+	#[default]
+	Error,
+	/// Add the signature again under a new id, so the pattern carries several objects. Every one of
+	/// them is returned by `get_signatures()` with `OverlapPolicy::ReportAll`; the other queries
+	/// return the one added first.
+	Append
+}
+
+/// Represents the result of `SignatureDecisionTree::scan_sampled()`: the hits that were kept and
+/// how many were dropped for each signature that fired more often than the limit.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SampledScan<T> {
+	/// The hits kept, as the offset they matched at and the object of the signature, in offset
+	/// order.
+	pub hits: Vec<(usize, T)>,
+	/// The signatures whose hits were cut off, in the order they were added to the tree.
+	pub suppressed: Vec<Suppressed<T>>
+}
+
+/// Represents a signature whose hits were cut off by `SignatureDecisionTree::scan_sampled()`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suppressed<T> {
+	/// The bytes of the signature.
+	pub bytes: Vec<u8>,
+	/// The object associated with the signature.
+	pub object: T,
+	/// The number of hits dropped after the limit was reached.
+	pub count: usize
+}
+
+/// Represents the shape and size of a tree, as returned by `SignatureDecisionTree::stats()`.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TreeStats {
+	/// The number of signatures stored.
+	pub signatures: usize,
+	/// The number of nodes, including the root.
+	pub nodes: usize,
+	/// The depth of the deepest node, the root being at depth 0.
+	pub max_depth: usize,
+	/// The average number of children of the nodes that have any, counting a wildcard branch as
+	/// a child.
+	pub average_branching: f64,
+	/// The approximate number of bytes the tree holds on the heap. Anything the objects themselves
+	/// allocate isn't counted.
+	pub heap_bytes: usize
+}
+
+/// Represents an iterator over the signatures of a tree, as returned by
+/// `SignatureDecisionTree::iter()`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct Iter<'a, T> where T: Clone {
+	signatures: std::slice::Iter<'a, SignatureInfo<T>>
+}
+
+#[cfg(feature = "std")]
+impl<'a, T> Iterator for Iter<'a, T> where T: Clone {
+	type Item = (&'a [u8], Cow<'a, [u8]>, &'a T);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let sig = self.signatures.next()?;
+		Some((&sig.bytes, sig.masks.as_slice(sig.bytes.len()), &sig.object))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.signatures.size_hint()
+	}
+}
+
+#[cfg(feature = "std")]
+impl<T> ExactSizeIterator for Iter<'_, T> where T: Clone {}
+
+/// Represents which signatures a walk of the tree reports.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+struct Scope<'a> {
+	/// Whether disabled signatures are reported too.
+	include_disabled: bool,
+	/// Whether the staged signatures are walked instead of the primary ones.
+	staged: bool,
+	/// The kind of input being walked, if known, so signatures for other inputs are skipped.
+	target: Option<&'a target::Target>
+}
+
+#[cfg(feature = "std")]
+impl Scope<'_> {
+	/// The enabled signatures, whatever their target.
+	const ENABLED: Scope<'static> = Scope { include_disabled: false, staged: false, target: None };
+
+	/// Check whether `sig` is reported.
+	fn admits<T>(&self, sig: &SignatureInfo<T>) -> bool where T: Clone {
+		(self.include_disabled || sig.enabled)
+			&& sig.staged == self.staged
+			&& self.target.zip(sig.target.as_ref()).is_none_or(|(input, target)| target.is_compatible(input))
+	}
 }
 
 /// Represents a decision tree that can be used to search for signatures. This is a tree structure that
 /// can be used to search for signatures in a binary blob. The tree is built by adding signatures to the
 /// tree and then searching for them.
+///
+/// The tree is generic over the `BuildHasher` used by its internal index, so a faster hasher can be
+/// supplied with `SignatureDecisionTree::with_hasher()` when bulk loading large signature sets.
+///
+/// Nodes are kept in one arena and linked by index, so cloning a tree yields an independent copy.
+/// The tree is `Send + Sync` whenever its objects are, so a built tree can be shared between
+/// worker threads (for example behind an `Arc`) and queried concurrently.
+///
+/// With the `serde` feature enabled the tree implements `Serialize` and `Deserialize`, so a tree
+/// built from a large signature set can be persisted once and reloaded without rebuilding it.
 /// ```rust
 /// use dectree_rs::SignatureDecisionTree;
 /// 
 /// let mut tree = SignatureDecisionTree::new();
-/// tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32, 0x77, 0x89, 0x4f, 0x55], None, None);
-/// tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32], None, None);
-/// tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0x00], None, None);
+/// tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32, 0x77, 0x89, 0x4f, 0x55], None, ());
+/// tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32], None, ());
+/// tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0x00], None, ());
 /// assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32, 0x00, 0x99, 0x36, 0x5f, 0x21, 0xfd], None), Some(()));
 /// assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32], None), Some(()));
 /// assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0x00], None), Some(()));
 /// assert_eq!(tree.get_signature(vec![0x55], None), None);
 /// ```
-#[derive(Clone, Debug, Default)]
-pub struct SignatureDecisionTree<T> where T: Clone + Default {
-	base_node: RcRefCellTreeNode<T>,
-	sigs_dup: HashMap<Vec<u8>, bool>
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+	serialize = "T: serde::Serialize",
+	deserialize = "T: serde::Deserialize<'de>, S: BuildHasher + Default"
+)))]
+pub struct SignatureDecisionTree<T, S = RandomState> where T: Clone {
+	/// The nodes of the tree, starting with the root.
+	nodes: Vec<TreeNode>,
+	/// Every signature in the tree, in the order they were added.
+	signatures: Vec<SignatureInfo<T>>,
+	sigs_dup: HashSet<(Vec<u8>, Mask), S>,
+	wildcard_tail: WildcardTail,
+	min_signature_length: usize,
+	overlap_policy: OverlapPolicy,
+	duplicate_policy: DuplicatePolicy,
+	/// Whether signatures are staged as they are added.
+	staging: bool,
+	/// The input bytes a signature can start with, so scans can skip other offsets without
+	/// walking the tree.
+	first_bytes: ByteSet,
+	max_signature_length: usize
+}
+
+#[cfg(feature = "std")]
+impl<T, S> Default for SignatureDecisionTree<T, S> where T: Clone, S: BuildHasher + Default {
+	fn default() -> Self {
+		SignatureDecisionTree::with_hasher(S::default())
+	}
 }
 
-impl<T> SignatureDecisionTree<T> where T: Clone + Default {
+#[cfg(feature = "std")]
+impl<T> SignatureDecisionTree<T> where T: Clone {
 	
 	/// Create a new `SignatureDecisionTree`.
 	pub fn new() -> Self {
 		SignatureDecisionTree::default()
 	}
 
-	/// Add a choice to the search tree.
-	fn add_choice(&mut self, signature_info: SignatureInfo<T>, tree_node: Rc<RefCell<TreeNode<T>>>) {
-		let mut node_info_list = vec![(tree_node, signature_info)];
-		// Workaround to avoid recursion
-		while let Some((node, sig_info)) = node_info_list.pop() {
-			let mut borrowed_node = node.borrow_mut();
-			let (depth, mut sigs, choices, mut term) = (borrowed_node.depth, borrowed_node.subtree_signatures.clone(), borrowed_node.choices.clone(), borrowed_node.term.clone());
-			let bytes = &sig_info.bytes;
-			let siglen = sigs.len();
-			if bytes.len() as i32 > depth {
-				sigs.push(sig_info.clone());
-				*borrowed_node = TreeNode {
-					depth: borrowed_node.depth,
-					subtree_signatures: sigs.clone(),
-					choices: borrowed_node.choices.clone(),
-					term: borrowed_node.term.clone()
-				};
-			} else {
-				term.push(sig_info.clone());
-				*borrowed_node = TreeNode {
-					depth: borrowed_node.depth,
-					subtree_signatures: borrowed_node.subtree_signatures.clone(),
-					choices: borrowed_node.choices.clone(),
-					term
-				};
-				continue;
-			}
-			let choices = Rc::new(RefCell::new(choices));
-			// If one sig is [85, 139, 236] and another is [85, 139, 236, 232, 144], then
-			// we're gonna panic without this check
-			if siglen == 0 {
-				// If it has no sigs, we need to add a level
-				// modify the next node
-				continue;
-			} else if siglen == 1 {
-				// If it has one already, we *both* need to add another level
-				// (because if it is the only one, it thought it was last choice)
-				for sig in sigs.iter() {
-					let ch_val = sig.bytes[depth as usize];
-					let nn_node = self.get_node(depth, choices.clone(), ch_val as i32);
-					*borrowed_node = TreeNode {
-						depth: borrowed_node.depth,
-						subtree_signatures: borrowed_node.subtree_signatures.clone(),
-						choices: choices.borrow().clone(),
-						term: borrowed_node.term.clone()
-					};
-					node_info_list.push((nn_node, sig.clone()));
-				}
-			} else {
-				// This is already a choice node, keep on choosing...
-				let ch_val = bytes[depth as usize];
-				let nn_node = self.get_node(depth, choices.clone(), ch_val as i32);
-				*borrowed_node = TreeNode {
-					depth: borrowed_node.depth,
-					subtree_signatures: borrowed_node.subtree_signatures.clone(),
-					choices: choices.borrow().clone(),
-					term: borrowed_node.term.clone()
-				};
-				node_info_list.push((nn_node, sig_info));
-			}
+	/// Build a tree from signatures given as their bytes, masks and object in one pass. The
+	/// signatures are sorted and every node is built once, top-down, instead of being revisited for
+	/// each signature passing through it as `add_signature()` does. The result is the same:
+	/// signatures that `add_signature()` would skip are skipped, and the rest get their ids in the
+	/// order given.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let tree = SignatureDecisionTree::build_from(vec![
+	///     (vec![0x55, 0x8b, 0xec], vec![0xff, 0xff, 0xff], "frame"),
+	///     (vec![0x55, 0x89, 0xe5], vec![0xff, 0xff, 0xff], "frame (AT&T)"),
+	///     (vec![0xc3], vec![0xff], "ret")
+	/// ]);
+	/// assert_eq!(tree.len(), 3);
+	/// assert_eq!(tree.scan([0x55, 0x89, 0xe5, 0xc3]), vec![(0, "frame (AT&T)"), (3, "ret")]);
+	/// ```
+	pub fn build_from(sigs: Vec<(Vec<u8>, Vec<u8>, T)>) -> Self {
+		let mut tree = SignatureDecisionTree::new();
+		tree.signatures.reserve(sigs.len());
+		tree.sigs_dup.reserve(sigs.len());
+		for (bytes, masks, val) in sigs {
+			let _ = tree.store_signature(bytes, Some(masks), val);
 		}
+		tree.build_nodes();
+		tree
 	}
+}
 
-	/// Chose, (and or initialize) a sub node.
-	fn get_node(&self, depth: i32, choices: Rc<RefCell<Vec<Option<RcRefCellTreeNode<T>>>>>, choice: i32) -> RcRefCellTreeNode<T> {
-		let mut borrowed_choices = choices.borrow_mut();
-		let nn_node = borrowed_choices[choice as usize].clone();
-		if nn_node.is_none() {
-			let nn_node = TreeNode{
-				depth: depth + 1,
-				..Default::default()
-			};
-			borrowed_choices[choice as usize] = Some(Rc::new(RefCell::new(nn_node)));
+#[cfg(feature = "std")]
+impl<T, S> Extend<(Vec<u8>, Option<Vec<u8>>, T)> for SignatureDecisionTree<T, S> where T: Clone, S: BuildHasher {
+	/// Add every signature of `iter`, given as its bytes, masks and object, as `add_signature()`
+	/// does. Signatures that would be skipped by `add_signature()` are skipped here too. Room for
+	/// the signatures is reserved up front, and they are added in the order given, so they get the
+	/// same ids as they would one by one.
+	fn extend<I>(&mut self, iter: I) where I: IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>, T)> {
+		let iter = iter.into_iter();
+		let (lower, _) = iter.size_hint();
+		self.signatures.reserve(lower);
+		self.sigs_dup.reserve(lower);
+		for (bytes, masks, val) in iter {
+			self.add_signature(bytes, masks, val);
 		}
-		let nn_node = borrowed_choices[choice as usize].as_ref().unwrap();
-		nn_node.clone()
 	}
+}
 
-	/// Add a signature to the search tree.  If masks goes unspecified, it will be
-	/// assumed to be all ones `vec![0xff; bytes.len()]`.
-	/// 
-	/// Additionally, you may specify `val` as the object to get back with
-	/// `tree.get_signature()`.
-	pub fn add_signature(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: Option<T>) {
-		let masks = masks.unwrap_or(vec![0xff; bytes.len()]);
-		let val = val.unwrap_or_default();
-		// Detect and skip duplicate additions...
-		let byte_key = [bytes.clone(), masks.clone()].concat();
-		if self.sigs_dup.contains_key(&byte_key) {
-			return
+#[cfg(feature = "std")]
+impl<T, S> FromIterator<(Vec<u8>, Option<Vec<u8>>, T)> for SignatureDecisionTree<T, S> where T: Clone, S: BuildHasher + Default {
+	/// Build a tree from signatures given as their bytes, masks and object, as `extend()` adds
+	/// them.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let signatures = vec![
+	///     (vec![0x55, 0x8b, 0xec], None, "frame"),
+	///     (vec![0xc3], None, "ret")
+	/// ];
+	/// let tree = signatures.into_iter().collect::<SignatureDecisionTree<_>>();
+	/// assert_eq!(tree.scan([0x55, 0x8b, 0xec, 0xc3]), vec![(0, "frame"), (3, "ret")]);
+	/// ```
+	fn from_iter<I>(iter: I) -> Self where I: IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>, T)> {
+		let mut tree = SignatureDecisionTree::default();
+		tree.extend(iter);
+		tree
+	}
+}
+
+#[cfg(feature = "std")]
+impl<'a, T, S> IntoIterator for &'a SignatureDecisionTree<T, S> where T: Clone, S: BuildHasher {
+	type Item = (&'a [u8], Cow<'a, [u8]>, &'a T);
+	type IntoIter = Iter<'a, T>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+#[cfg(feature = "std")]
+impl<T, S> SignatureDecisionTree<T, S> where T: Clone, S: BuildHasher {
+
+	/// Create a new `SignatureDecisionTree` whose internal index uses `hash_builder` to hash keys.
+	/// ```rust
+	/// use std::collections::hash_map::DefaultHasher;
+	/// use std::hash::BuildHasherDefault;
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// assert_eq!(tree.get_signature(vec![0x55, 0x8b, 0xec], None), Some(1));
+	/// ```
+	pub fn with_hasher(hash_builder: S) -> Self {
+		SignatureDecisionTree {
+			nodes: vec![TreeNode::default()],
+			signatures: Vec::new(),
+			sigs_dup: HashSet::with_hasher(hash_builder),
+			wildcard_tail: WildcardTail::default(),
+			min_signature_length: 0,
+			overlap_policy: OverlapPolicy::default(),
+			duplicate_policy: DuplicatePolicy::default(),
+			staging: false,
+			first_bytes: ByteSet::default(),
+			max_signature_length: 0
 		}
-		self.sigs_dup.insert(byte_key, true);
-		let sig_info = SignatureInfo {
-			bytes,
-			masks,
-			object: val
-		};
-		self.add_choice(sig_info, Rc::clone(&self.base_node));
 	}
 
-	/// Check if a signature is in the search tree.
-	pub fn is_signature(&self, bytes: Vec<u8>, offset: Option<i32>) -> bool {
-		self.get_signature(bytes, offset).is_some()
+	/// Get the number of signatures in the tree, disabled ones included.
+	pub fn len(&self) -> usize {
+		self.signatures.len()
 	}
 
-	/// Get the object associated with a signature in the search tree.
-	pub fn get_signature(&self, bytes: Vec<u8>, offset: Option<i32>) -> Option<T> {
-		let offset = offset.unwrap_or_default();
-		let mut matches = vec![];
-		let mut nn_node = Some(Rc::clone(&self.base_node));
-		loop {
-			if let Some(node) = nn_node {
-				let node = node.borrow();
-				let (depth, sigs, choices, term) = (&node.depth, &node.subtree_signatures, &node.choices, &node.term);
-				matches.append(&mut term.clone());
-				// Once we get down to one sig, there are no more branches,
-				// just check the byte sequence.
-				if sigs.len() == 1 {
-					let (sbytes, smasks) = (&sigs[0].bytes, &sigs[0].masks);
-					let mut is_match = true;
-					for i in (*depth as usize)..sbytes.len() {
-						let real_off = offset + i as i32;
-						// We still have pieces of the signature left, but we're out of bytes
-						if real_off >= bytes.len() as i32 {
-							is_match = false;
-							break;
-						}
-						let masked = bytes[real_off as usize] & smasks[i];
-						if masked != sbytes[i] {
-							is_match = false;
-							break;
-						}
-					}
-					if is_match {
-						matches.push(sigs[0].clone());
-					}
-					break;
-				}
-				// There are still more choices to make, keep on truckin'
-				nn_node = None;
-				for sig in sigs.iter() {
-					let (sbytes, smasks) = (&sig.bytes, &sig.masks);
-					if (offset + *depth) >= bytes.len() as i32 {
-						continue
-					}
-					// We've reached the end of the signature, Just mask the rest
-					let masked = bytes[(offset + *depth) as usize] & smasks[*depth as usize];
-					if masked == sbytes[*depth as usize] {
-						// FIXME: Find the *best* winner! Because of masking.
-						nn_node = choices[masked as usize].as_ref().map(Rc::clone);
-						break
-					}
-				}
-				// We failed to make our next choice
-				if nn_node.is_none() {
-					break
-				}
+	/// Check whether the tree has no signatures.
+	pub fn is_empty(&self) -> bool {
+		self.signatures.is_empty()
+	}
+
+	/// Measure the tree: its node count, depth, average branching factor and approximate heap
+	/// usage, for monitoring memory while loading large signature sets.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// tree.add_signature(vec![0x55, 0x89, 0xe5], None, 2);
+	/// let stats = tree.stats();
+	/// assert_eq!((stats.signatures, stats.nodes, stats.max_depth), (2, 4, 2));
+	/// assert_eq!(stats.average_branching, 1.5);
+	/// assert!(stats.heap_bytes > 0);
+	/// ```
+	pub fn stats(&self) -> TreeStats {
+		use std::mem::size_of;
+		let (mut children, mut parents, mut max_depth) = (0, 0, 0);
+		let mut heap_bytes = self.nodes.capacity() * size_of::<TreeNode>()
+			+ self.signatures.capacity() * size_of::<SignatureInfo<T>>()
+			+ self.sigs_dup.capacity() * size_of::<(Vec<u8>, Mask)>();
+		for node in &self.nodes {
+			let count = node.choices.len() + usize::from(node.wildcard.is_some());
+			if count > 0 {
+				children += count;
+				parents += 1;
 			}
+			max_depth = max_depth.max(node.depth as usize);
+			heap_bytes += (node.subtree_signatures.capacity() + node.term.capacity()) * size_of::<SignatureIndex>()
+				+ node.choices.heap_size();
 		}
-		return if matches.is_empty() {
-			None
-		} else {
-			matches.sort_by(|a, b| b.bytes.len().cmp(&a.bytes.len()));
-			matches.first().map(|x| x.object.clone())
+		for sig in &self.signatures {
+			heap_bytes += sig.bytes.capacity() + sig.masks.heap_size();
+			if let Some(provenance) = &sig.provenance {
+				heap_bytes += size_of::<provenance::Provenance>() + provenance.text.capacity();
+				heap_bytes += provenance.rule.as_ref().map_or(0, String::capacity);
+			}
+			heap_bytes += sig.target.as_ref().map_or(0, |_| size_of::<target::Target>());
+			heap_bytes += sig.constraints.capacity() * size_of::<constraint::Constraint>();
 		}
+		// The duplicate index holds its own copy of every signature.
+		heap_bytes += self.sigs_dup.iter().map(|(bytes, masks)| bytes.capacity() + masks.heap_size()).sum::<usize>();
+		TreeStats {
+			signatures: self.signatures.len(),
+			nodes: self.nodes.len(),
+			max_depth,
+			average_branching: if parents == 0 { 0.0 } else { children as f64 / parents as f64 },
+			heap_bytes
+		}
+	}
+
+	/// Get the length of the longest signature in the tree, which is the most input a single
+	/// match can cover. Consumers scanning a stream in chunks must carry over the last
+	/// `max_signature_length() - 1` bytes of each chunk so matches spanning chunk boundaries are
+	/// still found.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// assert_eq!(tree.max_signature_length(), 0);
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// tree.add_signature(vec![0xc3], None, 2);
+	/// assert_eq!(tree.max_signature_length(), 3);
+	/// ```
+	pub fn max_signature_length(&self) -> usize {
+		self.max_signature_length
+	}
+
+	/// Get how overlapping matches are resolved.
+	pub fn overlap_policy(&self) -> OverlapPolicy {
+		self.overlap_policy
+	}
+
+	/// Set how overlapping matches are resolved when a shorter signature is a prefix of a longer
+	/// one and both match. By default the longest signature wins (`OverlapPolicy::PreferDeepest`).
+	/// ```rust
+	/// use dectree_rs::{OverlapPolicy, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, 1);
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 2);
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(2));
+	/// tree.set_overlap_policy(OverlapPolicy::PreferTerminal);
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(1));
+	/// tree.set_overlap_policy(OverlapPolicy::ReportAll);
+	/// assert_eq!(tree.get_signatures([0x55, 0x8b, 0xec], None), vec![2, 1]);
+	/// ```
+	pub fn set_overlap_policy(&mut self, overlap_policy: OverlapPolicy) {
+		self.overlap_policy = overlap_policy;
+	}
+
+	/// Get how signatures already in the tree are handled when they are added again.
+	pub fn duplicate_policy(&self) -> DuplicatePolicy {
+		self.duplicate_policy
+	}
+
+	/// Set how signatures already in the tree, with the same bytes and masks once canonicalized,
+	/// are handled when they are added again. By default they are skipped and reported as
+	/// `DectreeError::Duplicate` (`DuplicatePolicy::Error`).
+	/// ```rust
+	/// use dectree_rs::{DuplicatePolicy, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, "frame").unwrap();
+	/// tree.set_duplicate_policy(DuplicatePolicy::Replace);
+	/// assert_eq!(tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, "prologue"), Some(id));
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some("prologue"));
+	/// ```
+	pub fn set_duplicate_policy(&mut self, duplicate_policy: DuplicatePolicy) {
+		self.duplicate_policy = duplicate_policy;
+	}
+
+	/// Check whether signatures are staged as they are added.
+	pub fn staging(&self) -> bool {
+		self.staging
+	}
+
+	/// Set whether signatures are staged as they are added, so a feed update can be soak-tested
+	/// before it is trusted. Staged signatures never affect the results of the tree's queries, or
+	/// of the modules built on them; their would-be matches are reported apart by `scan_staged()`
+	/// until `promote_staged()` makes them primary. A staged signature with the same pattern as one
+	/// already stored is added as a signature of its own, whatever the `duplicate_policy()`.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, "push");
+	/// tree.set_staging(true);
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, "frame");
+	/// assert_eq!(tree.scan([0x55, 0x8b, 0xec]), vec![(0, "push")]);
+	/// assert_eq!(tree.scan_staged([0x55, 0x8b, 0xec]).iter().map(|found| found.object).collect::<Vec<_>>(), vec!["frame"]);
+	/// tree.set_staging(false);
+	/// assert_eq!(tree.promote_staged(), 1);
+	/// assert_eq!(tree.scan([0x55, 0x8b, 0xec]), vec![(0, "frame")]);
+	/// ```
+	pub fn set_staging(&mut self, staging: bool) {
+		self.staging = staging;
+	}
+
+	/// Get the minimum number of effective bytes a signature needs to be added to the tree.
+	pub fn min_signature_length(&self) -> usize {
+		self.min_signature_length
+	}
+
+	/// Set the minimum number of effective bytes a signature needs to be added to the tree, as a
+	/// guardrail against loading short signatures that would match almost anything. Effective
+	/// bytes are the ones whose mask isn't `0x00`. This only affects signatures added afterwards.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.set_min_signature_length(4);
+	/// assert!(!tree.add_signature(vec![0x4d, 0x5a], None, 1));
+	/// assert!(tree.add_signature(vec![0x55, 0x8b, 0xec, 0x83], None, 2));
+	/// ```
+	pub fn set_min_signature_length(&mut self, min_signature_length: usize) {
+		self.min_signature_length = min_signature_length;
+	}
+
+	/// Get how signatures ending in fully-wildcarded bytes are treated.
+	pub fn wildcard_tail(&self) -> WildcardTail {
+		self.wildcard_tail
+	}
+
+	/// Set how signatures ending in fully-wildcarded bytes are treated. By default the trailing
+	/// wildcards are required (`WildcardTail::Require`), meaning the input must extend past them.
+	/// With `WildcardTail::Trim` they are dropped when a signature is added, so a match near the
+	/// end of the input still counts. This only affects signatures added afterwards.
+	/// ```rust
+	/// use dectree_rs::{SignatureDecisionTree, WildcardTail};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.set_wildcard_tail(WildcardTail::Trim);
+	/// tree.add_signature(vec![0x55, 0x8b, 0x00, 0x00], Some(vec![0xff, 0xff, 0x00, 0x00]), 1);
+	/// assert_eq!(tree.get_signature(vec![0x55, 0x8b], None), Some(1));
+	/// ```
+	pub fn set_wildcard_tail(&mut self, wildcard_tail: WildcardTail) {
+		self.wildcard_tail = wildcard_tail;
+	}
+
+	/// Add a choice to the search tree. Only signature indices are copied into the nodes along the
+	/// way, so inserting never clones a signature or a node's lists.
+	fn add_choice(&mut self, signature: SignatureIndex, node_index: usize) {
+		let mut node_info_list = vec![(node_index, signature)];
+		// Workaround to avoid recursion
+		while let Some((index, sig)) = node_info_list.pop() {
+			self.split_edge(index, sig);
+			let sig_info = &self.signatures[sig as usize];
+			let node = &mut self.nodes[index];
+			let depth = node.depth;
+			if sig_info.bytes.len() as i32 <= depth {
+				node.term.push(sig);
+				continue;
+			}
+			let choice = sig_info.choice_at(depth as usize);
+			let siglen = node.subtree_signatures.len();
+			node.subtree_signatures.push(sig);
+			// If one sig is [85, 139, 236] and another is [85, 139, 236, 232, 144], then
+			// we're gonna panic without this check
+			if siglen == 0 {
+				// If it has no sigs, we need to add a level
+				// modify the next node
+				continue;
+			} else if siglen == 1 {
+				// If it has one already, we *both* need to add another level
+				// (because if it is the only one, it thought it was last choice)
+				let (first, movable) = (node.subtree_signatures[0], index != 0 && node.term.is_empty());
+				let shared = self.signature(first).shared_choices(self.signature(sig), depth as usize);
+				if movable && shared > 0 {
+					// Nothing ends at this node, so it moves down past the choices both share,
+					// and both are added to it again there.
+					self.nodes[index].subtree_signatures.clear();
+					self.extend_edge(index, shared);
+					node_info_list.extend([(index, sig), (index, first)]);
+					continue
+				}
+				let first_choice = self.signature(first).choice_at(depth as usize);
+				let nn_node = self.get_node(index, first_choice);
+				if first_choice == choice {
+					// Both go down the new node, which can skip the choices they share.
+					let shared = self.signature(first).shared_choices(self.signature(sig), depth as usize + 1);
+					self.extend_edge(nn_node, shared);
+				}
+				node_info_list.push((nn_node, first));
+			}
+			// This is a choice node now, keep on choosing...
+			let nn_node = self.get_node(index, choice);
+			node_info_list.push((nn_node, sig));
+		}
+	}
+
+	/// Split the edge leading to the node at `index` where the signature at `signature`, which is
+	/// about to be added to the node, stops sharing its choices. The node keeps its place and moves
+	/// to the split, and its former contents move to a new child below it.
+	fn split_edge(&mut self, index: usize, signature: SignatureIndex) {
+		let (node, sig) = (&self.nodes[index], self.signature(signature));
+		let Some(representative) = node.signatures().next().map(|first| self.signature(first)) else {
+			return
+		};
+		let split = node.edge().find(|&depth| sig.bytes.len() <= depth || sig.choice_at(depth) != representative.choice_at(depth));
+		let Some(split) = split else {
+			return
+		};
+		let (start, choice) = (node.edge().start, representative.choice_at(split));
+		let mut rest = std::mem::take(&mut self.nodes[index]);
+		rest.skipped = rest.depth - split as i32 - 1;
+		let mut passing = rest.signatures().collect::<Vec<_>>();
+		passing.sort_unstable();
+		let child = u32::try_from(self.nodes.len()).ok().and_then(NodeIndex::new).expect("the tree has more nodes than it can index");
+		self.nodes.push(rest);
+		let node = &mut self.nodes[index];
+		node.depth = split as i32;
+		node.skipped = (split - start) as i32;
+		node.subtree_signatures = passing;
+		match choice {
+			Some(choice) => node.choices.insert(choice, child),
+			None => node.wildcard = Some(child)
+		}
+	}
+
+	/// Move the node at `index`, which holds no signatures yet, `shared` bytes deeper, so the edge
+	/// leading to it carries them.
+	fn extend_edge(&mut self, index: usize, shared: usize) {
+		let node = &mut self.nodes[index];
+		node.depth += shared as i32;
+		node.skipped += shared as i32;
+	}
+
+	/// Build the nodes below the root for every signature in the list, top-down. The signatures
+	/// are sorted by the choices they make, so the ones sharing a node are next to each other and
+	/// each node is created once, with its signature lists already complete. The lists keep the
+	/// order of the signature list, so the nodes come out just as `add_choice()` would leave them.
+	fn build_nodes(&mut self) {
+		let signatures = &self.signatures;
+		let mut order = (0..signatures.len() as SignatureIndex).collect::<Vec<_>>();
+		order.sort_by_cached_key(|&index| {
+			let sig = &signatures[index as usize];
+			(0..sig.bytes.len()).map(|depth| sig.choice_at(depth)).collect::<Vec<_>>()
+		});
+		// Each pending node is paired with the range of `order` holding the signatures that reach
+		// it. Signatures ending at the node's depth sort before the ones going on.
+		let mut pending = vec![(0, 0, order.len())];
+		while let Some((index, start, end)) = pending.pop() {
+			let depth = self.nodes[index].depth as usize;
+			let ending = order[start..end].iter().take_while(|&&sig| self.signature(sig).bytes.len() <= depth).count();
+			let mut term = order[start..start + ending].to_vec();
+			let mut subtree_signatures = order[start + ending..end].to_vec();
+			term.sort_unstable();
+			subtree_signatures.sort_unstable();
+			let node = &mut self.nodes[index];
+			node.term = term;
+			node.subtree_signatures = subtree_signatures;
+			// A single signature is checked byte by byte from here, without any more nodes.
+			if end - start - ending < 2 {
+				continue
+			}
+			let mut first = start + ending;
+			while first < end {
+				let choice = self.signature(order[first]).choice_at(depth);
+				let last = first + order[first..end].iter().take_while(|&&sig| self.signature(sig).choice_at(depth) == choice).count();
+				let child = self.get_node(index, choice);
+				if last - first > 1 {
+					// The signatures in between share every choice the first and last share.
+					let shared = self.signature(order[first]).shared_choices(self.signature(order[last - 1]), depth + 1);
+					self.extend_edge(child, shared);
+				}
+				pending.push((child, first, last));
+				first = last;
+			}
+		}
+	}
+
+	/// Get the signature at `index` in the signature list.
+	fn signature(&self, index: SignatureIndex) -> &SignatureInfo<T> {
+		&self.signatures[index as usize]
+	}
+
+	/// Chose, (and or initialize) a sub node. A `choice` of `None` is the wildcard choice.
+	fn get_node(&mut self, parent: usize, choice: Option<u8>) -> usize {
+		if let Some(child) = self.nodes[parent].child(choice) {
+			return child
+		}
+		let index = self.nodes.len();
+		let child = u32::try_from(index).ok().and_then(NodeIndex::new).expect("the tree has more nodes than it can index");
+		let nn_node = TreeNode {
+			depth: self.nodes[parent].depth + 1,
+			..Default::default()
+		};
+		self.nodes.push(nn_node);
+		let parent = &mut self.nodes[parent];
+		match choice {
+			Some(choice) => parent.choices.insert(choice, child),
+			None => parent.wildcard = Some(child)
+		}
+		index
+	}
+
+	/// Add a signature to the search tree.  If masks goes unspecified, it will be
+	/// assumed to be all ones `vec![0xff; bytes.len()]`. Bits outside the masks are ignored, so a
+	/// wildcard byte may be written as any value. Trailing fully-wildcarded bytes are handled
+	/// according to `wildcard_tail()`.
+	/// 
+	/// `val` is the object to get back with `tree.get_signature()`. Every signature carries one, so
+	/// a match is never confused with a default value; use `()` when only the match itself matters.
+	///
+	/// Returns whether the signature was added. Empty signatures, signatures whose masks don't
+	/// line up with their bytes and signatures with fewer effective bytes than
+	/// `min_signature_length()` are skipped. Duplicates of a signature already in the tree are
+	/// handled as `duplicate_policy()` says, and skipped by default. Use `try_add_signature()` to
+	/// find out why a signature was skipped.
+	pub fn add_signature(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: T) -> bool {
+		self.try_add_signature(bytes, masks, val).is_ok()
+	}
+
+	/// Add a signature to the search tree like `add_signature()`, and return the id it was given,
+	/// or `None` if it was skipped. The id can be used to switch the signature off and on again
+	/// with `set_enabled()`.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 1).unwrap();
+	/// assert_eq!(tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 2), None);
+	/// assert_eq!(tree.get_match([0x55, 0x8b, 0xec], None).map(|found| found.id), Some(id));
+	/// ```
+	pub fn add_signature_with_id(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: T) -> Option<SignatureId> {
+		self.try_add_signature(bytes, masks, val).ok()
+	}
+
+	/// Add a signature to the search tree like `add_signature()`, and return the id it was given,
+	/// or the reason it couldn't be added.
+	/// ```rust
+	/// use dectree_rs::{DectreeError, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// assert!(tree.try_add_signature(vec![0x55, 0x8b, 0xec], None, 1).is_ok());
+	/// assert_eq!(tree.try_add_signature(vec![0x55, 0x8b, 0xec], None, 2), Err(DectreeError::Duplicate));
+	/// assert_eq!(tree.try_add_signature(vec![0x55, 0x8b], Some(vec![0xff]), 3), Err(DectreeError::LengthMismatch { bytes: 2, masks: 1 }));
+	/// assert_eq!(tree.try_add_signature(vec![], None, 4), Err(DectreeError::EmptyPattern));
+	/// ```
+	pub fn try_add_signature(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: T) -> Result<SignatureId, DectreeError> {
+		let (index, new) = self.store_signature(bytes, masks, val)?;
+		if new {
+			self.add_choice(index, 0);
+		}
+		Ok(SignatureId(index))
+	}
+
+	/// Check a signature and add it to the signature list, without adding it to the nodes, or
+	/// handle it as `duplicate_policy()` says if it's already there. Returns its index and whether
+	/// it was added, or the reason it was skipped.
+	fn store_signature(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: T) -> Result<(SignatureIndex, bool), DectreeError> {
+		if let Some(masks) = masks.as_ref().filter(|masks| masks.len() != bytes.len()) {
+			return Err(DectreeError::LengthMismatch { bytes: bytes.len(), masks: masks.len() })
+		}
+		// Equivalent signatures are stored identically, so duplicates are caught however they
+		// were written.
+		let (bytes, masks) = pattern::canonicalize(bytes, masks, self.wildcard_tail);
+		if bytes.is_empty() {
+			return Err(DectreeError::EmptyPattern)
+		}
+		let masks = masks.map_or(Mask::Exact, Mask::new);
+		let effective_len = (0..bytes.len()).filter(|&i| masks.get(i) != 0x00).count();
+		if effective_len < self.min_signature_length {
+			return Err(DectreeError::TooShort { effective: effective_len, minimum: self.min_signature_length })
+		}
+		// Detect duplicate additions...
+		let byte_key = (bytes.clone(), masks.clone());
+		if !self.sigs_dup.insert(byte_key) {
+			// A staged duplicate is a signature of its own, so the one already stored is untouched.
+			let policy = if self.staging { DuplicatePolicy::Append } else { self.duplicate_policy };
+			let existing = match policy {
+				DuplicatePolicy::Error => return Err(DectreeError::Duplicate),
+				DuplicatePolicy::Append => None,
+				DuplicatePolicy::Ignore | DuplicatePolicy::Replace => self.find_signature(&bytes, &masks)
+			};
+			if let Some(index) = existing {
+				if policy == DuplicatePolicy::Replace {
+					self.signatures[index as usize].object = val;
+				}
+				return Ok((index, false))
+			}
+		}
+		self.max_signature_length = self.max_signature_length.max(bytes.len());
+		for byte in 0..=0xff {
+			if byte & masks.get(0) == bytes[0] {
+				self.first_bytes.insert(byte);
+			}
+		}
+		let index = SignatureIndex::try_from(self.signatures.len()).expect("the tree has more signatures than it can index");
+		self.signatures.push(SignatureInfo {
+			bytes,
+			masks,
+			object: val,
+			enabled: true,
+			provenance: None,
+			target: None,
+			constraints: Vec::new(),
+			staged: self.staging
+		});
+		Ok((index, true))
+	}
+
+	/// Find the signature stored with exactly `bytes` and `masks`, by following the choices it
+	/// makes down the tree. Where the pattern was added several times, the first one is returned.
+	fn find_signature(&self, bytes: &[u8], masks: &Mask) -> Option<SignatureIndex> {
+		let mut node = &self.nodes[0];
+		// While a bulk build is still storing signatures, only the signature list knows them all.
+		if node.subtree_signatures.len() + node.term.len() < self.signatures.len() {
+			let position = self.signatures.iter().position(|sig| sig.bytes == bytes && sig.masks == *masks)?;
+			return SignatureIndex::try_from(position).ok()
+		}
+		loop {
+			let depth = node.depth as usize;
+			if depth < bytes.len() && node.subtree_signatures.len() > 1 {
+				let choice = Some(bytes[depth]).filter(|_| masks.get(depth) != 0x00);
+				if let Some(child) = node.child(choice) {
+					node = &self.nodes[child];
+					continue
+				}
+			}
+			return node.signatures()
+				.filter(|&index| self.signature(index).bytes == bytes && self.signature(index).masks == *masks)
+				.min()
+		}
+	}
+
+	/// Find the signature stored with `bytes` and `masks`, checking and canonicalizing them as
+	/// `try_add_signature()` does, so equivalent patterns are found however they are written.
+	fn locate_signature(&self, bytes: Vec<u8>, masks: Option<Vec<u8>>) -> Result<Option<SignatureIndex>, DectreeError> {
+		if let Some(masks) = masks.as_ref().filter(|masks| masks.len() != bytes.len()) {
+			return Err(DectreeError::LengthMismatch { bytes: bytes.len(), masks: masks.len() })
+		}
+		let (bytes, masks) = pattern::canonicalize(bytes, masks, self.wildcard_tail);
+		if bytes.is_empty() {
+			return Err(DectreeError::EmptyPattern)
+		}
+		let key = (bytes, masks.map_or(Mask::Exact, Mask::new));
+		match self.sigs_dup.contains(&key) {
+			true => Ok(self.find_signature(&key.0, &key.1)),
+			false => Ok(None)
+		}
+	}
+
+	/// Replace the object of the signature stored with exactly `bytes` and `masks`, keeping
+	/// everything else about it (its id, whether it is enabled, its provenance). Returns the object
+	/// it replaced, or `None` if no such signature is stored, in which case `val` is dropped. Where
+	/// the pattern was added several times, the first one is updated.
+	/// ```rust
+	/// use dectree_rs::{DectreeError, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, "frame");
+	/// assert_eq!(tree.update_value(vec![0x55, 0x8b, 0xec], None, "prologue"), Ok(Some("frame")));
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some("prologue"));
+	/// assert_eq!(tree.update_value(vec![0x55, 0x8b], None, "partial"), Ok(None));
+	/// assert_eq!(tree.update_value(vec![0x55], Some(vec![]), "invalid"), Err(DectreeError::LengthMismatch { bytes: 1, masks: 0 }));
+	/// ```
+	pub fn update_value(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: T) -> Result<Option<T>, DectreeError> {
+		let index = self.locate_signature(bytes, masks)?;
+		Ok(index.map(|index| std::mem::replace(&mut self.signatures[index as usize].object, val)))
+	}
+
+	/// Check whether a signature with exactly `bytes` and `masks` is stored in the tree, as opposed
+	/// to `is_signature()`, which checks whether some signature matches `bytes`. Patterns are
+	/// compared as `try_add_signature()` compares duplicates, so equivalent masks are equal.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x00, 0xec], Some(vec![0xff, 0x00, 0xff]), 1);
+	/// assert!(tree.contains_pattern(&[0x55, 0x42, 0xec], Some(&[0xff, 0x00, 0xff])));
+	/// assert!(!tree.contains_pattern(&[0x55, 0x42, 0xec], None));
+	/// assert!(tree.is_signature([0x55, 0x42, 0xec], None));
+	/// ```
+	pub fn contains_pattern(&self, bytes: &[u8], masks: Option<&[u8]>) -> bool {
+		self.get_by_pattern(bytes, masks).is_some()
+	}
+
+	/// Get a reference to the object of the signature stored with exactly `bytes` and `masks`, or
+	/// `None` if there is none or the pattern isn't valid. Where the pattern was added several
+	/// times, the first one's object is returned.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, "push");
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, "frame");
+	/// assert_eq!(tree.get_by_pattern(&[0x55, 0x8b], None), Some(&"push"));
+	/// assert_eq!(tree.get_by_pattern(&[0x55, 0x8b, 0xec, 0x83], None), None);
+	/// ```
+	pub fn get_by_pattern(&self, bytes: &[u8], masks: Option<&[u8]>) -> Option<&T> {
+		let index = self.locate_signature(bytes.to_vec(), masks.map(<[u8]>::to_vec)).ok()??;
+		Some(&self.signature(index).object)
+	}
+
+	/// Switch the signature with the id `id` on or off. A disabled signature stays in the tree but
+	/// its matches are no longer reported, so rules can be toggled instantly without rebuilding
+	/// anything. Returns whether the tree has a signature with that id.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, 1);
+	/// let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 2).unwrap();
+	/// assert!(tree.set_enabled(id, false));
+	/// assert!(!tree.is_enabled(id));
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(1));
+	/// tree.set_enabled(id, true);
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(2));
+	/// ```
+	pub fn set_enabled(&mut self, id: SignatureId, enabled: bool) -> bool {
+		match self.signatures.get_mut(id.0 as usize) {
+			Some(sig) => {
+				sig.enabled = enabled;
+				true
+			},
+			None => false
+		}
+	}
+
+	/// Check if the signature with the id `id` is in the tree and enabled.
+	pub fn is_enabled(&self, id: SignatureId) -> bool {
+		self.signatures.get(id.0 as usize).is_some_and(|sig| sig.enabled)
+	}
+
+	/// Stage the signature with the id `id`, or make it primary again. Returns whether the tree
+	/// has a signature with that id.
+	pub fn set_staged(&mut self, id: SignatureId, staged: bool) -> bool {
+		match self.signatures.get_mut(id.0 as usize) {
+			Some(sig) => {
+				sig.staged = staged;
+				true
+			},
+			None => false
+		}
+	}
+
+	/// Check if the signature with the id `id` is in the tree and staged.
+	pub fn is_staged(&self, id: SignatureId) -> bool {
+		self.signatures.get(id.0 as usize).is_some_and(|sig| sig.staged)
+	}
+
+	/// Make every staged signature primary. Returns the number of signatures promoted.
+	pub fn promote_staged(&mut self) -> usize {
+		self.signatures.iter_mut()
+			.filter(|sig| sig.staged)
+			.map(|sig| sig.staged = false)
+			.count()
+	}
+
+	/// Record where the signature with the id `id` was imported from, replacing anything recorded
+	/// before. Returns whether the tree has a signature with that id.
+	pub fn set_provenance(&mut self, id: SignatureId, provenance: provenance::Provenance) -> bool {
+		match self.signatures.get_mut(id.0 as usize) {
+			Some(sig) => {
+				sig.provenance = Some(provenance);
+				true
+			},
+			None => false
+		}
+	}
+
+	/// Get where the signature with the id `id` was imported from, if that was recorded. Signatures
+	/// added from hex strings and GUIDs record their original text; importers of rule files also
+	/// record the file, feed and line.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	/// use dectree_rs::provenance::ImportFormat;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature_str("55 8B ?? EC", 1).unwrap();
+	/// let found = tree.get_match([0x55, 0x8b, 0x42, 0xec], None).unwrap();
+	/// let provenance = tree.provenance(found.id).unwrap();
+	/// assert_eq!((provenance.format, provenance.text.as_str()), (ImportFormat::HexString, "55 8B ?? EC"));
+	/// ```
+	pub fn provenance(&self, id: SignatureId) -> Option<&provenance::Provenance> {
+		self.signatures.get(id.0 as usize)?.provenance.as_ref()
+	}
+
+	/// Iterate over every signature in the tree, disabled ones included, in the order they were
+	/// added. Each is yielded as its bytes (already masked, as stored), one mask per byte and its
+	/// object. Masks are only borrowed when the tree stores them byte by byte; exact and
+	/// run-length encoded masks are expanded.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature_str("55 8B ?? EC", "frame").unwrap();
+	/// tree.add_signature(vec![0xc3], None, "ret");
+	/// let signatures = tree.iter().map(|(bytes, masks, object)| (bytes.to_vec(), masks.to_vec(), *object)).collect::<Vec<_>>();
+	/// assert_eq!(signatures, vec![
+	///     (vec![0x55, 0x8b, 0x00, 0xec], vec![0xff, 0xff, 0x00, 0xff], "frame"),
+	///     (vec![0xc3], vec![0xff], "ret")
+	/// ]);
+	/// ```
+	pub fn iter(&self) -> Iter<'_, T> {
+		Iter { signatures: self.signatures.iter() }
+	}
+
+	/// Limit the signature with the id `id` to inputs compatible with `target`, replacing any
+	/// earlier target. The signature is then skipped by queries such as `scan_for()` that describe
+	/// an incompatible input, and still reported by queries that describe none. Returns whether the
+	/// tree has a signature with that id.
+	pub fn set_target(&mut self, id: SignatureId, target: target::Target) -> bool {
+		match self.signatures.get_mut(id.0 as usize) {
+			Some(sig) => {
+				sig.target = Some(target);
+				true
+			},
+			None => false
+		}
+	}
+
+	/// Get the kind of input the signature with the id `id` is limited to, if it was limited.
+	pub fn target(&self, id: SignatureId) -> Option<&target::Target> {
+		self.signatures.get(id.0 as usize)?.target.as_ref()
+	}
+
+	/// Attach `constraints` to the signature with the id `id`, replacing any earlier ones. The
+	/// signature then only matches where every constraint holds; see the `constraint` module.
+	/// Returns whether the constraints were attached: not if the tree has no signature with that
+	/// id, or if a constraint reaches past the end of the signature.
+	pub fn set_constraints(&mut self, id: SignatureId, constraints: Vec<constraint::Constraint>) -> bool {
+		match self.signatures.get_mut(id.0 as usize) {
+			Some(sig) if constraints.iter().all(|constraint| constraint.reach() <= sig.bytes.len()) => {
+				sig.constraints = constraints;
+				true
+			},
+			_ => false
+		}
+	}
+
+	/// Get the constraints attached to the signature with the id `id`, if any.
+	pub fn constraints(&self, id: SignatureId) -> &[constraint::Constraint] {
+		self.signatures.get(id.0 as usize).map_or(&[], |sig| &sig.constraints)
+	}
+
+	/// Add a signature written as an IDA/YARA-style hex string, such as `"55 8B ?? EC 83"`, to the
+	/// search tree. See the `parser` module for the accepted syntax. Returns whether the signature
+	/// was added, as `add_signature()` does, or the reason the pattern couldn't be parsed. The
+	/// pattern is recorded as the signature's provenance.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// assert_eq!(tree.add_signature_str("55 8B ?? EC", 1), Ok(true));
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0x42, 0xec], None), Some(1));
+	/// assert!(tree.add_signature_str("55 8B ?? E", 2).is_err());
+	/// ```
+	pub fn add_signature_str(&mut self, pattern: &str, val: T) -> Result<bool, parser::ParseError> {
+		match self.try_add_signature_str(pattern, val) {
+			Ok(_) => Ok(true),
+			Err(DectreeError::Parse(error)) => Err(error),
+			Err(_) => Ok(false)
+		}
+	}
+
+	/// Add a signature written as a hex string like `add_signature_str()`, and return the id it was
+	/// given, or the reason it couldn't be parsed or added.
+	/// ```rust
+	/// use dectree_rs::{DectreeError, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// assert!(tree.try_add_signature_str("55 8B ?? EC", 1).is_ok());
+	/// assert!(matches!(tree.try_add_signature_str("55 8B ?? E", 2), Err(DectreeError::Parse(_))));
+	/// ```
+	pub fn try_add_signature_str(&mut self, pattern: &str, val: T) -> Result<SignatureId, DectreeError> {
+		let (bytes, masks) = parser::parse_hex_pattern(pattern)?;
+		let id = self.try_add_signature(bytes, Some(masks), val)?;
+		self.set_provenance(id, provenance::Provenance::new(provenance::ImportFormat::HexString, pattern));
+		Ok(id)
+	}
+
+	/// Add a signature assembled with a `pattern::PatternBuilder` to the search tree. Returns
+	/// whether the signature was added, as `add_signature()` does.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	/// use dectree_rs::pattern::PatternBuilder;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// let pattern = PatternBuilder::new().byte(0xb8).u32_le(0xdeadbeef).build().unwrap();
+	/// assert!(tree.add_pattern(pattern, 1));
+	/// assert_eq!(tree.get_signature([0xb8, 0xef, 0xbe, 0xad, 0xde], None), Some(1));
+	/// ```
+	pub fn add_pattern(&mut self, pattern: pattern::Pattern, val: T) -> bool {
+		let (bytes, masks) = pattern.into_parts();
+		self.add_signature(bytes, Some(masks), val)
+	}
+
+	/// Add the in-memory bytes of a GUID written in registry format, such as
+	/// `"{6b29fc40-ca47-1067-b31d-00dd010662da}"`, to the search tree. See `parser::parse_guid()` for
+	/// the byte order. Returns whether the signature was added, as `add_signature()` does, or the
+	/// reason the GUID couldn't be parsed. The GUID is recorded as the signature's provenance.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// assert_eq!(tree.add_guid("6b29fc40-ca47-1067-b31d-00dd010662da", 1), Ok(true));
+	/// let memory = [0x40, 0xfc, 0x29, 0x6b, 0x47, 0xca, 0x67, 0x10, 0xb3, 0x1d, 0x00, 0xdd, 0x01, 0x06, 0x62, 0xda];
+	/// assert_eq!(tree.get_signature(memory, None), Some(1));
+	/// ```
+	pub fn add_guid(&mut self, guid: &str, val: T) -> Result<bool, parser::ParseError> {
+		let bytes = parser::parse_guid(guid)?;
+		let id = self.add_signature_with_id(bytes.to_vec(), None, val);
+		if let Some(id) = id {
+			self.set_provenance(id, provenance::Provenance::new(provenance::ImportFormat::Guid, guid));
+		}
+		Ok(id.is_some())
+	}
+
+	/// Add a `u32` constant, stored in little endian byte order, to the search tree. Returns
+	/// whether the signature was added, as `add_signature()` does.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_u32_le(0xdeadbeef, 1);
+	/// assert_eq!(tree.get_signature([0xef, 0xbe, 0xad, 0xde], None), Some(1));
+	/// ```
+	pub fn add_u32_le(&mut self, constant: u32, val: T) -> bool {
+		self.add_signature(constant.to_le_bytes().to_vec(), None, val)
+	}
+
+	/// Add a `u32` constant, stored in big endian byte order, to the search tree. Returns whether
+	/// the signature was added, as `add_signature()` does.
+	pub fn add_u32_be(&mut self, constant: u32, val: T) -> bool {
+		self.add_signature(constant.to_be_bytes().to_vec(), None, val)
+	}
+
+	/// Add a `u64` constant, stored in little endian byte order, to the search tree. Returns
+	/// whether the signature was added, as `add_signature()` does.
+	pub fn add_u64_le(&mut self, constant: u64, val: T) -> bool {
+		self.add_signature(constant.to_le_bytes().to_vec(), None, val)
+	}
+
+	/// Add a `u64` constant, stored in big endian byte order, to the search tree. Returns whether
+	/// the signature was added, as `add_signature()` does.
+	pub fn add_u64_be(&mut self, constant: u64, val: T) -> bool {
+		self.add_signature(constant.to_be_bytes().to_vec(), None, val)
+	}
+
+	/// Add a signature to the search tree along with its endian-swapped variants. Each swap in
+	/// `swaps` is applied to both the bytes and the masks, and the result is stored with the same
+	/// object. Swaps that don't evenly divide the signature length are skipped.
+	/// ```rust
+	/// use dectree_rs::{EndianSwap, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature_with_swaps(vec![0xde, 0xad, 0xbe, 0xef], None, 1, &[EndianSwap::Swap32]);
+	/// assert_eq!(tree.get_signature(vec![0xef, 0xbe, 0xad, 0xde], None), Some(1));
+	/// ```
+	pub fn add_signature_with_swaps(&mut self, bytes: Vec<u8>, masks: Option<Vec<u8>>, val: T, swaps: &[EndianSwap]) {
+		let masks = masks.unwrap_or(vec![0xff; bytes.len()]);
+		let variants = swaps.iter()
+			.filter_map(|swap| Some((swap.apply(&bytes)?, swap.apply(&masks)?)))
+			.collect::<Vec<_>>();
+		self.add_signature(bytes, Some(masks), val.clone());
+		for (swapped_bytes, swapped_masks) in variants {
+			self.add_signature(swapped_bytes, Some(swapped_masks), val.clone());
+		}
+	}
+
+	/// Check if a signature is in the search tree. `bytes` can be anything that derefs to a byte
+	/// slice, so scan buffers can be queried without copying them.
+	pub fn is_signature(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> bool {
+		self.lookup_with(bytes.as_ref(), offset.unwrap_or_default(), |_| ()).is_some()
+	}
+
+	/// Get the object associated with a signature in the search tree. `bytes` can be anything
+	/// that derefs to a byte slice, so scan buffers can be queried without copying them.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let buffer = vec![0x90, 0x55, 0x8b, 0xec];
+	/// assert_eq!(tree.get_signature(&buffer, Some(1)), Some(1));
+	/// assert_eq!(tree.get_signature(&buffer[1..], None), Some(1));
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(1));
+	/// ```
+	pub fn get_signature(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Option<T> {
+		self.lookup(bytes.as_ref(), offset.unwrap_or_default())
+	}
+
+	/// Get a reference to the object associated with a signature in the search tree, like
+	/// `get_signature()` but without cloning it.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, vec!["frame".to_string()]);
+	/// assert_eq!(tree.get([0x55, 0x8b, 0xec], None).map(Vec::len), Some(1));
+	/// ```
+	pub fn get(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Option<&T> {
+		let index = self.select_index(bytes.as_ref(), offset.unwrap_or_default(), &mut Budget(None)).unwrap_or_default()?;
+		Some(&self.signature(index).object)
+	}
+
+	/// Get a mutable reference to the object associated with a signature in the search tree, so
+	/// it can be updated in place. The signature is chosen as `get()` chooses it.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 0u32);
+	/// if let Some(hits) = tree.get_mut([0x55, 0x8b, 0xec], None) {
+	///     *hits += 1;
+	/// }
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(1));
+	/// ```
+	pub fn get_mut(&mut self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Option<&mut T> {
+		let index = self.select_index(bytes.as_ref(), offset.unwrap_or_default(), &mut Budget(None)).unwrap_or_default()?;
+		Some(&mut self.signatures[index as usize].object)
+	}
+
+	/// Get the objects of the signatures matching `bytes` at `offset`. With
+	/// `OverlapPolicy::ReportAll` this is every matching signature, longest first (ties in the order
+	/// they were found); with the other policies it is at most the one signature they prefer.
+	pub fn get_signatures(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Vec<T> {
+		let (bytes, offset) = (bytes.as_ref(), offset.unwrap_or_default());
+		if self.overlap_policy != OverlapPolicy::ReportAll {
+			return self.lookup(bytes, offset).into_iter().collect()
+		}
+		let mut matches = Vec::new();
+		let _ = self.walk(bytes, offset, &mut Budget(None), Scope::ENABLED, |_, sig| {
+			matches.push((sig.bytes.len(), sig.object.clone()));
+			ControlFlow::Continue(())
+		});
+		matches.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+		matches.into_iter().map(|(_, val)| val).collect()
+	}
+
+	/// Get every signature matching `bytes` at `offset`, disabled ones included, longest first (ties
+	/// in the order they were found). Each match is flagged with whether its signature is enabled,
+	/// so what a disabled rule would catch can be reviewed before switching it back on.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, 1);
+	/// let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 2).unwrap();
+	/// tree.set_enabled(id, false);
+	/// let matches = tree.what_if_matches([0x55, 0x8b, 0xec], None);
+	/// assert_eq!(matches.iter().map(|found| (found.object, found.enabled)).collect::<Vec<_>>(), vec![(2, false), (1, true)]);
+	/// assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(1));
+	/// ```
+	pub fn what_if_matches(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Vec<Match<T>> {
+		let offset = offset.unwrap_or_default();
+		let mut matches = Vec::new();
+		let _ = self.walk(bytes.as_ref(), offset, &mut Budget(None), Scope { include_disabled: true, staged: false, target: None }, |index, sig| {
+			matches.push(sig.to_match(SignatureId(index), offset as usize));
+			ControlFlow::Continue(())
+		});
+		matches.sort_by_key(|found| std::cmp::Reverse(found.length));
+		matches
+	}
+
+	/// Find every signature hit in `buffer` like `scan_matches()`, but as if every disabled
+	/// signature were enabled, reporting all the signatures matching at each offset as
+	/// `what_if_matches()` does. Comparing the result against `scan_matches()` shows exactly what
+	/// re-enabling the disabled rules would change.
+	pub fn scan_what_if(&self, buffer: impl AsRef<[u8]>) -> Vec<Match<T>> {
+		let buffer = buffer.as_ref();
+		self.candidate_offsets(buffer)
+			.flat_map(|offset| self.what_if_matches(buffer, Some(offset as i32)))
+			.collect()
+	}
+
+	/// Get the longest signature matching `bytes` at `offset`, along with where it matched and
+	/// how many bytes it covers. This is the same lookup as `get_signature()` with the full details
+	/// of the match.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0x00], Some(vec![0xff, 0xff, 0x00]), 1);
+	/// let found = tree.get_match([0x90, 0x55, 0x8b, 0xec], Some(1)).unwrap();
+	/// assert_eq!((found.offset, found.length, found.object), (1, 3, 1));
+	/// assert_eq!(found.masks, vec![0xff, 0xff, 0x00]);
+	/// ```
+	pub fn get_match(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Option<Match<T>> {
+		let offset = offset.unwrap_or_default();
+		self.select_index(bytes.as_ref(), offset, &mut Budget(None))
+			.unwrap_or_default()
+			.map(|index| self.signature(index).to_match(SignatureId(index), offset as usize))
+	}
+
+	/// Get the objects associated with signatures anchored at each of `offsets` in `bytes`, such as
+	/// the function entry points from a symbol table. The results line up with `offsets`, with
+	/// `None` for offsets where nothing matched (including offsets past the end of `bytes`).
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let code = [0x55, 0x8b, 0xec, 0xc3, 0x55, 0x8b, 0xec];
+	/// assert_eq!(tree.get_signature_at_offsets(&code, &[0, 3, 4, 16]), vec![Some(1), None, Some(1), None]);
+	/// ```
+	pub fn get_signature_at_offsets(&self, bytes: impl AsRef<[u8]>, offsets: &[usize]) -> Vec<Option<T>> {
+		let bytes = bytes.as_ref();
+		offsets.iter().map(|&offset| self.lookup(bytes.get(offset..).unwrap_or_default(), 0)).collect()
+	}
+
+	/// Get the best signature hit anchored anywhere in `range` of `bytes`, for inputs where a
+	/// structure is known to begin roughly, but not exactly, at some offset. Signatures may run past
+	/// the end of `range`, but not past the end of `bytes`. The hit is chosen among the ones preferred
+	/// at each offset as `overlap_policy()` chooses at a single offset, with ties going to the
+	/// earliest offset. Offsets whose byte can't start any signature are skipped without walking the
+	/// tree. Returns the offset of the hit along with its object.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, "short");
+	/// tree.add_signature(vec![0x55, 0x89, 0xe5], None, "frame");
+	/// let code = [0x90, 0x55, 0x8b, 0x90, 0x55, 0x89, 0xe5];
+	/// assert_eq!(tree.get_signature_in_range(&code, 0..6), Some((4, "frame")));
+	/// assert_eq!(tree.get_signature_in_range(&code, 0..4), Some((1, "short")));
+	/// assert_eq!(tree.get_signature_in_range(&code, 2..4), None);
+	/// ```
+	pub fn get_signature_in_range(&self, bytes: impl AsRef<[u8]>, range: Range<usize>) -> Option<(usize, T)> {
+		let bytes = bytes.as_ref();
+		let prefer_terminal = self.overlap_policy == OverlapPolicy::PreferTerminal;
+		let mut best: Option<(usize, &SignatureInfo<T>)> = None;
+		let offsets = range.start.min(bytes.len())..range.end.min(bytes.len());
+		for offset in offsets.filter(|&offset| self.first_bytes.contains(bytes[offset])) {
+			let Some(index) = self.select_index(bytes, offset as i32, &mut Budget(None)).unwrap_or_default() else {
+				continue
+			};
+			let sig = self.signature(index);
+			let better = best.is_none_or(|(_, best)| match prefer_terminal {
+				true => sig.bytes.len() < best.bytes.len(),
+				false => sig.bytes.len() > best.bytes.len()
+			});
+			if better {
+				best = Some((offset, sig));
+			}
+		}
+		best.map(|(offset, sig)| (offset, sig.object.clone()))
+	}
+
+	/// Get the object associated with a signature in a circular buffer. The logical input starts
+	/// at `head` and ends at `tail` (exclusive), wrapping around the end of `ring`, so signatures
+	/// that straddle the end of the buffer are still found. When `head == tail` the whole buffer
+	/// is considered full. `offset` is relative to `head`.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let ring = [0xec, 0x00, 0x00, 0x55, 0x8b];
+	/// assert_eq!(tree.get_signature_wrapping(&ring, 3, 1, None), Some(1));
+	/// ```
+	pub fn get_signature_wrapping(&self, ring: &[u8], head: usize, tail: usize, offset: Option<i32>) -> Option<T> {
+		self.lookup(&RingView::new(ring, head, tail), offset.unwrap_or_default())
+	}
+
+	/// Get the object associated with a signature in a list of non-contiguous slices. The slices
+	/// are treated as one logical stream, so signatures may span slice boundaries. `offset` is
+	/// relative to the start of the first slice.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// assert_eq!(tree.get_signature_vectored(&[&[0x55], &[0x8b, 0xec]], None), Some(1));
+	/// ```
+	pub fn get_signature_vectored(&self, slices: &[&[u8]], offset: Option<i32>) -> Option<T> {
+		self.lookup(&SlicesView::new(slices), offset.unwrap_or_default())
+	}
+
+	/// Get the object associated with a signature in a strided view of `bytes`, made of every
+	/// `stride`th byte starting at `start`. This is useful for interleaved data where the signal
+	/// occupies a single channel. `offset` is counted in elements of the view.
+	///
+	/// # Panics
+	/// Panics if `stride` is zero.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let interleaved = [0x00, 0x55, 0x00, 0x8b, 0x00, 0xec];
+	/// assert_eq!(tree.get_signature_strided(&interleaved, 2, 1, None), Some(1));
+	/// ```
+	pub fn get_signature_strided(&self, bytes: &[u8], stride: usize, start: usize, offset: Option<i32>) -> Option<T> {
+		assert!(stride > 0, "stride must be greater than zero");
+		self.lookup(&StrideView { data: bytes, start, stride }, offset.unwrap_or_default())
+	}
+
+	/// Get the object associated with a signature in `bytes`, also trying the 7 non-byte-aligned
+	/// bit shifts of the input. Bits are read most significant first, so a shift of `n` means the
+	/// signature starts `n` bits into the byte at `offset`. Returns the smallest shift that matched
+	/// along with the object.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0xff, 0x00], None, 1);
+	/// // 0b0000_1111, 0b1111_0000, 0b0000_0000 holds 0xff00 four bits in.
+	/// assert_eq!(tree.get_signature_bit_shifted(&[0x0f, 0xf0, 0x00], None), Some((4, 1)));
+	/// ```
+	pub fn get_signature_bit_shifted(&self, bytes: &[u8], offset: Option<i32>) -> Option<(u8, T)> {
+		let offset = offset.unwrap_or_default();
+		(0..8).find_map(|shift| self.lookup(&BitShiftView { data: bytes, shift }, offset).map(|val| (shift as u8, val)))
+	}
+
+	/// Find every signature hit in `buffer`. Each hit is reported as the offset it matched at along
+	/// with the object of the signature preferred there, in offset order. Offsets whose byte can't
+	/// start any signature are skipped without walking the tree.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// tree.add_signature(vec![0xc3], None, 2);
+	/// assert_eq!(tree.scan([0x55, 0x8b, 0xec, 0x90, 0xc3, 0x55, 0x8b, 0xec]), vec![(0, 1), (4, 2), (5, 1)]);
+	/// ```
+	pub fn scan(&self, buffer: impl AsRef<[u8]>) -> Vec<(usize, T)> {
+		self.scan_buffer(buffer.as_ref()).collect()
+	}
+
+	/// Get the object associated with a signature in the search tree, like `get_signature()`, for
+	/// input of the kind described by `target`. Signatures limited to incompatible inputs with
+	/// `set_target()` are skipped.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	/// use dectree_rs::target::{Arch, Target};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, "generic");
+	/// let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, "x86").unwrap();
+	/// tree.set_target(id, Target::any().arch(Arch::X86));
+	/// assert_eq!(tree.get_signature_for([0x55, 0x8b, 0xec], None, &Target::any().arch(Arch::X86)), Some("x86"));
+	/// assert_eq!(tree.get_signature_for([0x55, 0x8b, 0xec], None, &Target::any().arch(Arch::Mips)), Some("generic"));
+	/// ```
+	pub fn get_signature_for(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>, target: &target::Target) -> Option<T> {
+		let scope = Scope { include_disabled: false, staged: false, target: Some(target) };
+		self.select_index_in(bytes.as_ref(), offset.unwrap_or_default(), &mut Budget(None), scope, |_, _| true)
+			.unwrap_or_default()
+			.map(|index| self.signature(index).object.clone())
+	}
+
+	/// Find every signature hit in `buffer`, like `scan()`, for input of the kind described by
+	/// `target`. Signatures limited to incompatible inputs with `set_target()` are skipped.
+	pub fn scan_for(&self, buffer: impl AsRef<[u8]>, target: &target::Target) -> Vec<(usize, T)> {
+		let buffer = buffer.as_ref();
+		self.candidate_offsets(buffer)
+			.filter_map(|offset| self.get_signature_for(buffer, Some(offset as i32), target).map(|val| (offset, val)))
+			.collect()
+	}
+
+	/// Find the signature hits in `buffer`, like `scan()`, keeping only the first `limit` hits of
+	/// each signature. Hits past the limit are counted rather than kept, so a generic signature that
+	/// fires thousands of times neither floods the results nor grows them without bound.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x90, 0x90], None, "nops");
+	/// tree.add_signature(vec![0xc3], None, "ret");
+	/// let sampled = tree.scan_sampled([0x90, 0x90, 0x90, 0x90, 0x90, 0xc3], 2);
+	/// assert_eq!(sampled.hits, vec![(0, "nops"), (1, "nops"), (5, "ret")]);
+	/// assert_eq!((sampled.suppressed[0].object, sampled.suppressed[0].count), ("nops", 2));
+	/// ```
+	pub fn scan_sampled(&self, buffer: impl AsRef<[u8]>, limit: usize) -> SampledScan<T> {
+		let buffer = buffer.as_ref();
+		let mut counts: HashMap<SignatureIndex, usize> = HashMap::new();
+		let mut hits = Vec::new();
+		for offset in self.candidate_offsets(buffer) {
+			let Ok(Some(index)) = self.select_index(buffer, offset as i32, &mut Budget(None)) else {
+				continue
+			};
+			let count = counts.entry(index).or_default();
+			*count += 1;
+			if *count <= limit {
+				hits.push((offset, self.signature(index).object.clone()));
+			}
+		}
+		let mut suppressed = counts.into_iter().filter(|(_, count)| *count > limit).collect::<Vec<_>>();
+		suppressed.sort_unstable_by_key(|(index, _)| *index);
+		SampledScan {
+			hits,
+			suppressed: suppressed.into_iter()
+				.map(|(index, count)| Suppressed {
+					bytes: self.signature(index).bytes.clone(),
+					object: self.signature(index).object.clone(),
+					count: count - limit
+				})
+				.collect()
+		}
+	}
+
+	/// Find every signature hit in `buffer`, like `scan()`, with the full details of each match.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let matches = tree.scan_matches([0x90, 0x55, 0x8b, 0xec]);
+	/// assert_eq!((matches[0].offset, matches[0].length, matches[0].object), (1, 3, 1));
+	/// ```
+	pub fn scan_matches(&self, buffer: impl AsRef<[u8]>) -> Vec<Match<T>> {
+		let buffer = buffer.as_ref();
+		self.candidate_offsets(buffer)
+			.filter_map(|offset| {
+				let index = self.select_index(buffer, offset as i32, &mut Budget(None)).unwrap_or_default()?;
+				Some(self.signature(index).to_match(SignatureId(index), offset))
+			})
+			.collect()
+	}
+
+	/// Find every hit of the staged signatures in `buffer`, as if they were the only signatures in
+	/// the tree, with the full details of each match as `scan_matches()` gives them. The primary
+	/// results are unaffected by staged signatures, so the two scans side by side show what
+	/// promoting them would add.
+	pub fn scan_staged(&self, buffer: impl AsRef<[u8]>) -> Vec<Match<T>> {
+		let buffer = buffer.as_ref();
+		let scope = Scope { include_disabled: false, staged: true, target: None };
+		self.candidate_offsets(buffer)
+			.filter_map(|offset| {
+				let index = self.select_index_in(buffer, offset as i32, &mut Budget(None), scope, |_, _| true).unwrap_or_default()?;
+				Some(self.signature(index).to_match(SignatureId(index), offset))
+			})
+			.collect()
+	}
+
+	/// Find the places in `buffer` where a signature matched at least `min_matched` of its leading
+	/// unmasked bytes before diverging from the input, such as patched or corrupted copies of known
+	/// code. Signatures that match, and ones the input runs out on, aren't reported. `min_matched`
+	/// is at least 1. Near misses are reported in offset order, and in id order at each offset.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec, 0x83, 0xec], None, "frame");
+	/// let patched = [0x90, 0x55, 0x8b, 0xec, 0xcc, 0xec];
+	/// let misses = tree.scan_near_misses(patched, 3);
+	/// assert_eq!(misses.len(), 1);
+	/// assert_eq!((misses[0].offset, misses[0].matched, misses[0].position, misses[0].found), (1, 3, 3, 0xcc));
+	/// assert!(tree.scan_near_misses(patched, 4).is_empty());
+	/// ```
+	pub fn scan_near_misses(&self, buffer: impl AsRef<[u8]>, min_matched: usize) -> Vec<NearMiss<T>> {
+		let buffer = buffer.as_ref();
+		let mut misses = Vec::new();
+		// With at least one byte matched, the first byte has to be able to start a signature.
+		for offset in self.candidate_offsets(buffer) {
+			let first = misses.len();
+			self.near_misses_at(buffer, offset, min_matched.max(1), &mut misses);
+			misses[first..].sort_by_key(|miss| miss.id);
+		}
+		misses
+	}
+
+	/// Find the near misses of the enabled signatures at `offset` of `bytes`, as
+	/// `scan_near_misses()` does, and add them to `misses`. The tree is walked as far as the input
+	/// leads, and each signature is compared on its own where it leaves the walk: where its branch
+	/// isn't taken, where it is the only signature left, or where it ends.
+	fn near_misses_at(&self, bytes: &[u8], offset: usize, min_matched: usize, misses: &mut Vec<NearMiss<T>>) {
+		// A signature left behind down one masked branch can be reached again down another, but is
+		// only reported once.
+		let mut checked = HashSet::new();
+		let mut check = |index: SignatureIndex| {
+			let sig = self.signature(index);
+			if !checked.insert(index) || !sig.enabled || sig.staged {
+				return
+			}
+			if let Some((position, matched)) = sig.divergence(bytes, offset).filter(|&(_, matched)| matched >= min_matched) {
+				misses.push(NearMiss {
+					id: SignatureId(index),
+					offset,
+					matched,
+					position,
+					found: bytes[offset + position],
+					object: sig.object.clone()
+				});
+			}
+		};
+		let mut pending = vec![0];
+		while let Some(current) = pending.pop() {
+			let node = &self.nodes[current];
+			let depth = node.depth as usize;
+			let leaves = |index: SignatureIndex| {
+				let sig = self.signature(index);
+				node.edge().any(|depth| bytes.get(offset + depth).is_none_or(|&byte| byte & sig.masks.get(depth) != sig.bytes[depth]))
+			};
+			// Where the edge loses any signature, every signature below is compared on its own.
+			if node.signatures().any(leaves) {
+				node.signatures().for_each(&mut check);
+				continue
+			}
+			node.term.iter().for_each(|&index| check(index));
+			let Some(&byte) = bytes.get(offset + depth).filter(|_| node.subtree_signatures.len() > 1) else {
+				node.subtree_signatures.iter().for_each(|&index| check(index));
+				continue
+			};
+			let (mut taken, mut taken_wildcard) = (ByteSet::default(), false);
+			for &index in &node.subtree_signatures {
+				let sig = self.signature(index);
+				let mask = sig.masks.get(depth);
+				let choice = match mask {
+					0x00 => None,
+					_ if byte & mask == sig.bytes[depth] => Some(byte & mask),
+					_ => {
+						check(index);
+						continue
+					}
+				};
+				let first_visit = match choice {
+					Some(choice) => !taken.contains(choice),
+					None => !taken_wildcard
+				};
+				if first_visit {
+					match choice {
+						Some(choice) => taken.insert(choice),
+						None => taken_wildcard = true
+					}
+					pending.extend(node.child(choice));
+				}
+			}
+		}
+	}
+
+	/// Find every signature hit in `buffer`, like `scan_matches()`, for the input described by
+	/// `context`. Signatures targeted at a kind of input that conflicts with `context.target` are
+	/// skipped, and a signature only counts as a hit where `condition` accepts its match; where it
+	/// doesn't, the next signature preferred at that offset is tried. Match offsets stay relative
+	/// to `buffer`; `context.address()` turns them into addresses.
+	pub fn scan_with_context<U, C>(&self, buffer: impl AsRef<[u8]>, context: &context::ScanContext<U>, condition: C) -> Vec<Match<T>>
+		where C: Fn(&context::ScanContext<U>, &Match<T>) -> bool {
+		let mut matches = Vec::new();
+		self.for_each_hit_with_context(buffer, context, condition, |_, found| matches.push(found));
+		matches
+	}
+
+	/// Find every signature hit in `buffer` like `scan_with_context()`, handing each match to
+	/// `on_hit` along with `context` as soon as it is found rather than collecting them.
+	pub fn for_each_hit_with_context<U, C, F>(&self, buffer: impl AsRef<[u8]>, context: &context::ScanContext<U>, condition: C, mut on_hit: F)
+		where C: Fn(&context::ScanContext<U>, &Match<T>) -> bool, F: FnMut(&context::ScanContext<U>, Match<T>) {
+		let buffer = buffer.as_ref();
+		let scope = Scope { include_disabled: false, staged: false, target: Some(&context.target) };
+		for offset in self.candidate_offsets(buffer) {
+			let admit = |index, sig: &SignatureInfo<T>| condition(context, &sig.to_match(SignatureId(index), offset));
+			if let Ok(Some(index)) = self.select_index_in(buffer, offset as i32, &mut Budget(None), scope, admit) {
+				on_hit(context, self.signature(index).to_match(SignatureId(index), offset));
+			}
+		}
+	}
+
+	/// Find every signature hit in `buffer`, like `scan_matches()`, along with the printable
+	/// ASCII and UTF-16LE strings of at least `min_length` characters that overlap each match or
+	/// the `radius` bytes on either side of it. See the `strings` module for what counts as a string.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0xe8, 0x00, 0x00, 0x00, 0x00], Some(vec![0xff, 0x00, 0x00, 0x00, 0x00]), "call");
+	/// let buffer = b"LoadLibraryA\x00\xe8\x10\x20\x30\x40\x00ws2_32.dll\x00";
+	/// let hits = tree.scan_with_strings(buffer, 4, 4);
+	/// let strings = hits[0].strings.iter().map(|string| string.text.as_str()).collect::<Vec<_>>();
+	/// assert_eq!(strings, vec!["LoadLibraryA", "ws2_32.dll"]);
+	/// ```
+	pub fn scan_with_strings(&self, buffer: impl AsRef<[u8]>, radius: usize, min_length: usize) -> Vec<strings::AnnotatedMatch<T>> {
+		let buffer = buffer.as_ref();
+		let matches = self.scan_matches(buffer);
+		if matches.is_empty() {
+			return Vec::new()
+		}
+		let found_strings = strings::extract_strings(buffer, min_length);
+		matches.into_iter()
+			.map(|found| strings::AnnotatedMatch {
+				strings: strings::strings_near(&found_strings, &found, radius),
+				found
+			})
+			.collect()
+	}
+
+	/// Find every signature hit in each of `buffers`, like `scan()`. The results are grouped per
+	/// buffer, in the same order as `buffers`. The buffers are scanned one after another; a
+	/// `CompiledTree` can spread them over threads with `scan_many_parallel()`.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let hits = tree.scan_many(&[&[0x90, 0x55, 0x8b, 0xec], &[0x90], &[0x55, 0x8b, 0xec]]);
+	/// assert_eq!(hits, vec![vec![(1, 1)], vec![], vec![(0, 1)]]);
+	/// ```
+	pub fn scan_many(&self, buffers: &[&[u8]]) -> Vec<Vec<(usize, T)>> {
+		buffers.iter().map(|buffer| self.scan_buffer(buffer).collect()).collect()
+	}
+
+	/// Find every signature hit in each of `buffers` like `scan_many()`, where each buffer comes
+	/// with the logical base address it was read from (such as a file or process virtual address).
+	/// Hits are reported at `base + offset` instead of at their index in the buffer.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// let hits = tree.scan_many_at(&[(0x401000, &[0x90, 0x55, 0x8b, 0xec])]);
+	/// assert_eq!(hits, vec![vec![(0x401001, 1)]]);
+	/// ```
+	pub fn scan_many_at(&self, buffers: &[(u64, &[u8])]) -> Vec<Vec<(u64, T)>> {
+		buffers.iter().map(|(base, buffer)| {
+			self.scan_buffer(buffer).map(|(offset, val)| (base + offset as u64, val)).collect()
+		}).collect()
+	}
+
+	/// Find every signature hit in `buffer` and collapse the hits into one entry per family, where
+	/// `family` maps a signature's object to the family it belongs to. Each entry counts the hits of
+	/// its family and keeps the best one, which is the hit with the longest matching signature (the
+	/// earliest on ties). Entries are ordered by the first hit of each family.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b], None, ("prologue", 1));
+	/// tree.add_signature(vec![0x55, 0x89, 0xe5], None, ("prologue", 2));
+	/// let hits = tree.scan_families(&[0x55, 0x8b, 0x55, 0x89, 0xe5], |(family, _)| *family);
+	/// assert_eq!(hits.len(), 1);
+	/// assert_eq!((hits[0].count, hits[0].best_offset, hits[0].best), (2, 2, ("prologue", 2)));
+	/// ```
+	pub fn scan_families<K, F>(&self, buffer: &[u8], family: F) -> Vec<FamilyHit<K, T>> where K: Hash + Eq + Clone, F: Fn(&T) -> K {
+		let mut hits: Vec<FamilyHit<K, T>> = Vec::new();
+		let mut index: HashMap<K, usize> = HashMap::new();
+		for offset in self.candidate_offsets(buffer) {
+			let found = self.lookup_with(buffer, offset as i32, |sig| (sig.bytes.len(), sig.object.clone()));
+			let Some((length, val)) = found else {
+				continue
+			};
+			let key = family(&val);
+			match index.get(&key) {
+				Some(&position) => {
+					let hit = &mut hits[position];
+					hit.count += 1;
+					if length > hit.best_length {
+						hit.best_offset = offset;
+						hit.best_length = length;
+						hit.best = val;
+					}
+				},
+				None => {
+					index.insert(key.clone(), hits.len());
+					hits.push(FamilyHit {
+						family: key,
+						count: 1,
+						best_offset: offset,
+						best_length: length,
+						best: val
+					});
+				}
+			}
+		}
+		hits
+	}
+
+	/// Try every candidate offset of `buffer` and yield the offsets that match along with their
+	/// object.
+	fn scan_buffer<'a>(&'a self, buffer: &'a [u8]) -> impl Iterator<Item = (usize, T)> + 'a {
+		self.candidate_offsets(buffer).filter_map(move |offset| self.lookup(buffer, offset as i32).map(|val| (offset, val)))
+	}
+
+	/// Yield the offsets of `buffer` whose byte can start a signature.
+	fn candidate_offsets<'a>(&'a self, buffer: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+		buffer.iter().enumerate().filter(|(_, byte)| self.first_bytes.contains(**byte)).map(|(offset, _)| offset)
+	}
+
+	/// Explain why `bytes` at `offset` doesn't match any signature in the search tree. Returns
+	/// `None` if it does match. Positions in the returned `MatchFailure` are relative to `offset`.
+	/// Where the input holds the bytes of a signature that was left out of the match, because it is
+	/// disabled, staged or has a constraint that doesn't hold, that signature is reported.
+	/// ```rust
+	/// use dectree_rs::{MatchFailure, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// tree.add_signature(vec![0x55, 0x89, 0xe5], None, 2);
+	/// assert_eq!(tree.match_failure_reason(&[0x55, 0x90], None), Some(MatchFailure::Diverged { depth: 1, expected: vec![0x89, 0x8b] }));
+	/// assert_eq!(tree.match_failure_reason(&[0x55, 0x8b], None), Some(MatchFailure::OutOfInput { depth: 2, needed: 1 }));
+	/// assert_eq!(tree.match_failure_reason(&[0x55, 0x8b, 0xec], None), None);
+	/// ```
+	pub fn match_failure_reason(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Option<MatchFailure> {
+		let bytes = bytes.as_ref();
+		let offset = offset.unwrap_or_default();
+		if self.lookup_with(bytes, offset, |_| ()).is_some() {
+			return None
+		}
+		let filtered = self.signatures.iter()
+			.enumerate()
+			.find(|(_, sig)| offset >= 0 && Self::matches_from(sig, bytes, offset, 0, &mut Budget(None)).unwrap_or_default());
+		if let Some((index, sig)) = filtered {
+			let id = SignatureId(index as SignatureIndex);
+			return Some(match (sig.enabled, sig.staged) {
+				(false, _) => MatchFailure::Disabled { id },
+				(true, true) => MatchFailure::Staged { id },
+				(true, false) => MatchFailure::ConstraintFailed { id }
+			})
+		}
+		let available = (bytes.len() as i32 - offset).max(0) as usize;
+		let mut nn_node = 0;
+		loop {
+			let next = {
+				let node = &self.nodes[nn_node];
+				let depth = node.depth as usize;
+				for depth in node.edge() {
+					let mut passing = node.signatures().map(|index| self.signature(index));
+					if depth >= available {
+						let needed = passing.map(|sig| sig.bytes.len() - depth).min().unwrap_or_default();
+						return Some(MatchFailure::OutOfInput { depth, needed })
+					}
+					let byte = bytes[offset as usize + depth];
+					if !passing.any(|sig| byte & sig.masks.get(depth) == sig.bytes[depth]) {
+						let expected = node.signatures().next().map(|index| self.signature(index).bytes[depth]);
+						return Some(MatchFailure::Diverged { depth, expected: expected.into_iter().collect() })
+					}
+				}
+				let sigs = node.subtree_signatures.iter().map(|&index| self.signature(index));
+				if let [only] = node.subtree_signatures[..] {
+					let (sbytes, smasks) = (&self.signature(only).bytes, &self.signature(only).masks);
+					for (i, sbyte) in sbytes.iter().enumerate().skip(depth) {
+						if i >= available {
+							return Some(MatchFailure::OutOfInput { depth: i, needed: sbytes.len() - i })
+						}
+						if bytes[offset as usize + i] & smasks.get(i) != *sbyte {
+							return Some(MatchFailure::MaskMismatch { position: i, expected: *sbyte, mask: smasks.get(i) })
+						}
+					}
+				}
+				if depth >= available {
+					let needed = sigs.map(|sig| sig.bytes.len() - depth).min().unwrap_or_default();
+					return Some(MatchFailure::OutOfInput { depth, needed })
+				}
+				let byte = bytes[offset as usize + depth];
+				let next = sigs.clone()
+					.find(|sig| byte & sig.masks.get(depth) == sig.bytes[depth])
+					.and_then(|sig| node.child(sig.choice_at(depth)));
+				match next {
+					Some(next) => next,
+					None => {
+						let mut expected = sigs.map(|sig| sig.bytes[depth]).collect::<Vec<_>>();
+						expected.sort_unstable();
+						expected.dedup();
+						return Some(MatchFailure::Diverged { depth, expected })
+					}
+				}
+			};
+			nn_node = next;
+		}
+	}
+
+	/// Get the object associated with a signature in the search tree, for objects that are
+	/// `Copy`. The object is copied straight out of the tree, without going through `Clone`.
+	/// ```rust
+	/// use dectree_rs::SignatureDecisionTree;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 7u32);
+	/// assert_eq!(tree.get_signature_copied(&[0x55, 0x8b, 0xec], None), Some(7));
+	/// ```
+	pub fn get_signature_copied(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>) -> Option<T> where T: Copy {
+		self.lookup_with(bytes.as_ref(), offset.unwrap_or_default(), |sig| sig.object)
+	}
+
+	/// Walk the tree against `bytes`, anchored at `offset`, and return a clone of the object of
+	/// the longest matching signature.
+	fn lookup<B>(&self, bytes: &B, offset: i32) -> Option<T> where B: ByteSource + ?Sized {
+		self.lookup_with(bytes, offset, |sig| sig.object.clone())
+	}
+
+	/// Get the object associated with a signature in the search tree, giving up once `budget`
+	/// units of work have been spent. Each tree node visited and each input byte compared costs
+	/// one unit, so the cost of a query against untrusted input can be capped regardless of the
+	/// signatures loaded.
+	/// ```rust
+	/// use dectree_rs::{BudgetExceeded, SignatureDecisionTree};
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+	/// assert_eq!(tree.get_signature_with_budget(&[0x55, 0x8b, 0xec], None, 16), Ok(Some(1)));
+	/// assert_eq!(tree.get_signature_with_budget(&[0x55, 0x8b, 0xec], None, 2), Err(BudgetExceeded));
+	/// ```
+	pub fn get_signature_with_budget(&self, bytes: impl AsRef<[u8]>, offset: Option<i32>, budget: usize) -> Result<Option<T>, BudgetExceeded> {
+		self.select(bytes.as_ref(), offset.unwrap_or_default(), &mut Budget(Some(budget)), |sig| sig.object.clone())
+	}
+
+	/// Walk the tree against `bytes`, anchored at `offset`, and apply `f` to the matching signature
+	/// preferred by the tree's `OverlapPolicy`.
+	fn lookup_with<B, R, F>(&self, bytes: &B, offset: i32, f: F) -> Option<R>
+		where B: ByteSource + ?Sized, F: FnOnce(&SignatureInfo<T>) -> R {
+		self.select(bytes, offset, &mut Budget(None), f).unwrap_or_default()
+	}
+
+	/// Walk the tree like `lookup_with()`, within `budget`. The longest match is preferred unless
+	/// the policy is `OverlapPolicy::PreferTerminal`, in which case the shortest one is. When
+	/// several matches share a length, the first one found wins.
+	fn select<B, R, F>(&self, bytes: &B, offset: i32, budget: &mut Budget, f: F) -> Result<Option<R>, BudgetExceeded>
+		where B: ByteSource + ?Sized, F: FnOnce(&SignatureInfo<T>) -> R {
+		Ok(self.select_index(bytes, offset, budget)?.map(|index| f(self.signature(index))))
+	}
+
+	/// Walk the tree like `select()`, and return the index of the preferred signature.
+	fn select_index<B>(&self, bytes: &B, offset: i32, budget: &mut Budget) -> Result<Option<SignatureIndex>, BudgetExceeded>
+		where B: ByteSource + ?Sized {
+		self.select_index_in(bytes, offset, budget, Scope::ENABLED, |_, _| true)
+	}
+
+	/// Walk the tree like `select_index()`, choosing among the signatures in `scope` that `admit`
+	/// accepts.
+	fn select_index_in<B, F>(&self, bytes: &B, offset: i32, budget: &mut Budget, scope: Scope, mut admit: F) -> Result<Option<SignatureIndex>, BudgetExceeded>
+		where B: ByteSource + ?Sized, F: FnMut(SignatureIndex, &SignatureInfo<T>) -> bool {
+		let prefer_terminal = self.overlap_policy == OverlapPolicy::PreferTerminal;
+		let mut best: Option<(SignatureIndex, &SignatureInfo<T>)> = None;
+		self.walk(bytes, offset, budget, scope, |index, sig| {
+			if !admit(index, sig) {
+				return ControlFlow::Continue(())
+			}
+			let better = best.is_none_or(|(_, best)| match prefer_terminal {
+				true => sig.bytes.len() < best.bytes.len(),
+				false => sig.bytes.len() > best.bytes.len()
+			});
+			if better {
+				best = Some((index, sig));
+			}
+			ControlFlow::Continue(())
+		})?;
+		Ok(best.map(|(index, _)| index))
+	}
+
+	/// Walk the tree against `bytes`, anchored at `offset`, and hand every matching signature to
+	/// `visit`, along with its index, in the order they are found. Signatures outside `scope` still
+	/// steer the walk but are never handed to `visit`. Where an input byte is accepted by
+	/// signatures with different masks that lead down different branches, every one of those
+	/// branches is explored, and along each branch matches are found shortest first. Signatures
+	/// with different masks share a branch whenever their masked bytes agree, so once a branch is
+	/// taken on a masked input byte that differs from the input byte itself, the signatures found
+	/// below it are compared from their start again. Signatures are borrowed from the tree, so a
+	/// match can be kept without cloning it. Returning `ControlFlow::Break` from `visit` ends the
+	/// walk early. One unit of `budget` is spent for every node visited and every input byte
+	/// compared.
+	fn walk<'a, B, V>(&'a self, bytes: &B, offset: i32, budget: &mut Budget, scope: Scope, mut visit: V) -> Result<(), BudgetExceeded>
+		where B: ByteSource + ?Sized, V: FnMut(SignatureIndex, &'a SignatureInfo<T>) -> ControlFlow<()> {
+		// Each pending node is paired with whether every byte on the way to it was taken as is.
+		let mut pending = vec![(0, true)];
+		while let Some((current, verified)) = pending.pop() {
+			budget.spend()?;
+			let node = &self.nodes[current];
+			let Some(exact) = self.follow_edge(node, bytes, offset, budget)? else {
+				continue
+			};
+			let verified = verified && exact;
+			let (depth, sigs, term) = (&node.depth, &node.subtree_signatures, &node.term);
+			for &index in term.iter().filter(|&&index| scope.admits(self.signature(index))) {
+				let sig = self.signature(index);
+				let matched = verified || Self::matches_from(sig, bytes, offset, 0, budget)?;
+				if matched && sig.constraints_hold(bytes, offset) && visit(index, sig).is_break() {
+					return Ok(())
+				}
+			}
+			// Once we get down to one sig, there are no more branches,
+			// just check the byte sequence.
+			if let [only_index] = sigs[..] {
+				let only = self.signature(only_index);
+				let start = if verified { *depth as usize } else { 0 };
+				let matched = scope.admits(only) && Self::matches_from(only, bytes, offset, start, budget)?;
+				if matched && only.constraints_hold(bytes, offset) && visit(only_index, only).is_break() {
+					return Ok(())
+				}
+				continue;
+			}
+			// There are still more choices to make, keep on truckin'
+			if (offset + *depth) >= bytes.len() as i32 {
+				continue
+			}
+			let byte = bytes.byte_at((offset + *depth) as usize);
+			let (mut taken, mut taken_wildcard, first_branch) = (ByteSet::default(), false, pending.len());
+			for &index in sigs.iter() {
+				let (sbytes, smasks) = (&self.signature(index).bytes, &self.signature(index).masks);
+				// Signatures with different masks can accept the same input byte and still lead
+				// down different branches, so every viable one has to be followed.
+				budget.spend()?;
+				let mask = smasks.get(*depth as usize);
+				if mask == 0x00 {
+					if !taken_wildcard {
+						taken_wildcard = true;
+						pending.extend(node.wildcard.map(|child| (child.get() as usize, verified)));
+					}
+					continue
+				}
+				let masked = byte & mask;
+				if masked == sbytes[*depth as usize] && !taken.contains(masked) {
+					taken.insert(masked);
+					pending.extend(node.child(Some(masked)).map(|child| (child, verified && masked == byte)));
+				}
+			}
+			// Explore the branches in the order their signatures are listed.
+			pending[first_branch..].reverse();
+		}
+		Ok(())
+	}
+
+	/// Check the bytes carried by the edge leading to `node` against `bytes` at `offset`, as the
+	/// nodes of a chain would be. Returns `None` if no signature below the node accepts them, or
+	/// else whether every byte was taken as is. One unit of `budget` is spent for every byte.
+	fn follow_edge<B>(&self, node: &TreeNode, bytes: &B, offset: i32, budget: &mut Budget) -> Result<Option<bool>, BudgetExceeded>
+		where B: ByteSource + ?Sized {
+		let mut exact = true;
+		for depth in node.edge() {
+			budget.spend()?;
+			if offset as usize + depth >= bytes.len() {
+				return Ok(None)
+			}
+			let byte = bytes.byte_at(offset as usize + depth);
+			let mut sigs = node.signatures().map(|index| self.signature(index));
+			match sigs.clone().next().and_then(|sig| sig.choice_at(depth)) {
+				Some(choice) if choice != byte => match sigs.any(|sig| byte & sig.masks.get(depth) == choice) {
+					true => exact = false,
+					false => return Ok(None)
+				},
+				_ => ()
+			}
+		}
+		Ok(Some(exact))
+	}
+
+	/// Check whether `sig` matches `bytes` at `offset` from its byte at `start` on, spending one
+	/// unit of `budget` for every byte compared.
+	fn matches_from<B>(sig: &SignatureInfo<T>, bytes: &B, offset: i32, start: usize, budget: &mut Budget) -> Result<bool, BudgetExceeded>
+		where B: ByteSource + ?Sized {
+		for (i, sbyte) in sig.bytes.iter().enumerate().skip(start) {
+			let real_off = offset + i as i32;
+			// We still have pieces of the signature left, but we're out of bytes
+			if real_off >= bytes.len() as i32 {
+				return Ok(false)
+			}
+			budget.spend()?;
+			if bytes.byte_at(real_off as usize) & sig.masks.get(i) != *sbyte {
+				return Ok(false)
+			}
+		}
+		Ok(true)
 	}
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
 	#[test]
 	fn test_signature_subset() {
 		let signature_base = vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32, 0x77, 0x89, 0x4f, 0x55];
 		let mut tree = super::SignatureDecisionTree::new();
-		tree.add_signature(signature_base.clone(), None, None);
-		tree.add_signature(signature_base.clone().into_iter().take(7).collect(), None, Some(signature_base.clone().into_iter().take(7).collect()));
-		tree.add_signature(signature_base.clone().into_iter().take(4).collect(), None, Some(signature_base.clone().into_iter().take(4).collect()));
-		tree.add_signature([signature_base.clone(), vec![0xfe, 0x38]].concat(), None, Some([signature_base.clone(), vec![0xfe, 0x38]].concat()));
+		tree.add_signature(signature_base.clone(), None, signature_base.clone());
+		tree.add_signature(signature_base.clone().into_iter().take(7).collect(), None, signature_base.clone().into_iter().take(7).collect());
+		tree.add_signature(signature_base.clone().into_iter().take(4).collect(), None, signature_base.clone().into_iter().take(4).collect());
+		tree.add_signature([signature_base.clone(), vec![0xfe, 0x38]].concat(), None, [signature_base.clone(), vec![0xfe, 0x38]].concat());
 		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32, 0x00, 0x99, 0x36, 0x5f, 0x21, 0xfd], None), Some(signature_base.clone().into_iter().take(7).collect()));
 		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff, 0x32], None), Some(signature_base.clone().into_iter().take(7).collect()));
 		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0x00], None), Some(signature_base.clone().into_iter().take(4).collect()));
 		assert_eq!(tree.get_signature(vec![0x55], None), None);
 	}
+
+	#[test]
+	fn test_overlap_policy() {
+		use super::OverlapPolicy;
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 2);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe], None, 3);
+		let input = [0x55, 0xe9, 0xd8, 0x01, 0xfe];
+		assert_eq!(tree.overlap_policy(), OverlapPolicy::PreferDeepest);
+		assert_eq!(tree.get_signature(input, None), Some(3));
+		assert_eq!(tree.get_signatures(input, None), vec![3]);
+		tree.set_overlap_policy(OverlapPolicy::PreferTerminal);
+		assert_eq!(tree.get_signature(input, None), Some(1));
+		assert_eq!(tree.get_signatures(input, None), vec![1]);
+		tree.set_overlap_policy(OverlapPolicy::ReportAll);
+		assert_eq!(tree.get_signature(input, None), Some(3));
+		assert_eq!(tree.get_signatures(input, None), vec![3, 2, 1]);
+		assert_eq!(tree.get_signatures(&input[..4], None), vec![2, 1]);
+		assert!(tree.get_signatures([0x00], None).is_empty());
+	}
+
+	#[test]
+	fn test_get_match() {
+		use super::Match;
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0x00, 0x01], Some(vec![0xff, 0xff, 0x00, 0xff]), 2);
+		assert_eq!(tree.get_match([0x00, 0x55, 0xe9, 0x42, 0x01], Some(1)), Some(Match {
+			id: tree.get_match([0x55, 0xe9, 0x42, 0x01], None).unwrap().id,
+			offset: 1,
+			length: 4,
+			bytes: vec![0x55, 0xe9, 0x00, 0x01],
+			masks: vec![0xff, 0xff, 0x00, 0xff],
+			object: 2,
+			enabled: true
+		}));
+		assert_eq!(tree.get_match([0x55, 0xe9, 0x42], None).map(|found| (found.length, found.masks)), Some((2, vec![0xff, 0xff])));
+		assert_eq!(tree.get_match([0x55], None), None);
+	}
+
+	#[test]
+	fn test_signature_at_offsets() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 2);
+		let bytes = [0x55, 0xe9, 0xd8, 0x55, 0xe9];
+		assert_eq!(tree.get_signature_at_offsets(bytes, &[3, 0, 1, 9]), vec![Some(1), Some(2), None, None]);
+		assert!(tree.get_signature_at_offsets(bytes, &[]).is_empty());
+	}
+
+	#[test]
+	fn test_signature_wrapping() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff], None, 2);
+		let ring = vec![0x01, 0xfe, 0xff, 0x00, 0x00, 0x55, 0xe9, 0xd8];
+		assert_eq!(tree.get_signature_wrapping(&ring, 5, 3, None), Some(2));
+		// The tail cuts the stream short, so only the shorter signature fits.
+		assert_eq!(tree.get_signature_wrapping(&ring, 5, 1, None), Some(1));
+		assert_eq!(tree.get_signature_wrapping(&ring, 5, 3, Some(1)), None);
+		// Without wrapping, the stream ends at the end of the buffer.
+		assert_eq!(tree.get_signature(ring.clone(), Some(5)), None);
+	}
+
+	#[test]
+	fn test_signature_vectored() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff], None, 2);
+		let first: &[u8] = &[0x00, 0x55, 0xe9];
+		let second: &[u8] = &[0xd8];
+		let third: &[u8] = &[0x01, 0xfe, 0xff];
+		assert_eq!(tree.get_signature_vectored(&[first, &[], second, third], Some(1)), Some(2));
+		assert_eq!(tree.get_signature_vectored(&[first, second, &third[..1]], Some(1)), Some(1));
+		assert_eq!(tree.get_signature_vectored(&[first, second], Some(1)), None);
+		assert_eq!(tree.get_signature_vectored(&[], None), None);
+	}
+
+	#[test]
+	fn test_signature_strided() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 2);
+		let planar = vec![0x55, 0xaa, 0x00, 0xe9, 0xbb, 0x00, 0xd8, 0xcc, 0x00, 0x01];
+		assert_eq!(tree.get_signature_strided(&planar, 3, 0, None), Some(2));
+		assert_eq!(tree.get_signature_strided(&planar[..9], 3, 0, None), Some(1));
+		assert_eq!(tree.get_signature_strided(&planar, 3, 1, None), None);
+		assert_eq!(tree.get_signature_strided(&planar, 1, 20, None), None);
+	}
+
+	#[test]
+	fn test_signature_bit_shifted() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 1);
+		let aligned = vec![0x55, 0xe9, 0xd8];
+		assert_eq!(tree.get_signature_bit_shifted(&aligned, None), Some((0, 1)));
+		// Shift the whole stream right by 3 bits.
+		let bits = aligned.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32) << 5;
+		let shifted = vec![(bits >> 24) as u8, (bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+		assert_eq!(tree.get_signature_bit_shifted(&shifted, None), Some((3, 1)));
+		assert_eq!(tree.get_signature(shifted.clone(), None), None);
+		assert_eq!(tree.get_signature_bit_shifted(&shifted[..3], None), None);
+	}
+
+	#[test]
+	fn test_signature_str() {
+		use super::parser::ParseError;
+		let mut tree = super::SignatureDecisionTree::new();
+		assert_eq!(tree.add_signature_str("55 e9 ?? 01", 1), Ok(true));
+		assert_eq!(tree.add_signature_str("55E9??01", 2), Ok(false));
+		assert_eq!(tree.add_signature_str("55 e9 ?? 0x", 3), Err(ParseError::InvalidCharacter { position: 10, character: 'x' }));
+		assert_eq!(tree.get_signature([0x55, 0xe9, 0xd8, 0x01], None), Some(1));
+		assert_eq!(tree.get_signature([0x55, 0xe9, 0xd8, 0x02], None), None);
+	}
+
+	#[test]
+	fn test_signature_with_swaps() {
+		use super::EndianSwap;
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature_with_swaps(vec![0x11, 0x00, 0x33, 0x44], Some(vec![0xff, 0x00, 0xff, 0xff]), 1, &[EndianSwap::Swap32]);
+		tree.add_signature_with_swaps(vec![0x12, 0x34, 0x56, 0x78], None, 3, &[EndianSwap::Swap16]);
+		tree.add_signature_with_swaps(vec![0x55, 0x66, 0x77], None, 2, &[EndianSwap::Swap16]);
+		assert_eq!(tree.get_signature(vec![0x11, 0x99, 0x33, 0x44], None), Some(1));
+		assert_eq!(tree.get_signature(vec![0x44, 0x33, 0x99, 0x11], None), Some(1));
+		assert_eq!(tree.get_signature(vec![0x44, 0x33, 0x99, 0x12], None), None);
+		assert_eq!(tree.get_signature(vec![0x34, 0x12, 0x78, 0x56], None), Some(3));
+		assert_eq!(tree.get_signature(vec![0x55, 0x66, 0x77], None), Some(2));
+		assert_eq!(tree.get_signature(vec![0x66, 0x55, 0x77], None), None);
+	}
+
+	#[test]
+	fn test_signature_copied() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 1u32);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff], None, 2u32);
+		tree.add_signature(vec![0x55, 0xe9], None, 3u32);
+		assert_eq!(tree.get_signature_copied([0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff], None), Some(2));
+		assert_eq!(tree.get_signature_copied([0x55, 0xe9, 0xd8, 0x01, 0xfe], None), Some(1));
+		assert_eq!(tree.get_signature_copied([0x55, 0xe9, 0xd8], None), Some(3));
+		assert_eq!(tree.get_signature_copied([0x55], None), None);
+	}
+
+	#[test]
+	fn test_signature_with_hasher() {
+		use std::collections::hash_map::DefaultHasher;
+		use std::hash::BuildHasherDefault;
+		let mut tree = super::SignatureDecisionTree::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 2);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe], None, 3);
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01], None), Some(1));
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe], None), Some(3));
+	}
+
+	#[test]
+	fn test_signature_implicit_masks() {
+		use super::Mask;
+		assert_eq!(Mask::new(vec![0xff, 0xff]), Mask::Exact);
+		assert_eq!(Mask::new(vec![0xff, 0x0f]), Mask::Bytes(vec![0xff, 0x0f]));
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 1);
+		// Explicit all-ones masks are the same signature as no masks at all.
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], Some(vec![0xff; 3]), 2);
+		tree.add_signature(vec![0x55, 0x0a, 0xd8], Some(vec![0xff, 0x0f, 0xff]), 3);
+		assert_eq!(tree.sigs_dup.len(), 2);
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xd8], None), Some(1));
+		assert_eq!(tree.get_signature(vec![0x55, 0x1a, 0xd8], None), Some(3));
+	}
+
+	#[test]
+	fn test_signature_run_length_masks() {
+		use super::{Mask, MaskRun};
+		let masks = [vec![0xff; 2], vec![0x00; 28], vec![0xff; 2]].concat();
+		assert_eq!(Mask::new(masks.clone()), Mask::Runs(vec![
+			MaskRun { end: 2, mask: 0xff },
+			MaskRun { end: 30, mask: 0x00 },
+			MaskRun { end: 32, mask: 0xff }
+		]));
+		assert!((0..32).all(|i| Mask::new(masks.clone()).get(i) == masks[i]));
+		// Short or busy masks aren't worth encoding.
+		assert_eq!(Mask::new(vec![0xff, 0x00, 0xff]), Mask::Bytes(vec![0xff, 0x00, 0xff]));
+		let mut tree = super::SignatureDecisionTree::new();
+		let bytes = [vec![0x4d, 0x5a], vec![0x00; 28], vec![0x50, 0x45]].concat();
+		tree.add_signature(bytes, Some(masks), 1);
+		let sample = [vec![0x4d, 0x5a], vec![0x90; 28], vec![0x50, 0x45]].concat();
+		assert_eq!(tree.get_signature(sample.clone(), None), Some(1));
+		assert_eq!(tree.get_signature(&sample[..31], None), None);
+	}
+
+	#[test]
+	fn test_scan() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		tree.add_signature(vec![0x10, 0xe9], Some(vec![0xf0, 0xff]), 2);
+		let buffer = [0x55, 0xe9, 0x1f, 0xe9, 0x00, 0x55];
+		assert_eq!(tree.scan(buffer), vec![(0, 1), (2, 2)]);
+		assert!((0..=0xff).all(|byte| tree.first_bytes.contains(byte) == (byte == 0x55 || byte & 0xf0 == 0x10)));
+		assert!(!tree.add_signature(vec![], None, 3));
+		assert_eq!(tree.scan(buffer), vec![(0, 1), (2, 2)]);
+		assert!(tree.scan([]).is_empty());
+	}
+
+	#[test]
+	fn test_max_signature_length() {
+		use super::WildcardTail;
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.set_wildcard_tail(WildcardTail::Trim);
+		tree.add_signature(vec![0x55, 0xe9, 0x00, 0x00], Some(vec![0xff, 0xff, 0x00, 0x00]), 1);
+		assert_eq!(tree.max_signature_length(), 2);
+		tree.set_min_signature_length(4);
+		tree.add_signature(vec![0x55, 0xe9, 0x00, 0x00, 0x00], Some(vec![0xff, 0xff, 0x00, 0x00, 0xff]), 2);
+		assert_eq!(tree.max_signature_length(), 2);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe], None, 3);
+		assert_eq!(tree.max_signature_length(), 5);
+	}
+
+	#[test]
+	fn test_scan_many() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 2);
+		tree.add_signature(vec![0xd8, 0x55], None, 3);
+		let hits = tree.scan_many(&[&[0x00, 0x55, 0xe9, 0xd8, 0x55, 0xe9], &[], &[0x55]]);
+		assert_eq!(hits, vec![vec![(1, 2), (3, 3), (4, 1)], vec![], vec![]]);
+		let hits = tree.scan_many_at(&[(0x1000, &[0x00, 0x55, 0xe9, 0xd8]), (0x7ff0_0000_0000, &[0xd8, 0x55])]);
+		assert_eq!(hits, vec![vec![(0x1001, 2)], vec![(0x7ff0_0000_0000, 3)]]);
+	}
+
+	#[test]
+	fn test_signature_with_budget() {
+		use super::BudgetExceeded;
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff], None, 2);
+		let input = [0x55, 0xe9, 0xd8, 0x01, 0xfe, 0xff];
+		let cost = (1..64).find(|&budget| tree.get_signature_with_budget(input, None, budget).is_ok()).unwrap();
+		assert_eq!(tree.get_signature_with_budget(input, None, cost), Ok(Some(2)));
+		assert_eq!(tree.get_signature_with_budget(input, None, cost - 1), Err(BudgetExceeded));
+		assert_eq!(tree.get_signature_with_budget([0x00], None, 3), Ok(None));
+		assert_eq!(tree.get_signature_with_budget(input, None, 0), Err(BudgetExceeded));
+	}
+
+	#[test]
+	fn test_signature_wildcard_tail() {
+		use super::WildcardTail;
+		let (bytes, masks) = (vec![0x55, 0xe9, 0x00, 0x00], vec![0xff, 0xff, 0x00, 0x00]);
+		let mut tree = super::SignatureDecisionTree::new();
+		assert_eq!(tree.wildcard_tail(), WildcardTail::Require);
+		tree.add_signature(bytes.clone(), Some(masks.clone()), 1);
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0x01], None), None);
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0x01, 0x02], None), Some(1));
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.set_wildcard_tail(WildcardTail::Trim);
+		tree.add_signature(bytes.clone(), Some(masks.clone()), 1);
+		tree.add_signature(vec![0x55, 0xe9], None, 2);
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9], None), Some(1));
+		assert_eq!(tree.get_signature(vec![0x55, 0xe8], None), None);
+	}
+
+	#[test]
+	fn test_signature_min_length() {
+		let mut tree = super::SignatureDecisionTree::new();
+		assert!(tree.add_signature(vec![0x55], None, 1));
+		assert!(!tree.add_signature(vec![0x55], None, 1));
+		tree.set_min_signature_length(3);
+		assert_eq!(tree.min_signature_length(), 3);
+		assert!(!tree.add_signature(vec![0x55, 0xe9, 0x00, 0x00], Some(vec![0xff, 0xff, 0x00, 0x00]), 2));
+		assert!(tree.add_signature(vec![0x55, 0xe9, 0x00, 0x01], Some(vec![0xff, 0xff, 0x0f, 0xff]), 3));
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0x00, 0x00], None), Some(1));
+		assert_eq!(tree.get_signature(vec![0x55, 0xe9, 0xf0, 0x01], None), Some(3));
+	}
+
+	#[test]
+	fn test_scan_families() {
+		use super::FamilyHit;
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, (1, 'a'));
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], None, (1, 'b'));
+		tree.add_signature(vec![0xd8, 0x01], None, (2, 'c'));
+		let buffer = [0x55, 0xe9, 0xd8, 0x01, 0x55, 0xe9, 0xd8, 0x55, 0xe9];
+		assert_eq!(tree.scan_families(&buffer, |(family, _)| *family), vec![
+			FamilyHit { family: 1, count: 3, best_offset: 0, best_length: 3, best: (1, 'b') },
+			FamilyHit { family: 2, count: 1, best_offset: 2, best_length: 2, best: (2, 'c') }
+		]);
+		assert!(tree.scan_families(&[], |(family, _)| *family).is_empty());
+	}
+
+	#[test]
+	fn test_match_failure_reason() {
+		use super::MatchFailure;
+		let mut tree = super::SignatureDecisionTree::new();
+		assert_eq!(tree.match_failure_reason([0x55], None), Some(MatchFailure::Diverged { depth: 0, expected: vec![] }));
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 1);
+		assert_eq!(tree.match_failure_reason([0x55, 0xe9, 0xd8, 0x02], None), Some(MatchFailure::MaskMismatch { position: 3, expected: 0x01, mask: 0xff }));
+		assert_eq!(tree.match_failure_reason([0x55, 0xe9], None), Some(MatchFailure::OutOfInput { depth: 2, needed: 2 }));
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01, 0xfe], None, 2);
+		tree.add_signature(vec![0x55, 0x00, 0x77], Some(vec![0xff, 0xf0, 0xff]), 3);
+		assert_eq!(tree.match_failure_reason([0x00, 0x55, 0x10], Some(1)), Some(MatchFailure::Diverged { depth: 1, expected: vec![0x00, 0xe9] }));
+		assert_eq!(tree.match_failure_reason([0x55, 0x0f, 0x78], None), Some(MatchFailure::MaskMismatch { position: 2, expected: 0x77, mask: 0xff }));
+		assert_eq!(tree.match_failure_reason([0x55], Some(4)), Some(MatchFailure::OutOfInput { depth: 0, needed: 3 }));
+		assert_eq!(tree.match_failure_reason([0x55, 0xe9, 0xd8, 0x01], None), None);
+	}
+
+	#[test]
+	fn test_match_failure_reason_filtered() {
+		use super::constraint::{Computation, Constraint};
+		use super::MatchFailure;
+		let mut tree = super::SignatureDecisionTree::new();
+		let disabled = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 1).unwrap();
+		tree.set_enabled(disabled, false);
+		assert_eq!(tree.match_failure_reason([0x55, 0x8b, 0xec], None), Some(MatchFailure::Disabled { id: disabled }));
+		let constrained = tree.add_signature_with_id(vec![0xa5, 0x00, 0x00], Some(vec![0xff, 0x00, 0x00]), 2).unwrap();
+		tree.set_constraints(constrained, vec![Constraint::new(2, Computation::Sum(1..2))]);
+		assert_eq!(tree.match_failure_reason([0xa5, 0x10, 0x11], None), Some(MatchFailure::ConstraintFailed { id: constrained }));
+		assert_eq!(tree.match_failure_reason([0xa5, 0x10, 0x10], None), None);
+		tree.set_staging(true);
+		let staged = tree.add_signature_with_id(vec![0xc3], None, 3).unwrap();
+		assert_eq!(tree.match_failure_reason([0xc3], None), Some(MatchFailure::Staged { id: staged }));
+	}
+
+	#[test]
+	fn test_masked_branches() {
+		use super::OverlapPolicy;
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x00, 0x01], Some(vec![0xff, 0x00, 0xff]), 1);
+		tree.add_signature(vec![0x55, 0x8b, 0x02], None, 2);
+		tree.add_signature(vec![0x55, 0x8b, 0x01, 0x02], None, 3);
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0x02], None), Some(2));
+		assert_eq!(tree.get_signature([0x55, 0x42, 0x01], None), Some(1));
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0x01, 0x02], None), Some(3));
+		tree.set_overlap_policy(OverlapPolicy::PreferTerminal);
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0x01, 0x02], None), Some(1));
+		tree.set_overlap_policy(OverlapPolicy::ReportAll);
+		assert_eq!(tree.get_signatures([0x55, 0x8b, 0x01, 0x02], None), vec![3, 1]);
+	}
+
+	#[test]
+	fn test_nibble_branches() {
+		let mut tree = super::SignatureDecisionTree::new();
+		assert_eq!(tree.add_signature_str("E8 ?4 5?", 1), Ok(true));
+		assert_eq!(tree.add_signature_str("E8 04 50", 2), Ok(true));
+		assert_eq!(tree.add_signature_str("E8 3? 90", 3), Ok(true));
+		assert_eq!(tree.add_signature_str("E8 34", 4), Ok(true));
+		assert_eq!(tree.get_signature([0xe8, 0x04, 0x50], None), Some(2));
+		assert_eq!(tree.get_signature([0xe8, 0x04, 0x5f], None), Some(1));
+		assert_eq!(tree.get_signature([0xe8, 0xa4, 0x51], None), Some(1));
+		assert_eq!(tree.get_signature([0xe8, 0xa5, 0x51], None), None);
+		assert_eq!(tree.get_signature([0xe8, 0x34, 0x90], None), Some(3));
+		assert_eq!(tree.get_signature([0xe8, 0x34, 0x55], None), Some(1));
+		tree.set_overlap_policy(super::OverlapPolicy::ReportAll);
+		assert_eq!(tree.get_signatures([0xe8, 0x34, 0x55], None), vec![1, 4]);
+		assert_eq!(tree.get_signature([0xe8, 0x3f, 0x90], None), Some(3));
+	}
+
+	#[test]
+	fn test_try_add_signature() {
+		use super::{DectreeError, WildcardTail};
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.set_min_signature_length(2);
+		assert_eq!(tree.try_add_signature(vec![0x55, 0x00], Some(vec![0xff, 0x00]), 1), Err(DectreeError::TooShort { effective: 1, minimum: 2 }));
+		tree.set_wildcard_tail(WildcardTail::Trim);
+		assert_eq!(tree.try_add_signature(vec![0x00, 0x00], Some(vec![0x00, 0x00]), 2), Err(DectreeError::EmptyPattern));
+		assert!(tree.try_add_signature(vec![0x55, 0x8b, 0x00], Some(vec![0xff, 0xff, 0x00]), 3).is_ok());
+		assert_eq!(tree.try_add_signature(vec![0x55, 0x8b], None, 4), Err(DectreeError::Duplicate));
+		assert_eq!(tree.get_signature([0x55, 0x8b], None), Some(3));
+		assert!(tree.try_add_signature(vec![0x55, 0x8f], Some(vec![0xff, 0xf0]), 5).is_ok());
+		assert_eq!(tree.try_add_signature(vec![0x55, 0x80, 0x00], Some(vec![0xff, 0xf0, 0x00]), 6), Err(DectreeError::Duplicate));
+	}
+
+	#[test]
+	fn test_set_enabled() {
+		use super::SignatureId;
+		let mut tree = super::SignatureDecisionTree::new();
+		let short = tree.add_signature_with_id(vec![0xc3], None, 1).unwrap();
+		let long = tree.add_signature_with_id(vec![0xc3, 0xcc], None, 2).unwrap();
+		assert_ne!(short, long);
+		tree.set_enabled(short, false);
+		assert_eq!(tree.scan([0xc3, 0xcc, 0xc3]), vec![(0, 2)]);
+		tree.set_enabled(long, false);
+		assert!(tree.scan([0xc3, 0xcc, 0xc3]).is_empty());
+		tree.set_enabled(short, true);
+		assert_eq!(tree.scan([0xc3, 0xcc, 0xc3]), vec![(0, 1), (2, 1)]);
+		assert!(!tree.set_enabled(SignatureId(7), false));
+		assert!(!tree.is_enabled(SignatureId(7)));
+	}
+
+	#[test]
+	fn test_scan_sampled() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0xcc], None, 1);
+		tree.add_signature(vec![0x00, 0x00], None, 2);
+		let sampled = tree.scan_sampled([0x00, 0x00, 0x00, 0xcc, 0xcc, 0xcc, 0xcc], 1);
+		assert_eq!(sampled.hits, vec![(0, 2), (3, 1)]);
+		assert_eq!(sampled.suppressed.iter().map(|hit| (hit.bytes.clone(), hit.count)).collect::<Vec<_>>(), vec![(vec![0xcc], 3), (vec![0x00, 0x00], 1)]);
+		assert_eq!(tree.scan_sampled([0xcc, 0xcc], 0).hits, vec![]);
+		assert!(tree.scan_sampled([0xcc, 0xcc], 2).suppressed.is_empty());
+	}
+
+	#[test]
+	fn test_wildcard_branch() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x00, 0x02], None, 1);
+		tree.add_signature(vec![0x55, 0x8b, 0x01], Some(vec![0xff, 0x00, 0xff]), 2);
+		tree.add_signature(vec![0x55, 0x8b, 0x02, 0x03], None, 3);
+		assert!(!tree.add_signature(vec![0x55, 0x42, 0x01], Some(vec![0xff, 0x00, 0xff]), 4));
+		assert_eq!(tree.nodes[1].wildcard.map(|child| tree.nodes[child.get() as usize].subtree_signatures.len()), Some(1));
+		assert_eq!(tree.get_signature([0x55, 0x00, 0x01], None), Some(2));
+		assert_eq!(tree.get_signature([0x55, 0x00, 0x02], None), Some(1));
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0x01], None), Some(2));
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0x02, 0x03], None), Some(3));
+		assert_eq!(tree.get_match([0x55, 0x8b, 0x01], None).unwrap().bytes, vec![0x55, 0x00, 0x01]);
+	}
+
+	#[test]
+	fn test_constant_adders() {
+		let mut tree = super::SignatureDecisionTree::new();
+		assert!(tree.add_u32_be(0xcafebabe, 1));
+		assert!(tree.add_u64_le(0x0102030405060708, 2));
+		assert!(tree.add_u64_be(0x0102030405060708, 3));
+		assert!(!tree.add_u32_le(0xbebafeca, 4));
+		assert_eq!(tree.get_signature([0xca, 0xfe, 0xba, 0xbe], None), Some(1));
+		assert_eq!(tree.get_signature([0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01], None), Some(2));
+		assert_eq!(tree.get_signature([0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08], None), Some(3));
+		assert!(tree.add_guid("{6b29fc40-ca47-1067-b31d}", 5).is_err());
+	}
+
+	#[test]
+	fn test_clone_is_independent() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		let mut copy = tree.clone();
+		copy.add_signature(vec![0x55, 0xe9, 0xd8], None, 2);
+		assert_eq!(copy.get_signature([0x55, 0xe9, 0xd8], None), Some(2));
+		assert_eq!(tree.get_signature([0x55, 0xe9, 0xd8], None), Some(1));
+		assert_eq!(tree.nodes.len(), 1);
+	}
+
+	#[test]
+	fn test_concurrent_queries() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 2);
+		let tree = &tree;
+		std::thread::scope(|scope| {
+			let workers: Vec<_> = (0..4).map(|_| scope.spawn(move || tree.scan([0x00, 0x55, 0xe9, 0xd8, 0x01]))).collect();
+			for worker in workers {
+				assert_eq!(worker.join().unwrap(), vec![(1, 2)]);
+			}
+		});
+	}
+
+	#[test]
+	fn test_scan_what_if() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b], None, 1);
+		let id = tree.add_signature_with_id(vec![0xc3], None, 2).unwrap();
+		tree.set_enabled(id, false);
+		let buffer = [0x55, 0x8b, 0xc3, 0x90, 0xc3];
+		assert_eq!(tree.scan(buffer), vec![(0, 1)]);
+		let hits = tree.scan_what_if(buffer).into_iter().map(|found| (found.offset, found.object, found.enabled)).collect::<Vec<_>>();
+		assert_eq!(hits, vec![(0, 1, true), (2, 2, false), (4, 2, false)]);
+		assert!(tree.scan_matches(buffer).iter().all(|found| found.enabled));
+	}
+
+	#[test]
+	fn test_provenance() {
+		use super::provenance::{ImportFormat, Provenance};
+		let mut tree = super::SignatureDecisionTree::new();
+		let id = tree.add_signature_with_id(vec![0x55, 0x8b], None, 1).unwrap();
+		assert_eq!(tree.provenance(id), None);
+		let provenance = Provenance::new(ImportFormat::HexString, "55 8B").path("rules.txt").line(3);
+		assert!(tree.set_provenance(id, provenance.clone()));
+		assert_eq!(tree.provenance(tree.get_match([0x55, 0x8b], None).unwrap().id), Some(&provenance));
+		assert_eq!(tree.add_guid("{6b29fc40-ca47-1067-b31d-00dd010662da}", 2), Ok(true));
+		let memory = [0x40, 0xfc, 0x29, 0x6b, 0x47, 0xca, 0x67, 0x10, 0xb3, 0x1d, 0x00, 0xdd, 0x01, 0x06, 0x62, 0xda];
+		let found = tree.get_match(memory, None).unwrap();
+		assert_eq!(tree.provenance(found.id).map(|provenance| provenance.format), Some(ImportFormat::Guid));
+		assert_eq!(tree.add_signature_str("55 8B", 3), Ok(false));
+		assert_eq!(tree.provenance(id), Some(&provenance));
+	}
+
+	#[test]
+	fn test_targets() {
+		use super::target::{Arch, FileType, Target};
+		let mut tree = super::SignatureDecisionTree::new();
+		let x86 = tree.add_signature_with_id(vec![0x55, 0x89, 0xe5], None, 1).unwrap();
+		let arm = tree.add_signature_with_id(vec![0x2d, 0xe9], None, 2).unwrap();
+		tree.add_signature(vec![0x55, 0x89], None, 3);
+		assert!(tree.set_target(x86, Target::any().arch(Arch::X86).bitness(32)));
+		tree.set_target(arm, Target::any().arch(Arch::Arm).file_type(FileType::Raw));
+		assert_eq!(tree.target(arm).and_then(|target| target.arch), Some(Arch::Arm));
+		let buffer = [0x55, 0x89, 0xe5, 0x2d, 0xe9];
+		assert_eq!(tree.scan(buffer), vec![(0, 1), (3, 2)]);
+		assert_eq!(tree.scan_for(buffer, &Target::any().arch(Arch::Arm)), vec![(0, 3), (3, 2)]);
+		assert_eq!(tree.scan_for(buffer, &Target::any().bitness(64)), vec![(0, 3), (3, 2)]);
+		assert_eq!(tree.scan_for(buffer, &Target::any().file_type(FileType::Pe)), vec![(0, 1)]);
+	}
+
+	#[test]
+	fn test_stats() {
+		let mut tree = super::SignatureDecisionTree::new();
+		assert!(tree.is_empty());
+		let empty = tree.stats();
+		assert_eq!((empty.signatures, empty.nodes, empty.max_depth, empty.average_branching), (0, 1, 0, 0.0));
+		tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+		tree.add_signature(vec![0x55, 0x00, 0xec], Some(vec![0xff, 0x00, 0xff]), 2);
+		tree.add_signature(vec![0x55, 0x8b, 0xec, 0x83], None, 3);
+		assert_eq!(tree.len(), 3);
+		let stats = tree.stats();
+		assert_eq!((stats.signatures, stats.max_depth), (3, 3));
+		assert!(stats.heap_bytes > empty.heap_bytes);
+	}
+
+	#[test]
+	fn test_extend() {
+		let mut tree = [(vec![0x55, 0x8b], None, 1), (vec![0x55, 0x8b], None, 2)].into_iter().collect::<super::SignatureDecisionTree<_>>();
+		assert_eq!(tree.len(), 1);
+		tree.extend([(vec![0x55, 0x00, 0xec], Some(vec![0xff, 0x00, 0xff]), 3), (vec![], None, 4)]);
+		assert_eq!(tree.len(), 2);
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(3));
+		let copy = tree.iter().map(|(bytes, masks, object)| (bytes.to_vec(), Some(masks.into_owned()), *object)).collect::<super::SignatureDecisionTree<_>>();
+		assert_eq!(copy.scan([0x55, 0x8b, 0x55, 0x42, 0xec]), tree.scan([0x55, 0x8b, 0x55, 0x42, 0xec]));
+	}
+
+	#[test]
+	fn test_get_signature_in_range() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x4d, 0x5a], None, 1);
+		tree.add_signature(vec![0x4d, 0x5a, 0x90], None, 2);
+		let bytes = [0x00, 0x4d, 0x5a, 0x00, 0x4d, 0x5a, 0x90];
+		assert_eq!(tree.get_signature_in_range(bytes, 0..3), Some((1, 1)));
+		assert_eq!(tree.get_signature_in_range(bytes, 0..usize::MAX), Some((4, 2)));
+		assert_eq!(tree.get_signature_in_range(bytes, 5..usize::MAX), None);
+		assert_eq!(tree.get_signature_in_range(bytes, 9..12), None);
+		tree.set_overlap_policy(super::OverlapPolicy::PreferTerminal);
+		assert_eq!(tree.get_signature_in_range(bytes, 0..usize::MAX), Some((1, 1)));
+	}
+
+	#[test]
+	fn test_scan_near_misses() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b, 0xec, 0x83, 0xec, 0x10], None, 1);
+		tree.add_signature(vec![0x55, 0x8b, 0x00, 0x5d, 0xc3], Some(vec![0xff, 0xff, 0x00, 0xff, 0xff]), 2);
+		tree.add_signature(vec![0x55, 0x8b], None, 3);
+		let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec, 0x90], None, 4).unwrap();
+		tree.set_enabled(id, false);
+		let buffer = [0x55, 0x8b, 0xec, 0x83, 0xcc, 0x10, 0x55, 0x8b, 0x42, 0x5d];
+		let misses = tree.scan_near_misses(buffer, 2);
+		let found = misses.iter().map(|miss| (miss.offset, miss.object, miss.matched, miss.position)).collect::<Vec<_>>();
+		// Signature 2 diverges at offset 0 after its wildcard, which isn't counted, and runs out at
+		// offset 6, where signature 3 matches.
+		assert_eq!(found, vec![(0, 1, 4, 4), (0, 2, 2, 3), (6, 1, 2, 2)]);
+		assert_eq!(tree.scan_near_misses(buffer, 3).len(), 1);
+		assert_eq!(tree.scan_near_misses(buffer, 0).len(), 3);
+	}
+
+	#[test]
+	fn test_update_value() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+		let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0x00, 0x83], Some(vec![0xff, 0xff, 0x00, 0xff]), 2).unwrap();
+		tree.add_signature(vec![0x55, 0x8b, 0xec, 0x00], Some(vec![0xff, 0xff, 0xff, 0x00]), 3);
+		tree.set_enabled(id, false);
+		// Equivalent masks find the same signature.
+		assert_eq!(tree.update_value(vec![0x55, 0x8b, 0x42, 0x83], Some(vec![0xff, 0xff, 0x00, 0xff]), 4), Ok(Some(2)));
+		assert_eq!(tree.what_if_matches([0x55, 0x8b, 0x00, 0x83], None).iter().map(|found| (found.object, found.enabled)).collect::<Vec<_>>(), vec![(4, false)]);
+		assert_eq!(tree.update_value(vec![0x55, 0x8b, 0xec], None, 5), Ok(Some(1)));
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(5));
+		assert_eq!(tree.update_value(vec![0x55, 0x8b, 0xec, 0x83], None, 6), Ok(None));
+		assert_eq!(tree.update_value(vec![], None, 7), Err(super::DectreeError::EmptyPattern));
+		assert_eq!(tree.len(), 3);
+	}
+
+	#[test]
+	fn test_get_by_pattern() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+		tree.add_signature(vec![0x55, 0x8b, 0x00, 0x83], Some(vec![0xff, 0xff, 0x00, 0xff]), 2);
+		tree.set_duplicate_policy(super::DuplicatePolicy::Append);
+		tree.add_signature(vec![0x55, 0x8b, 0xec], None, 3);
+		assert_eq!(tree.get_by_pattern(&[0x55, 0x8b, 0xec], None), Some(&1));
+		assert_eq!(tree.get_by_pattern(&[0x55, 0x8b, 0xec], Some(&[0xff, 0xff, 0xff])), Some(&1));
+		assert_eq!(tree.get_by_pattern(&[0x55, 0x8b, 0x90, 0x83], Some(&[0xff, 0xff, 0x00, 0xff])), Some(&2));
+		assert!(!tree.contains_pattern(&[0x55, 0x8b, 0xec, 0x83], None));
+		assert!(!tree.contains_pattern(&[0x55, 0x8b], None));
+		assert!(!tree.contains_pattern(&[0x55, 0x8b], Some(&[0xff])));
+		assert!(!tree.contains_pattern(&[], None));
+	}
+
+	#[test]
+	fn test_staging() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.set_duplicate_policy(super::DuplicatePolicy::Replace);
+		let primary = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 1).unwrap();
+		tree.set_staging(true);
+		let duplicate = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, 2).unwrap();
+		let longer = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec, 0x83], None, 3).unwrap();
+		tree.set_staging(false);
+		assert_ne!(primary, duplicate);
+		assert!(tree.is_staged(duplicate) && tree.is_staged(longer) && !tree.is_staged(primary));
+		let buffer = [0x55, 0x8b, 0xec, 0x83, 0x55, 0x8b, 0xec];
+		assert_eq!(tree.scan(buffer), vec![(0, 1), (4, 1)]);
+		assert!(tree.scan_near_misses([0x55, 0x8b, 0xec, 0x90], 1).is_empty());
+		let staged = tree.scan_staged(buffer).into_iter().map(|found| (found.offset, found.object)).collect::<Vec<_>>();
+		assert_eq!(staged, vec![(0, 3), (4, 2)]);
+		assert!(tree.set_staged(longer, false));
+		assert_eq!(tree.scan(buffer), vec![(0, 3), (4, 1)]);
+		assert_eq!(tree.promote_staged(), 1);
+		assert!(tree.scan_staged(buffer).is_empty());
+	}
+
+	#[test]
+	fn test_get() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b], None, vec![1]);
+		tree.add_signature(vec![0x55, 0x8b, 0xec], None, vec![2]);
+		assert_eq!(tree.get([0x90, 0x55, 0x8b, 0xec], Some(1)), Some(&vec![2]));
+		tree.get_mut([0x55, 0x8b, 0x90], None).unwrap().push(3);
+		assert_eq!(tree.get([0x55, 0x8b], None), Some(&vec![1, 3]));
+		assert_eq!(tree.get_mut([0x8b], None), None);
+	}
+
+	#[test]
+	fn test_duplicate_policy() {
+		use super::{DectreeError, DuplicatePolicy, OverlapPolicy};
+		let mut tree = super::SignatureDecisionTree::new();
+		let id = tree.try_add_signature(vec![0x55, 0x00, 0xec], Some(vec![0xff, 0x00, 0xff]), 1).unwrap();
+		tree.add_signature(vec![0x55, 0x8b, 0xec, 0x83], None, 2);
+		assert_eq!(tree.try_add_signature(vec![0x55, 0x42, 0xec], Some(vec![0xff, 0x00, 0xff]), 3), Err(DectreeError::Duplicate));
+		tree.set_duplicate_policy(DuplicatePolicy::Ignore);
+		assert_eq!(tree.try_add_signature(vec![0x55, 0x42, 0xec], Some(vec![0xff, 0x00, 0xff]), 3), Ok(id));
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(1));
+		tree.set_duplicate_policy(DuplicatePolicy::Replace);
+		assert_eq!(tree.try_add_signature(vec![0x55, 0x42, 0xec], Some(vec![0xff, 0x00, 0xff]), 4), Ok(id));
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(4));
+		tree.set_duplicate_policy(DuplicatePolicy::Append);
+		let appended = tree.try_add_signature(vec![0x55, 0x42, 0xec], Some(vec![0xff, 0x00, 0xff]), 5).unwrap();
+		assert_ne!(appended, id);
+		assert_eq!(tree.len(), 3);
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(4));
+		tree.set_overlap_policy(OverlapPolicy::ReportAll);
+		let mut all = tree.get_signatures([0x55, 0x8b, 0xec], None);
+		all.sort_unstable();
+		assert_eq!(all, vec![4, 5]);
+	}
+
+	#[test]
+	fn test_constraints() {
+		use super::constraint::{Computation, Constraint};
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x7e], None, "any");
+		let id = tree.add_signature_with_id(vec![0x7e, 0x00, 0x00, 0x00], Some(vec![0xff, 0x00, 0x00, 0x00]), "framed").unwrap();
+		assert!(tree.set_constraints(id, vec![Constraint::new(3, Computation::Xor(1..3))]));
+		assert_eq!(tree.constraints(id).len(), 1);
+		assert!(!tree.set_constraints(id, vec![Constraint::new(4, Computation::Xor(1..3))]));
+		assert!(!tree.set_constraints(id, vec![Constraint::new(0, Computation::Crc32Le(0..5))]));
+		assert_eq!(tree.constraints(id).len(), 1);
+		assert_eq!(tree.scan([0x7e, 0x0f, 0xf0, 0xff, 0x7e, 0x0f, 0xf0, 0x00]), vec![(0, "framed"), (4, "any")]);
+		tree.set_constraints(id, Vec::new());
+		assert_eq!(tree.get_signature([0x7e, 0x0f, 0xf0, 0x00], None), Some("framed"));
+	}
+
+	#[test]
+	fn test_near_misses_reported_once() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x10, 0x00, 0x10, 0x00, 0x05], Some(vec![0xff, 0x0f, 0xff, 0x00, 0x0f]), 0);
+		tree.add_signature(vec![0x00, 0x5a], None, 1);
+		tree.add_signature(vec![0x00], Some(vec![0xf0]), 2);
+		tree.add_signature(vec![0x10, 0x00, 0x01, 0x01], Some(vec![0xff, 0xf0, 0xff, 0x0f]), 3);
+		tree.add_signature(vec![0x10], Some(vec![0xf0]), 4);
+		let misses = tree.scan_near_misses([0x10, 0x10], 1).into_iter().map(|miss| (miss.id, miss.offset, miss.matched, miss.position)).collect::<Vec<_>>();
+		assert_eq!(misses, vec![(super::SignatureId(3), 0, 1, 1)]);
+	}
+
+	#[test]
+	fn test_path_compression() {
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x90; 64], None, 1);
+		tree.add_signature([vec![0x90; 63], vec![0xc3]].concat(), None, 2);
+		// The root, one node for the 63 shared bytes and a leaf for each signature.
+		assert_eq!(tree.stats().nodes, 4);
+		tree.add_signature([vec![0x90; 32], vec![0xcc]].concat(), None, 3);
+		let mut masks = vec![0xff; 40];
+		masks[10] = 0xf0;
+		tree.add_signature(vec![0x90; 40], Some(masks), 4);
+		assert_eq!(tree.get_signature([0x90; 64], None), Some(1));
+		assert_eq!(tree.get_signature([vec![0x90; 63], vec![0xc3]].concat(), None), Some(2));
+		assert_eq!(tree.get_signature([vec![0x90; 32], vec![0xcc]].concat(), None), Some(3));
+		let mut nibble = [0x90; 64];
+		nibble[10] = 0x9a;
+		assert_eq!(tree.get_signature(nibble, None), Some(4));
+		assert_eq!(tree.get_signature([vec![0x90; 20], vec![0x00]].concat(), None), None);
+		assert_eq!(tree.match_failure_reason([vec![0x90; 20], vec![0x00]].concat(), None), Some(super::MatchFailure::Diverged { depth: 20, expected: vec![0x90] }));
+		assert_eq!(tree.scan_near_misses([vec![0x90; 20], vec![0x00; 50]].concat(), 20).len(), 4);
+		let rebuilt = super::SignatureDecisionTree::build_from(tree.iter().map(|(bytes, masks, object)| (bytes.to_vec(), masks.into_owned(), *object)).collect());
+		assert_eq!(rebuilt.stats().nodes, tree.stats().nodes);
+		assert_eq!(rebuilt.get_signature(nibble, None), Some(4));
+	}
+
+	#[test]
+	fn test_sparse_children() {
+		let mut tree = super::SignatureDecisionTree::new();
+		for byte in (0..=0xff).rev() {
+			tree.add_signature(vec![byte, 0x00, byte], None, u16::from(byte));
+		}
+		tree.add_signature(vec![0x10, 0x01], None, 0x100);
+		assert!(matches!(tree.nodes[0].choices, super::Children::Dense(..)));
+		assert!((0..=0xff).all(|byte| tree.get_signature([byte, 0x00, byte], None) == Some(u16::from(byte))));
+		assert_eq!(tree.get_signature([0x10, 0x01], None), Some(0x100));
+		assert_eq!(tree.get_signature([0x10, 0x02], None), None);
+		// A chain of single children costs a few bytes per node, where a full table of 256 children
+		// took a kilobyte.
+		let mut chain = super::SignatureDecisionTree::new();
+		chain.add_signature(vec![0x90; 64], None, 1);
+		chain.add_signature([vec![0x90; 63], vec![0xc3]].concat(), None, 2);
+		let stats = chain.stats();
+		assert!(stats.heap_bytes < stats.nodes * 512);
+	}
+
+	#[test]
+	fn test_heap_bytes() {
+		// 10,000 pseudo-random signatures of 8 to 31 bytes, the same on every run.
+		let mut state = 0x2545_f491_4f6c_dd1d_u64;
+		let mut next = || {
+			state ^= state << 13;
+			state ^= state >> 7;
+			state ^= state << 17;
+			state
+		};
+		let sigs = (0..10_000u32).map(|object| {
+			let length = 8 + (next() % 24) as usize;
+			let bytes = (0..length).map(|_| next() as u8).collect::<Vec<_>>();
+			(bytes, vec![0xff; length], object)
+		}).collect::<Vec<_>>();
+		let stats = super::SignatureDecisionTree::build_from(sigs).stats();
+		assert_eq!(stats.nodes, 11_470);
+		// With a table of 256 child slots per node, the tables alone took more than twice what the
+		// whole tree takes now.
+		let tables = stats.nodes * 256 * std::mem::size_of::<Option<super::NodeIndex>>();
+		assert!(stats.heap_bytes * 2 < tables, "{} bytes", stats.heap_bytes);
+	}
+
+	#[test]
+	fn test_build_from() {
+		let sigs = vec![
+			(vec![0x55, 0x8b, 0xec, 0x83], vec![0xff; 4], 1),
+			(vec![0x55, 0x8b, 0xec], vec![0xff; 3], 2),
+			(vec![0x55, 0x00, 0xec], vec![0xff, 0x00, 0xff], 3),
+			(vec![0x50, 0x8b], vec![0xf0, 0xff], 4),
+			(vec![0x55, 0x8b, 0xec], vec![0xff; 3], 5),
+			(vec![0xc3], vec![0xff], 6),
+			(vec![0x55], vec![0xff, 0xff], 7)
+		];
+		let built = super::SignatureDecisionTree::build_from(sigs.clone());
+		let mut added = super::SignatureDecisionTree::new();
+		for (bytes, masks, val) in sigs {
+			added.add_signature(bytes, Some(masks), val);
+		}
+		assert_eq!(built.len(), 5);
+		assert_eq!(built.stats().nodes, added.stats().nodes);
+		let buffer = [0x55, 0x8b, 0xec, 0x83, 0x55, 0x42, 0xec, 0x5f, 0x8b, 0xc3, 0x55, 0x8b, 0xec];
+		assert_eq!(built.scan(buffer), added.scan(buffer));
+		assert_eq!(built.scan_matches(buffer), added.scan_matches(buffer));
+	}
+
+	#[test]
+	fn test_iter() {
+		let mut tree = super::SignatureDecisionTree::new();
+		let runs = [vec![0xff; 2], vec![0x00; 32]].concat();
+		tree.add_signature([vec![0x4d, 0x5a], vec![0x00; 32]].concat(), Some(runs.clone()), 1);
+		tree.add_signature(vec![0x55, 0x8b], None, 2);
+		let id = tree.add_signature_with_id(vec![0x50], Some(vec![0xf0]), 3).unwrap();
+		tree.set_enabled(id, false);
+		assert_eq!(tree.iter().len(), 3);
+		let masks = tree.iter().map(|(_, masks, _)| masks.into_owned()).collect::<Vec<_>>();
+		assert_eq!(masks, vec![runs, vec![0xff, 0xff], vec![0xf0]]);
+		let objects = (&tree).into_iter().map(|(_, _, object)| *object).collect::<Vec<_>>();
+		assert_eq!(objects, vec![1, 2, 3]);
+	}
+
+	#[test]
+	fn test_objects_without_default() {
+		#[derive(Clone, Debug, PartialEq)]
+		struct Rule(&'static str);
+		let mut tree = super::SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b], None, Rule("prologue"));
+		tree.add_signature_str("c3", Rule("ret")).unwrap();
+		assert_eq!(tree.get_signature([0x55, 0x8b, 0xec], None), Some(Rule("prologue")));
+		assert_eq!(tree.scan([0x55, 0x8b, 0xc3]), vec![(0, Rule("prologue")), (2, Rule("ret"))]);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_serde_round_trip() {
+		use super::{OverlapPolicy, SignatureDecisionTree};
+		let mut tree = SignatureDecisionTree::new();
+		tree.set_overlap_policy(OverlapPolicy::PreferTerminal);
+		tree.add_signature(vec![0x55, 0xe9], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 2);
+		tree.add_signature(vec![0x55, 0x00, 0x77], Some(vec![0xff, 0x00, 0xff]), 3);
+		let json = serde_json::to_string(&tree).unwrap();
+		let mut loaded: SignatureDecisionTree<i32> = serde_json::from_str(&json).unwrap();
+		assert_eq!(loaded.overlap_policy(), OverlapPolicy::PreferTerminal);
+		assert_eq!(loaded.max_signature_length(), 4);
+		assert_eq!(loaded.get_signature([0x55, 0xe9, 0xd8, 0x01], None), Some(1));
+		assert_eq!(loaded.get_signature([0x55, 0x42, 0x77], None), Some(3));
+		assert_eq!(loaded.scan([0x00, 0x55, 0xe9]), vec![(1, 1)]);
+		assert!(!loaded.add_signature(vec![0x55, 0xe9], None, 4));
+		assert!(loaded.add_signature(vec![0x55, 0xe9, 0xd8], None, 5));
+	}
 }
@@ -0,0 +1,165 @@
+//! Parsing of IDA/YARA-style hex-string patterns such as `"55 8B ?? EC 83"`.
+//!
+//! Each byte is written as two hex digits, case-insensitively, and a wildcard byte is written
+//! as `??` (or a lone `?`). Bytes may be separated by whitespace or run together (`558B??EC`).
+//! Wildcards become a `0x00` byte with a `0x00` mask, so they match any input byte.
+//!
+//! Either half of a byte can be left out on its own with a nibble wildcard: `5?` becomes the byte
+//! `0x50` under the mask `0xf0`, matching `0x50` to `0x5f`, and `?4` becomes `0x04` under `0x0f`,
+//! matching any byte whose low nibble is `4`. Since a `?` followed by a hex digit is a nibble
+//! wildcard, a lone `?` must be followed by whitespace or the end of the pattern.
+//!
+//! GUIDs written in registry format, such as `{6b29fc40-ca47-1067-b31d-00dd010662da}`, are parsed
+//! by `parse_guid()` into the bytes they occupy in memory.
+
+use std::fmt::{Display, Formatter};
+
+/// Represents the ways a hex-string pattern can be malformed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+	/// The pattern contains no bytes at all.
+	Empty,
+	/// A character that isn't a hex digit, a `?` or whitespace, at character index `position`.
+	InvalidCharacter {
+		position: usize,
+		character: char
+	},
+	/// A byte is missing its second digit, starting at character index `position`.
+	IncompleteByte {
+		position: usize
+	},
+	/// A GUID with `length` characters between its braces, rather than the 36 of the
+	/// `8-4-4-4-12` layout.
+	GuidLength {
+		length: usize
+	}
+}
+
+impl Display for ParseError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ParseError::Empty => write!(f, "pattern is empty"),
+			ParseError::InvalidCharacter { position, character } => write!(f, "invalid character {character:?} at position {position}"),
+			ParseError::IncompleteByte { position } => write!(f, "incomplete byte at position {position}"),
+			ParseError::GuidLength { length } => write!(f, "GUID has {length} characters, expected 36")
+		}
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a hex-string pattern into its signature bytes and masks.
+/// ```rust
+/// use dectree_rs::parser::parse_hex_pattern;
+///
+/// let (bytes, masks) = parse_hex_pattern("55 8B ?? EC E8 ?4 5?").unwrap();
+/// assert_eq!(bytes, vec![0x55, 0x8b, 0x00, 0xec, 0xe8, 0x04, 0x50]);
+/// assert_eq!(masks, vec![0xff, 0xff, 0x00, 0xff, 0xff, 0x0f, 0xf0]);
+/// ```
+pub fn parse_hex_pattern(pattern: &str) -> Result<(Vec<u8>, Vec<u8>), ParseError> {
+	let (mut bytes, mut masks) = (Vec::new(), Vec::new());
+	let mut chars = pattern.chars().enumerate().peekable();
+	while let Some((position, character)) = chars.next() {
+		if character.is_whitespace() {
+			continue
+		}
+		let high = nibble(position, character)?;
+		let low = match chars.peek() {
+			Some(&(position, character)) if !character.is_whitespace() => {
+				chars.next();
+				nibble(position, character)?
+			},
+			// A lone `?` is a whole wildcard byte, just like `??`.
+			_ if high.is_none() => None,
+			_ => return Err(ParseError::IncompleteByte { position })
+		};
+		let (high, high_mask) = high.map_or((0x0, 0x00), |digit| (digit << 4, 0xf0));
+		let (low, low_mask) = low.map_or((0x0, 0x00), |digit| (digit, 0x0f));
+		bytes.push(high | low);
+		masks.push(high_mask | low_mask);
+	}
+	if bytes.is_empty() {
+		return Err(ParseError::Empty)
+	}
+	Ok((bytes, masks))
+}
+
+/// Parse a GUID in registry format (`6b29fc40-ca47-1067-b31d-00dd010662da`, optionally wrapped in
+/// braces) into the 16 bytes of its in-memory `GUID` structure. The first three groups are stored
+/// little endian, as Windows lays them out, and the last two in the order written.
+/// ```rust
+/// use dectree_rs::parser::parse_guid;
+///
+/// let guid = parse_guid("{6B29FC40-CA47-1067-B31D-00DD010662DA}").unwrap();
+/// assert_eq!(guid, [0x40, 0xfc, 0x29, 0x6b, 0x47, 0xca, 0x67, 0x10, 0xb3, 0x1d, 0x00, 0xdd, 0x01, 0x06, 0x62, 0xda]);
+/// ```
+pub fn parse_guid(guid: &str) -> Result<[u8; 16], ParseError> {
+	const LAYOUT: &str = "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx";
+	let (start, inner) = match guid.strip_prefix('{').and_then(|inner| inner.strip_suffix('}')) {
+		Some(inner) => (1, inner),
+		None => (0, guid)
+	};
+	let length = inner.chars().count();
+	if length != LAYOUT.len() {
+		return Err(ParseError::GuidLength { length })
+	}
+	let mut digits = Vec::with_capacity(32);
+	for ((position, character), expected) in inner.chars().enumerate().zip(LAYOUT.chars()) {
+		let position = start + position;
+		match expected {
+			'-' if character != '-' => return Err(ParseError::InvalidCharacter { position, character }),
+			'-' => {},
+			_ => digits.push(hex_digit(position, character)?)
+		}
+	}
+	let mut bytes = [0u8; 16];
+	for (byte, pair) in bytes.iter_mut().zip(digits.chunks(2)) {
+		*byte = pair[0] << 4 | pair[1];
+	}
+	bytes[0..4].reverse();
+	bytes[4..6].reverse();
+	bytes[6..8].reverse();
+	Ok(bytes)
+}
+
+/// Parse half of a byte found at character index `position`: a hex digit, or `None` for `?`.
+fn nibble(position: usize, character: char) -> Result<Option<u8>, ParseError> {
+	match character {
+		'?' => Ok(None),
+		_ => hex_digit(position, character).map(Some)
+	}
+}
+
+/// Parse a single hex digit found at character index `position`.
+fn hex_digit(position: usize, character: char) -> Result<u8, ParseError> {
+	character.to_digit(16)
+		.map(|digit| digit as u8)
+		.ok_or(ParseError::InvalidCharacter { position, character })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_guid, parse_hex_pattern, ParseError};
+
+	#[test]
+	fn test_parse_hex_pattern() {
+		assert_eq!(parse_hex_pattern("55 8b ?? EC ? 83"), Ok((vec![0x55, 0x8b, 0x00, 0xec, 0x00, 0x83], vec![0xff, 0xff, 0x00, 0xff, 0x00, 0xff])));
+		assert_eq!(parse_hex_pattern("558B??EC"), Ok((vec![0x55, 0x8b, 0x00, 0xec], vec![0xff, 0xff, 0x00, 0xff])));
+		assert_eq!(parse_hex_pattern("  "), Err(ParseError::Empty));
+		assert_eq!(parse_hex_pattern("55 8G"), Err(ParseError::InvalidCharacter { position: 4, character: 'G' }));
+		assert_eq!(parse_hex_pattern("55 8 EC"), Err(ParseError::IncompleteByte { position: 3 }));
+		assert_eq!(parse_hex_pattern("55 8"), Err(ParseError::IncompleteByte { position: 3 }));
+		assert_eq!(parse_hex_pattern("5? ?a ?"), Ok((vec![0x50, 0x0a, 0x00], vec![0xf0, 0x0f, 0x00])));
+		assert_eq!(parse_hex_pattern("?5 5"), Err(ParseError::IncompleteByte { position: 3 }));
+		assert_eq!(parse_hex_pattern("5"), Err(ParseError::IncompleteByte { position: 0 }));
+	}
+
+	#[test]
+	fn test_parse_guid() {
+		let expected = [0x40, 0xfc, 0x29, 0x6b, 0x47, 0xca, 0x67, 0x10, 0xb3, 0x1d, 0x00, 0xdd, 0x01, 0x06, 0x62, 0xda];
+		assert_eq!(parse_guid("6b29fc40-ca47-1067-b31d-00dd010662da"), Ok(expected));
+		assert_eq!(parse_guid("{6b29fc40-ca47-1067-b31d-00dd010662da"), Err(ParseError::GuidLength { length: 37 }));
+		assert_eq!(parse_guid("{6b29fc40-ca47-1067-b31d+00dd010662da}"), Err(ParseError::InvalidCharacter { position: 24, character: '+' }));
+		assert_eq!(parse_guid("6b29fc40-ca47-1067-b31d-00dd010662dz"), Err(ParseError::InvalidCharacter { position: 35, character: 'z' }));
+	}
+}
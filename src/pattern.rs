@@ -0,0 +1,320 @@
+//! Programmatic construction of signatures.
+//!
+//! `PatternBuilder` assembles a signature piece by piece (literal bytes, wildcards, byte ranges and
+//! little or big endian integers) and keeps the bytes and masks in step, producing a validated
+//! `Pattern` that can be added to a tree with `SignatureDecisionTree::add_pattern()`.
+//!
+//! `pad_to()` and `align()` lay fields out at fixed offsets, so the in-memory image of a
+//! `#[repr(C)]` structure can be described field by field, with the fields that vary between
+//! instances left as wildcards:
+//! ```rust
+//! use std::mem::{offset_of, size_of};
+//! use dectree_rs::pattern::PatternBuilder;
+//!
+//! #[repr(C)]
+//! struct ObjectHeader {
+//!     kind: u16,
+//!     flags: u8,
+//!     refcount: u32,
+//!     magic: u32
+//! }
+//!
+//! let pattern = PatternBuilder::new()
+//!     .u16_le(0x0003)
+//!     .pad_to(offset_of!(ObjectHeader, magic))
+//!     .u32_le(0x6a624f21)
+//!     .pad_to(size_of::<ObjectHeader>())
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(pattern.len(), 12);
+//! assert_eq!(pattern.masks(), &[0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff]);
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+
+use crate::WildcardTail;
+
+/// Represents the ways a built pattern can be invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatternError {
+	/// The pattern contains no bytes at all.
+	Empty,
+	/// A byte range at `position` that a mask can't express. Only ranges covering an aligned
+	/// power-of-two block of values, such as `0x40..=0x4f`, can be matched by masking.
+	InvalidRange {
+		position: usize,
+		start: u8,
+		end: u8
+	},
+	/// Padding up to `offset` was requested, but the pattern already extends to `position`.
+	Overlap {
+		position: usize,
+		offset: usize
+	}
+}
+
+impl Display for PatternError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PatternError::Empty => write!(f, "pattern is empty"),
+			PatternError::InvalidRange { position, start, end } => write!(f, "byte range {start:#04x}..={end:#04x} at position {position} can't be expressed as a mask"),
+			PatternError::Overlap { position, offset } => write!(f, "can't pad to offset {offset}, the pattern already extends to {position}")
+		}
+	}
+}
+
+impl std::error::Error for PatternError {}
+
+/// Represents a signature as its bytes and the masks they are compared under. Every byte is stored
+/// already masked, as the tree expects.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Pattern {
+	bytes: Vec<u8>,
+	masks: Vec<u8>
+}
+
+impl Pattern {
+	/// Get the signature bytes.
+	pub fn bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	/// Get the comparison masks, one per signature byte.
+	pub fn masks(&self) -> &[u8] {
+		&self.masks
+	}
+
+	/// Get the number of bytes in the pattern.
+	pub fn len(&self) -> usize {
+		self.bytes.len()
+	}
+
+	/// Check whether the pattern has no bytes. Built patterns never are.
+	pub fn is_empty(&self) -> bool {
+		self.bytes.is_empty()
+	}
+
+	/// Split the pattern into its bytes and masks.
+	pub fn into_parts(self) -> (Vec<u8>, Vec<u8>) {
+		(self.bytes, self.masks)
+	}
+
+	/// Bring the pattern into the canonical form the tree stores signatures in: bits outside the
+	/// masks are cleared and, under `WildcardTail::Trim`, trailing fully-wildcarded bytes are
+	/// dropped. Two patterns that match the same inputs in a tree with that policy are equal once
+	/// simplified. Fails with `PatternError::Empty` if nothing but wildcards is left.
+	/// ```rust
+	/// use dectree_rs::WildcardTail;
+	/// use dectree_rs::pattern::PatternBuilder;
+	///
+	/// let pattern = PatternBuilder::new().masked(0x5f, 0xf0).wildcard(2).build().unwrap();
+	/// let simplified = pattern.simplify(WildcardTail::Trim).unwrap();
+	/// assert_eq!(simplified.bytes(), &[0x50]);
+	/// assert_eq!(simplified.masks(), &[0xf0]);
+	/// ```
+	pub fn simplify(self, wildcard_tail: WildcardTail) -> Result<Pattern, PatternError> {
+		let (bytes, masks) = canonicalize(self.bytes, Some(self.masks), wildcard_tail);
+		if bytes.is_empty() {
+			return Err(PatternError::Empty)
+		}
+		let masks = masks.unwrap_or_else(|| vec![0xff; bytes.len()]);
+		Ok(Pattern { bytes, masks })
+	}
+}
+
+/// Canonicalize a signature as `Pattern::simplify()` does. Masks that are all `0xff` are dropped,
+/// so an exact signature has a single representation whether or not its masks were spelled out.
+pub(crate) fn canonicalize(mut bytes: Vec<u8>, mut masks: Option<Vec<u8>>, wildcard_tail: WildcardTail) -> (Vec<u8>, Option<Vec<u8>>) {
+	if let (WildcardTail::Trim, Some(masks)) = (wildcard_tail, masks.as_mut()) {
+		while masks.last() == Some(&0x00) {
+			masks.pop();
+			bytes.pop();
+		}
+	}
+	if let Some(masks) = &masks {
+		for (byte, mask) in bytes.iter_mut().zip(masks) {
+			*byte &= mask;
+		}
+	}
+	if masks.as_ref().is_some_and(|masks| masks.iter().all(|&mask| mask == 0xff)) {
+		masks = None;
+	}
+	(bytes, masks)
+}
+
+/// Represents a signature under construction. Each method appends to the end of the pattern, and
+/// `build()` checks the result.
+/// ```rust
+/// use dectree_rs::pattern::PatternBuilder;
+///
+/// let pattern = PatternBuilder::new()
+///     .bytes(&[0x55, 0x8b])
+///     .wildcard(2)
+///     .byte_range(0x40..=0x4f)
+///     .u32_le(0xdeadbeef)
+///     .build()
+///     .unwrap();
+/// assert_eq!(pattern.bytes(), &[0x55, 0x8b, 0x00, 0x00, 0x40, 0xef, 0xbe, 0xad, 0xde]);
+/// assert_eq!(pattern.masks(), &[0xff, 0xff, 0x00, 0x00, 0xf0, 0xff, 0xff, 0xff, 0xff]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PatternBuilder {
+	bytes: Vec<u8>,
+	masks: Vec<u8>,
+	/// The first problem found while building, reported by `build()`.
+	error: Option<PatternError>
+}
+
+impl PatternBuilder {
+	/// Create a new, empty `PatternBuilder`.
+	pub fn new() -> Self {
+		PatternBuilder::default()
+	}
+
+	/// Append bytes that must match exactly.
+	pub fn bytes(mut self, bytes: &[u8]) -> Self {
+		self.bytes.extend_from_slice(bytes);
+		self.masks.resize(self.bytes.len(), 0xff);
+		self
+	}
+
+	/// Append a byte that must match exactly.
+	pub fn byte(self, byte: u8) -> Self {
+		self.bytes(&[byte])
+	}
+
+	/// Append a byte compared under `mask`, so only the bits set in `mask` must match.
+	pub fn masked(mut self, byte: u8, mask: u8) -> Self {
+		self.bytes.push(byte & mask);
+		self.masks.push(mask);
+		self
+	}
+
+	/// Append `count` bytes that match anything.
+	pub fn wildcard(mut self, count: usize) -> Self {
+		self.bytes.resize(self.bytes.len() + count, 0x00);
+		self.masks.resize(self.masks.len() + count, 0x00);
+		self
+	}
+
+	/// Append a byte that matches any value in `range`. The range must cover an aligned
+	/// power-of-two block of values (`0x40..=0x4f`, `0x00..=0x07`, a single value), since it is
+	/// matched by masking off the low bits; any other range makes `build()` fail.
+	pub fn byte_range(mut self, range: RangeInclusive<u8>) -> Self {
+		let (start, end) = range.into_inner();
+		let width = end.wrapping_sub(start);
+		let aligned = start <= end && width & width.wrapping_add(1) == 0 && start & width == 0;
+		if !aligned {
+			self.fail(PatternError::InvalidRange { position: self.bytes.len(), start, end });
+		}
+		self.masked(start, !width)
+	}
+
+	/// Append wildcards up to `offset` bytes from the start of the pattern, such as the offset of
+	/// the next fixed field of a structure. If the pattern already extends past `offset`,
+	/// `build()` fails.
+	pub fn pad_to(mut self, offset: usize) -> Self {
+		let position = self.bytes.len();
+		if position > offset {
+			self.fail(PatternError::Overlap { position, offset });
+		}
+		self.wildcard(offset.saturating_sub(position))
+	}
+
+	/// Append wildcards until the length of the pattern is a multiple of `alignment`, as the
+	/// compiler pads a field of that alignment.
+	///
+	/// # Panics
+	/// Panics if `alignment` is zero.
+	pub fn align(self, alignment: usize) -> Self {
+		let offset = self.bytes.len().next_multiple_of(alignment);
+		self.pad_to(offset)
+	}
+
+	/// Append a `u16` in little endian byte order.
+	pub fn u16_le(self, value: u16) -> Self {
+		self.bytes(&value.to_le_bytes())
+	}
+
+	/// Append a `u16` in big endian byte order.
+	pub fn u16_be(self, value: u16) -> Self {
+		self.bytes(&value.to_be_bytes())
+	}
+
+	/// Append a `u32` in little endian byte order.
+	pub fn u32_le(self, value: u32) -> Self {
+		self.bytes(&value.to_le_bytes())
+	}
+
+	/// Append a `u32` in big endian byte order.
+	pub fn u32_be(self, value: u32) -> Self {
+		self.bytes(&value.to_be_bytes())
+	}
+
+	/// Append a `u64` in little endian byte order.
+	pub fn u64_le(self, value: u64) -> Self {
+		self.bytes(&value.to_le_bytes())
+	}
+
+	/// Append a `u64` in big endian byte order.
+	pub fn u64_be(self, value: u64) -> Self {
+		self.bytes(&value.to_be_bytes())
+	}
+
+	/// Record `error`, unless an earlier problem was already found.
+	fn fail(&mut self, error: PatternError) {
+		self.error.get_or_insert(error);
+	}
+
+	/// Finish the pattern, or report the first problem with it.
+	pub fn build(self) -> Result<Pattern, PatternError> {
+		if let Some(error) = self.error {
+			return Err(error)
+		}
+		if self.bytes.is_empty() {
+			return Err(PatternError::Empty)
+		}
+		Ok(Pattern {
+			bytes: self.bytes,
+			masks: self.masks
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{canonicalize, PatternBuilder, PatternError};
+	use crate::WildcardTail;
+
+	#[test]
+	fn test_pattern_builder() {
+		let pattern = PatternBuilder::new().u16_be(0x4d5a).masked(0x9f, 0xf0).byte_range(0x07..=0x07).build().unwrap();
+		assert_eq!(pattern.clone().into_parts(), (vec![0x4d, 0x5a, 0x90, 0x07], vec![0xff, 0xff, 0xf0, 0xff]));
+		assert_eq!(pattern.len(), 4);
+		assert_eq!(PatternBuilder::new().byte_range(0x00..=0xff).build().unwrap().masks(), &[0x00]);
+		assert_eq!(PatternBuilder::new().byte(0x55).byte_range(0x41..=0x48).build(), Err(PatternError::InvalidRange { position: 1, start: 0x41, end: 0x48 }));
+		assert_eq!(PatternBuilder::new().byte_range(0x40..=0x45).build(), Err(PatternError::InvalidRange { position: 0, start: 0x40, end: 0x45 }));
+		let (start, end) = (0x4f, 0x40);
+		assert_eq!(PatternBuilder::new().byte_range(start..=end).build(), Err(PatternError::InvalidRange { position: 0, start, end }));
+		assert_eq!(PatternBuilder::new().wildcard(0).build(), Err(PatternError::Empty));
+	}
+
+	#[test]
+	fn test_pattern_layout() {
+		let pattern = PatternBuilder::new().byte(0x01).align(4).u32_le(0x10).pad_to(10).align(2).build().unwrap();
+		assert_eq!(pattern.masks(), &[0xff, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00]);
+		assert_eq!(PatternBuilder::new().u32_le(0x10).align(4).build().unwrap().len(), 4);
+		assert_eq!(PatternBuilder::new().u32_le(0x10).pad_to(2).build(), Err(PatternError::Overlap { position: 4, offset: 2 }));
+	}
+
+	#[test]
+	fn test_simplify() {
+		let pattern = PatternBuilder::new().byte(0x55).masked(0x8f, 0x0f).wildcard(1).build().unwrap();
+		assert_eq!(pattern.clone().simplify(WildcardTail::Require), Ok(pattern.clone()));
+		assert_eq!(pattern.simplify(WildcardTail::Trim).unwrap().masks(), &[0xff, 0x0f]);
+		assert_eq!(PatternBuilder::new().wildcard(3).build().unwrap().simplify(WildcardTail::Trim), Err(PatternError::Empty));
+		assert_eq!(canonicalize(vec![0x55, 0x8b], Some(vec![0xff, 0xff]), WildcardTail::Require), (vec![0x55, 0x8b], None));
+		assert_eq!(canonicalize(vec![0x55, 0x8b, 0x7f], Some(vec![0xff, 0xf0, 0x00]), WildcardTail::Require), (vec![0x55, 0x80, 0x00], Some(vec![0xff, 0xf0, 0x00])));
+	}
+}
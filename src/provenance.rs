@@ -0,0 +1,159 @@
+//! Tracking where signatures came from.
+//!
+//! A `Provenance` records the rule a signature was imported from: the file or feed it was read
+//! from, the format it was written in, and the original rule text and line. Importers attach it to
+//! every signature they add, and `SignatureDecisionTree::provenance()` looks it up again from the
+//! id of a match, so every hit can be traced back to the exact rule that produced it. The original
+//! text is kept as written, so `formats::export()` can reproduce imported rules unchanged.
+
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Represents the formats signatures can be imported from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ImportFormat {
+	/// An IDA/YARA-style hex string, as parsed by `parser::parse_hex_pattern()`.
+	HexString,
+	/// A GUID in registry format, as parsed by `parser::parse_guid()`.
+	Guid,
+	/// A hex string of a YARA rule, as parsed by `formats::yara`.
+	Yara,
+	/// A body-based ClamAV signature, as parsed by `formats::clamav`.
+	ClamAv,
+	/// A line of an IDA FLIRT pattern file, as parsed by `formats::flirt`.
+	Flirt,
+	/// An entry of a PEiD database, as parsed by `formats::peid`.
+	Peid,
+	/// A radare2 zignature, as parsed by `formats::zignature`.
+	Zignature
+}
+
+impl Display for ImportFormat {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ImportFormat::HexString => write!(f, "hex string"),
+			ImportFormat::Guid => write!(f, "GUID"),
+			ImportFormat::Yara => write!(f, "YARA"),
+			ImportFormat::ClamAv => write!(f, "ClamAV"),
+			ImportFormat::Flirt => write!(f, "FLIRT"),
+			ImportFormat::Peid => write!(f, "PEiD"),
+			ImportFormat::Zignature => write!(f, "r2 zignature")
+		}
+	}
+}
+
+impl FromStr for ImportFormat {
+	type Err = String;
+
+	/// Parse the short name of a format: `hex`, `guid`, `yara`, `clamav`, `flirt`, `peid` or `r2`,
+	/// case-insensitively.
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		match name.to_ascii_lowercase().as_str() {
+			"hex" => Ok(ImportFormat::HexString),
+			"guid" => Ok(ImportFormat::Guid),
+			"yara" => Ok(ImportFormat::Yara),
+			"clamav" => Ok(ImportFormat::ClamAv),
+			"flirt" => Ok(ImportFormat::Flirt),
+			"peid" => Ok(ImportFormat::Peid),
+			"r2" => Ok(ImportFormat::Zignature),
+			_ => Err(format!("unknown format {name:?}"))
+		}
+	}
+}
+
+/// Represents the origin of a signature.
+/// ```rust
+/// use dectree_rs::provenance::{ImportFormat, Provenance};
+///
+/// let provenance = Provenance::new(ImportFormat::HexString, "55 8B EC")
+///     .path("rules/prologues.txt")
+///     .line(12);
+/// assert_eq!(provenance.to_string(), "rules/prologues.txt:12 (hex string): 55 8B EC");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Provenance {
+	/// The format the rule was written in.
+	pub format: ImportFormat,
+	/// The original text the signature was defined by.
+	pub text: String,
+	/// The whole rule the signature belongs to, for formats whose rules define several signatures
+	/// (such as the strings of a YARA rule). `None` when `text` is the whole rule.
+	pub rule: Option<String>,
+	/// The file the rule was read from, if any.
+	pub path: Option<PathBuf>,
+	/// The name of the feed the rule was delivered by, if any.
+	pub feed: Option<String>,
+	/// The line the rule starts on in its file, counting from 1.
+	pub line: Option<usize>
+}
+
+impl Provenance {
+	/// Create a new `Provenance` for a rule written as `text` in `format`, not tied to any file,
+	/// feed or line.
+	pub fn new(format: ImportFormat, text: impl Into<String>) -> Self {
+		Provenance {
+			format,
+			text: text.into(),
+			rule: None,
+			path: None,
+			feed: None,
+			line: None
+		}
+	}
+
+	/// Set the whole rule the signature belongs to.
+	pub fn rule(mut self, rule: impl Into<String>) -> Self {
+		self.rule = Some(rule.into());
+		self
+	}
+
+	/// Set the file the rule was read from.
+	pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+		self.path = Some(path.into());
+		self
+	}
+
+	/// Set the name of the feed the rule was delivered by.
+	pub fn feed(mut self, feed: impl Into<String>) -> Self {
+		self.feed = Some(feed.into());
+		self
+	}
+
+	/// Set the line the rule starts on in its file, counting from 1.
+	pub fn line(mut self, line: usize) -> Self {
+		self.line = Some(line);
+		self
+	}
+}
+
+impl Display for Provenance {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		if let Some(feed) = &self.feed {
+			write!(f, "[{feed}] ")?;
+		}
+		if let Some(path) = &self.path {
+			write!(f, "{}", path.display())?;
+			if let Some(line) = self.line {
+				write!(f, ":{line}")?;
+			}
+			write!(f, " ")?;
+		}
+		write!(f, "({}): {}", self.format, self.text)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ImportFormat, Provenance};
+
+	#[test]
+	fn test_provenance_display() {
+		assert_eq!(Provenance::new(ImportFormat::Guid, "{6b29fc40-ca47-1067-b31d-00dd010662da}").to_string(), "(GUID): {6b29fc40-ca47-1067-b31d-00dd010662da}");
+		let provenance = Provenance::new(ImportFormat::HexString, "C3").feed("nightly").path("ret.txt");
+		assert_eq!(provenance.to_string(), "[nightly] ret.txt (hex string): C3");
+	}
+}
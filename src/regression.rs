@@ -0,0 +1,237 @@
+//! A golden-corpus regression harness for signature trees.
+//!
+//! Register known samples along with the objects each one is expected to match (none for samples
+//! that must stay clean), then `check()` a tree against them to get a structured report. Samples
+//! are scanned in full, so a signature is found wherever it sits in the sample, and a sample is
+//! classified by the set of objects it hits.
+//! This lets rule-set maintainers gate updates on known-sample behavior, and `diff_scan()` shows
+//! which samples of a corpus change classification between two versions of a rule set.
+
+use std::hash::BuildHasher;
+
+use crate::SignatureDecisionTree;
+
+/// Represents a known sample and the objects it is expected to match.
+#[derive(Clone, Debug)]
+pub struct RegressionCase<T> {
+	/// A name identifying the sample in reports.
+	pub name: String,
+	/// The sample bytes. The whole sample is scanned.
+	pub input: Vec<u8>,
+	/// The distinct objects the sample should hit, or none if it should not match anything.
+	pub expected: Vec<T>
+}
+
+/// Represents a sample whose matches didn't meet expectations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegressionFailure<T> {
+	/// The name of the failing sample.
+	pub name: String,
+	/// The distinct objects the sample was expected to hit.
+	pub expected: Vec<T>,
+	/// The distinct objects the sample actually hit, in the order of their first hit.
+	pub actual: Vec<T>
+}
+
+/// Represents the outcome of checking a tree against a `RegressionSuite`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegressionReport<T> {
+	/// The number of samples that behaved as expected.
+	pub passed: usize,
+	/// The samples that didn't, in registration order.
+	pub failures: Vec<RegressionFailure<T>>
+}
+
+impl<T> RegressionReport<T> {
+	/// Check if every sample behaved as expected.
+	pub fn is_success(&self) -> bool {
+		self.failures.is_empty()
+	}
+}
+
+/// Represents a corpus sample whose classification differs between two trees.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassificationChange<T> {
+	/// The index of the sample in the corpus.
+	pub index: usize,
+	/// The distinct objects the sample hit in the old tree, in the order of their first hit.
+	pub old: Vec<T>,
+	/// The distinct objects the sample hit in the new tree, in the order of their first hit.
+	pub new: Vec<T>
+}
+
+/// Scan every sample of `corpus` with both `old_tree` and `new_tree` and report the samples whose
+/// set of hit objects changed, in corpus order. Signatures are found anywhere in a sample.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::regression::{diff_scan, ClassificationChange};
+///
+/// let mut old_tree = SignatureDecisionTree::new();
+/// old_tree.add_signature(vec![0x4d, 0x5a], None, 1);
+/// let mut new_tree = SignatureDecisionTree::new();
+/// new_tree.add_signature(vec![0x4d, 0x5a], None, 1);
+/// new_tree.add_signature(vec![0x7f, 0x45, 0x4c, 0x46], None, 2);
+/// let corpus: [&[u8]; 2] = [&[0x4d, 0x5a, 0x90], &[0x00, 0x7f, 0x45, 0x4c, 0x46]];
+/// assert_eq!(diff_scan(&old_tree, &new_tree, &corpus), vec![ClassificationChange { index: 1, old: vec![], new: vec![2] }]);
+/// ```
+pub fn diff_scan<T, S1, S2>(old_tree: &SignatureDecisionTree<T, S1>, new_tree: &SignatureDecisionTree<T, S2>, corpus: &[&[u8]]) -> Vec<ClassificationChange<T>>
+	where T: Clone + PartialEq, S1: BuildHasher, S2: BuildHasher {
+	corpus.iter().enumerate().filter_map(|(index, sample)| {
+		let (old, new) = (hit_objects(old_tree, sample), hit_objects(new_tree, sample));
+		if old.len() == new.len() && old.iter().all(|object| new.contains(object)) {
+			None
+		} else {
+			Some(ClassificationChange { index, old, new })
+		}
+	}).collect()
+}
+
+/// Represents a golden corpus of samples with their expected matches.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::regression::RegressionSuite;
+///
+/// let mut tree = SignatureDecisionTree::new();
+/// tree.add_signature(vec![0x4d, 0x5a], None, "mz".to_string());
+/// let mut suite = RegressionSuite::new();
+/// suite.add_case("pe", vec![0x4d, 0x5a, 0x90, 0x00], Some("mz".to_string()));
+/// suite.add_case("embedded", vec![0x00, 0x00, 0x4d, 0x5a], ["mz".to_string()]);
+/// suite.add_case("clean", vec![0x7f, 0x45, 0x4c, 0x46], None);
+/// assert!(suite.check(&tree).is_success());
+/// ```
+#[derive(Clone, Debug)]
+pub struct RegressionSuite<T> {
+	cases: Vec<RegressionCase<T>>
+}
+
+impl<T> Default for RegressionSuite<T> {
+	fn default() -> Self {
+		RegressionSuite {
+			cases: Vec::new()
+		}
+	}
+}
+
+impl<T> RegressionSuite<T> where T: Clone + PartialEq {
+
+	/// Create a new, empty `RegressionSuite`.
+	pub fn new() -> Self {
+		RegressionSuite::default()
+	}
+
+	/// Register a sample and the objects it is expected to match, such as `Some(object)` for one
+	/// object, `None` for a sample that must stay clean, or a list of objects. Listing an object
+	/// more than once is the same as listing it once.
+	pub fn add_case(&mut self, name: impl Into<String>, input: Vec<u8>, expected: impl IntoIterator<Item = T>) {
+		let mut distinct = Vec::new();
+		for object in expected {
+			if !distinct.contains(&object) {
+				distinct.push(object);
+			}
+		}
+		self.cases.push(RegressionCase {
+			name: name.into(),
+			input,
+			expected: distinct
+		});
+	}
+
+	/// The registered samples, in registration order.
+	pub fn cases(&self) -> &[RegressionCase<T>] {
+		&self.cases
+	}
+
+	/// Scan every sample with `tree` and report those whose set of hit objects differs from the
+	/// expected one, regardless of order.
+	pub fn check<S>(&self, tree: &SignatureDecisionTree<T, S>) -> RegressionReport<T> where S: BuildHasher {
+		let mut report = RegressionReport {
+			passed: 0,
+			failures: Vec::new()
+		};
+		for case in self.cases.iter() {
+			let actual = hit_objects(tree, &case.input);
+			if actual.len() == case.expected.len() && actual.iter().all(|object| case.expected.contains(object)) {
+				report.passed += 1;
+			} else {
+				report.failures.push(RegressionFailure {
+					name: case.name.clone(),
+					expected: case.expected.clone(),
+					actual
+				});
+			}
+		}
+		report
+	}
+}
+
+/// Get the distinct objects `sample` hits anywhere in `tree`, in the order of their first hit.
+fn hit_objects<T, S>(tree: &SignatureDecisionTree<T, S>, sample: &[u8]) -> Vec<T> where T: Clone + PartialEq, S: BuildHasher {
+	let mut objects = Vec::new();
+	for (_, object) in tree.scan(sample) {
+		if !objects.contains(&object) {
+			objects.push(object);
+		}
+	}
+	objects
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{diff_scan, ClassificationChange, RegressionFailure, RegressionSuite};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_regression_check() {
+		let mut tree = SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 1);
+		tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 2);
+		tree.add_signature(vec![0xc3], None, 3);
+		let mut suite = RegressionSuite::new();
+		suite.add_case("short", vec![0x55, 0xe9, 0xd8, 0x00], Some(1));
+		suite.add_case("long", vec![0x55, 0xe9, 0xd8, 0x01], Some(1));
+		suite.add_case("clean", vec![0x55, 0xe9], None);
+		suite.add_case("offset", [vec![0x00; 0x400], vec![0x55, 0xe9, 0xd8, 0x00]].concat(), None);
+		suite.add_case("several", vec![0xc3, 0x55, 0xe9, 0xd8, 0x00, 0xc3], [1, 3, 1]);
+		assert_eq!(suite.cases()[4].expected, vec![1, 3]);
+		let report = suite.check(&tree);
+		assert_eq!(report.passed, 3);
+		assert!(!report.is_success());
+		assert_eq!(report.failures, vec![
+			RegressionFailure {
+				name: "long".to_string(),
+				expected: vec![1],
+				actual: vec![2]
+			},
+			RegressionFailure {
+				name: "offset".to_string(),
+				expected: vec![],
+				actual: vec![1]
+			}
+		]);
+	}
+
+	#[test]
+	fn test_diff_scan() {
+		let mut old_tree = SignatureDecisionTree::new();
+		old_tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 1);
+		old_tree.add_signature(vec![0x4d, 0x5a], None, 3);
+		let mut new_tree = SignatureDecisionTree::new();
+		new_tree.add_signature(vec![0x55, 0xe9, 0xd8], None, 1);
+		new_tree.add_signature(vec![0x55, 0xe9, 0xd8, 0x01], None, 2);
+		new_tree.add_signature(vec![0x4d, 0x5a], None, 3);
+		new_tree.add_signature(vec![0xc3], None, 4);
+		let corpus: [&[u8]; 6] = [
+			&[0x55, 0xe9, 0xd8, 0x00],
+			&[0x55, 0xe9, 0xd8, 0x01],
+			&[0x90, 0x4d, 0x5a],
+			&[0x4d, 0x5a, 0xc3],
+			&[0x4d, 0x5a, 0x55, 0xe9, 0xd8],
+			&[]
+		];
+		assert_eq!(diff_scan(&old_tree, &new_tree, &corpus), vec![
+			ClassificationChange { index: 1, old: vec![1], new: vec![2] },
+			ClassificationChange { index: 3, old: vec![3], new: vec![3, 4] }
+		]);
+		assert!(diff_scan(&old_tree, &old_tree, &corpus).is_empty());
+	}
+}
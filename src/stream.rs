@@ -0,0 +1,191 @@
+//! Scanning of streams too large to hold in memory.
+//!
+//! `StreamScanner` reads its input in chunks and scans each one, carrying the last
+//! `max_signature_length() - 1` bytes over into the next chunk. An offset is only looked up once
+//! every byte a signature could cover there has been read, so the hits are exactly those a scan of
+//! the whole input at once would report, including matches spanning chunk boundaries.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io::{ErrorKind, Read};
+
+#[cfg(feature = "journal")]
+use crate::journal::Journal;
+use crate::SignatureDecisionTree;
+
+/// The number of bytes read from the stream at a time, unless set with `chunk_size()`.
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Represents what a scan reports as it goes.
+enum ScanEvent<T> {
+	/// The object of the signature preferred at a stream offset.
+	Hit(u64, T),
+	/// Every offset below the stream offset has been scanned. Only the journal keeps track of it.
+	#[cfg_attr(not(feature = "journal"), allow(dead_code))]
+	Scanned(u64)
+}
+
+/// Represents a scan of a `Read` stream against a tree.
+/// ```rust
+/// use dectree_rs::SignatureDecisionTree;
+/// use dectree_rs::stream::StreamScanner;
+///
+/// let mut tree = SignatureDecisionTree::new();
+/// tree.add_signature(vec![0x55, 0x8b, 0xec], None, 1);
+/// let input: &[u8] = &[0x90, 0x90, 0x55, 0x8b, 0xec, 0x90, 0x55, 0x8b, 0xec];
+/// let hits = StreamScanner::new(&tree, input).chunk_size(4).scan().unwrap();
+/// assert_eq!(hits, vec![(2, 1), (6, 1)]);
+/// ```
+pub struct StreamScanner<'a, R, T, S = RandomState> where T: Clone {
+	tree: &'a SignatureDecisionTree<T, S>,
+	reader: R,
+	chunk_size: usize,
+	start_offset: u64
+}
+
+impl<'a, R, T, S> StreamScanner<'a, R, T, S> where R: Read, T: Clone, S: BuildHasher {
+	/// Create a new `StreamScanner` that scans everything `reader` yields against `tree`.
+	pub fn new(tree: &'a SignatureDecisionTree<T, S>, reader: R) -> Self {
+		StreamScanner {
+			tree,
+			reader,
+			chunk_size: DEFAULT_CHUNK_SIZE,
+			start_offset: 0
+		}
+	}
+
+	/// Set the number of bytes read from the stream at a time. Memory use is bounded by the chunk
+	/// size plus the length of the longest signature.
+	///
+	/// # Panics
+	/// Panics if `chunk_size` is zero.
+	pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+		assert!(chunk_size > 0, "chunk size must be non-zero");
+		self.chunk_size = chunk_size;
+		self
+	}
+
+	/// Set the stream offset the reader is positioned at, so hits are reported at their offsets in
+	/// the whole stream. The scan doesn't seek; the reader must already be at `start_offset`, such
+	/// as after a `Seek::seek()` to resume an interrupted scan.
+	/// ```rust
+	/// use std::io::{Cursor, Seek, SeekFrom};
+	/// use dectree_rs::SignatureDecisionTree;
+	/// use dectree_rs::stream::StreamScanner;
+	///
+	/// let mut tree = SignatureDecisionTree::new();
+	/// tree.add_signature(vec![0xc3], None, "ret");
+	/// let mut input = Cursor::new([0xc3, 0x90, 0xc3]);
+	/// input.seek(SeekFrom::Start(1)).unwrap();
+	/// assert_eq!(StreamScanner::new(&tree, input).start_offset(1).scan().unwrap(), vec![(2, "ret")]);
+	/// ```
+	pub fn start_offset(mut self, start_offset: u64) -> Self {
+		self.start_offset = start_offset;
+		self
+	}
+
+	/// Scan the whole stream, returning every hit as the stream offset it matched at along with
+	/// the object of the signature preferred there, in offset order.
+	pub fn scan(self) -> std::io::Result<Vec<(u64, T)>> {
+		let mut hits = Vec::new();
+		self.for_each_hit(|offset, val| hits.push((offset, val)))?;
+		Ok(hits)
+	}
+
+	/// Scan the whole stream, handing each hit to `on_hit` as soon as it is found rather than
+	/// collecting them. Returns the stream offset the scan ended at, which is the number of bytes
+	/// read unless a `start_offset()` was set.
+	pub fn for_each_hit<F>(self, mut on_hit: F) -> std::io::Result<u64> where F: FnMut(u64, T) {
+		self.try_for_each_event(|event| {
+			if let ScanEvent::Hit(offset, val) = event {
+				on_hit(offset, val);
+			}
+			Ok(())
+		})
+	}
+
+	/// Scan the whole stream, writing each hit to `journal` as soon as it is found, so the hits
+	/// found so far survive a crash mid-scan, along with progress records to resume it from. Returns
+	/// the stream offset the scan ended at. The scan stops at the first record that can't be written.
+	#[cfg(feature = "journal")]
+	pub fn scan_journaled<W>(self, journal: &mut Journal<W>) -> std::io::Result<u64> where T: std::fmt::Display, W: std::io::Write {
+		let end = self.try_for_each_event(|event| match event {
+			ScanEvent::Hit(offset, val) => journal.record_match(offset, &val),
+			ScanEvent::Scanned(offset) => journal.checkpoint(offset)
+		})?;
+		journal.record_progress(end)?;
+		Ok(end)
+	}
+
+	/// Scan the whole stream, handing each hit and the progress after each chunk to `on_event`, and
+	/// stopping at the first error either reading the stream or returned by `on_event`.
+	fn try_for_each_event<F>(mut self, mut on_event: F) -> std::io::Result<u64> where F: FnMut(ScanEvent<T>) -> std::io::Result<()> {
+		let carry = self.tree.max_signature_length().saturating_sub(1);
+		let mut window = Vec::with_capacity(carry + self.chunk_size);
+		// The stream offset of the first byte in `window`.
+		let mut base = self.start_offset;
+		loop {
+			let filled = window.len();
+			window.resize(filled + self.chunk_size, 0);
+			let read = loop {
+				match self.reader.read(&mut window[filled..]) {
+					Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+					result => break result?
+				}
+			};
+			window.truncate(filled + read);
+			let at_end = read == 0;
+			// Offsets closer to the end than the longest signature could still grow a longer match
+			// once more input arrives, so they wait for the next chunk unless the stream is done.
+			let decided = if at_end { window.len() } else { window.len().saturating_sub(carry) };
+			for offset in self.tree.candidate_offsets(&window[..decided]) {
+				if let Some(val) = self.tree.lookup(&window[..], offset as i32) {
+					on_event(ScanEvent::Hit(base + offset as u64, val))?;
+				}
+			}
+			window.drain(..decided);
+			base += decided as u64;
+			on_event(ScanEvent::Scanned(base))?;
+			if at_end {
+				return Ok(base)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::StreamScanner;
+	use crate::constraint::{Computation, Constraint};
+	use crate::SignatureDecisionTree;
+
+	#[test]
+	fn test_stream_scanner() {
+		let mut tree = SignatureDecisionTree::new();
+		tree.add_signature(vec![0x55, 0x8b], None, 1);
+		tree.add_signature(vec![0x55, 0x8b, 0xec, 0x83, 0xec], None, 2);
+		tree.add_signature(vec![0xc3], None, 3);
+		let input = [0x55, 0x8b, 0xec, 0x83, 0xec, 0x90, 0x55, 0x8b, 0xc3, 0x55, 0x8b, 0xec, 0x83];
+		let expected = tree.scan(input).into_iter().map(|(offset, val)| (offset as u64, val)).collect::<Vec<_>>();
+		for chunk_size in 1..=input.len() + 1 {
+			assert_eq!(StreamScanner::new(&tree, &input[..]).chunk_size(chunk_size).scan().unwrap(), expected);
+		}
+		let mut count = 0;
+		assert_eq!(StreamScanner::new(&tree, &input[..]).for_each_hit(|_, _| count += 1).unwrap(), input.len() as u64);
+		assert_eq!(count, expected.len());
+		assert!(StreamScanner::new(&SignatureDecisionTree::<i32>::new(), &input[..]).scan().unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_stream_scanner_constraints() {
+		let mut tree = SignatureDecisionTree::new();
+		let id = tree.add_signature_with_id(vec![0xa5, 0x00, 0x00, 0x00], Some(vec![0xff, 0x00, 0x00, 0x00]), "record").unwrap();
+		assert!(tree.set_constraints(id, vec![Constraint::new(3, Computation::Sum(1..3))]));
+		let input = [0x90, 0xa5, 0x10, 0x20, 0x30, 0x00, 0xa5, 0x10, 0x20, 0x31];
+		assert_eq!(tree.scan(input), vec![(1, "record")]);
+		// At small chunk sizes the checksum is read in a later chunk than the magic byte.
+		for chunk_size in 1..=input.len() {
+			assert_eq!(StreamScanner::new(&tree, &input[..]).chunk_size(chunk_size).scan().unwrap(), vec![(1, "record")]);
+		}
+	}
+}
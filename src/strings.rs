@@ -0,0 +1,131 @@
+//! Extraction of printable strings, for reporting them alongside signature hits.
+//!
+//! Both plain ASCII strings and UTF-16LE strings (ASCII characters each followed by a zero byte,
+//! as Windows binaries store them) are found. A character is printable if it is in the range
+//! `0x20..=0x7e` or is a tab.
+
+use crate::Match;
+
+/// Represents the ways a string can be stored in a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StringEncoding {
+	/// One byte per character.
+	Ascii,
+	/// Two bytes per character, low byte first.
+	Utf16Le
+}
+
+/// Represents a printable string found in a buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtractedString {
+	/// The offset in the buffer the string starts at.
+	pub offset: usize,
+	/// How the string is stored.
+	pub encoding: StringEncoding,
+	/// The characters of the string.
+	pub text: String
+}
+
+impl ExtractedString {
+	/// Get the number of buffer bytes the string occupies.
+	pub fn byte_len(&self) -> usize {
+		match self.encoding {
+			StringEncoding::Ascii => self.text.len(),
+			StringEncoding::Utf16Le => self.text.len() * 2
+		}
+	}
+}
+
+/// Represents a match along with the strings found around it, as returned by
+/// `SignatureDecisionTree::scan_with_strings()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnotatedMatch<T> {
+	/// The match itself.
+	pub found: Match<T>,
+	/// The strings overlapping the match or the bytes around it, in offset order.
+	pub strings: Vec<ExtractedString>
+}
+
+/// Check if `byte` is a printable character.
+fn is_printable(byte: u8) -> bool {
+	matches!(byte, 0x20..=0x7e | b'\t')
+}
+
+/// Find every string of at least `min_length` characters in `buffer`, in offset order. ASCII and
+/// UTF-16LE strings are both reported, so the two lists are interleaved.
+/// ```rust
+/// use dectree_rs::strings::{extract_strings, StringEncoding};
+///
+/// let buffer = b"\x00\x01kernel32\x00\x02N\x00t\x00Q\x00u\x00\x00\x00";
+/// let strings = extract_strings(buffer, 4);
+/// assert_eq!(strings.len(), 2);
+/// assert_eq!((strings[0].offset, strings[0].encoding, strings[0].text.as_str()), (2, StringEncoding::Ascii, "kernel32"));
+/// assert_eq!((strings[1].offset, strings[1].encoding, strings[1].text.as_str()), (12, StringEncoding::Utf16Le, "NtQu"));
+/// ```
+pub fn extract_strings(buffer: &[u8], min_length: usize) -> Vec<ExtractedString> {
+	let min_length = min_length.max(1);
+	let (mut strings, mut offset) = (Vec::new(), 0);
+	for run in buffer.split(|byte| !is_printable(*byte)) {
+		if run.len() >= min_length {
+			strings.push(ExtractedString {
+				offset,
+				encoding: StringEncoding::Ascii,
+				text: run.iter().map(|byte| *byte as char).collect()
+			});
+		}
+		// Skip the run and the unprintable byte that ended it.
+		offset += run.len() + 1;
+	}
+	let is_wide = |index: usize| index + 1 < buffer.len() && is_printable(buffer[index]) && buffer[index + 1] == 0x00;
+	let mut index = 0;
+	while index < buffer.len() {
+		if !is_wide(index) {
+			index += 1;
+			continue
+		}
+		let start = index;
+		while is_wide(index) {
+			index += 2;
+		}
+		if (index - start) / 2 >= min_length {
+			strings.push(ExtractedString {
+				offset: start,
+				encoding: StringEncoding::Utf16Le,
+				text: buffer[start..index].iter().step_by(2).map(|byte| *byte as char).collect()
+			});
+		}
+	}
+	strings.sort_by_key(|string| string.offset);
+	strings
+}
+
+/// Get the strings among `strings` (sorted by offset, as `extract_strings()` returns them) that
+/// overlap `found` or the `radius` bytes on either side of it.
+pub fn strings_near<T>(strings: &[ExtractedString], found: &Match<T>, radius: usize) -> Vec<ExtractedString> {
+	let start = found.offset.saturating_sub(radius);
+	let end = found.offset + found.length + radius;
+	let candidates = strings.partition_point(|string| string.offset < end);
+	strings[..candidates].iter()
+		.filter(|string| string.offset + string.byte_len() > start)
+		.cloned()
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{extract_strings, strings_near, StringEncoding};
+	use crate::{Match, SignatureId};
+
+	#[test]
+	fn test_extract_strings() {
+		let buffer = b"abc\x00GetProc\x00\x00A\x00B\x00C\x00\x00";
+		let strings = extract_strings(buffer, 3);
+		let found = strings.iter().map(|string| (string.offset, string.encoding, string.text.as_str())).collect::<Vec<_>>();
+		assert_eq!(found, vec![(0, StringEncoding::Ascii, "abc"), (4, StringEncoding::Ascii, "GetProc"), (13, StringEncoding::Utf16Le, "ABC")]);
+		assert!(extract_strings(b"ab\x00c", 3).is_empty());
+		let found = Match { id: SignatureId(0), offset: 8, length: 2, bytes: vec![], masks: vec![], object: (), enabled: true };
+		assert_eq!(strings_near(&strings, &found, 2).len(), 1);
+		assert_eq!(strings_near(&strings, &found, 4).len(), 2);
+		assert_eq!(strings_near(&strings, &found, 6).len(), 3);
+	}
+}
@@ -0,0 +1,215 @@
+//! Targeting signatures at the kind of input they apply to.
+//!
+//! A `Target` describes an architecture, bitness, operating system and file type, any of which may
+//! be left open. Attached to a signature with `SignatureDecisionTree::set_target()`, it limits the
+//! signature to inputs of that kind; passed to a query such as
+//! `SignatureDecisionTree::scan_for()`, it describes the input being scanned. Signatures whose
+//! target conflicts with the input are never reported, so x86 prologue rules don't fire on ARM
+//! firmware, and their bytes aren't compared once the walk narrows down to them. A field left open
+//! on either side never conflicts.
+//! ```rust
+//! use dectree_rs::SignatureDecisionTree;
+//! use dectree_rs::target::{Arch, Target};
+//!
+//! let mut tree = SignatureDecisionTree::new();
+//! let id = tree.add_signature_with_id(vec![0x55, 0x8b, 0xec], None, "x86 prologue").unwrap();
+//! tree.set_target(id, Target::any().arch(Arch::X86));
+//! let firmware = [0x55, 0x8b, 0xec, 0x00];
+//! assert_eq!(tree.scan_for(firmware, &Target::any().arch(Arch::Arm)), vec![]);
+//! assert_eq!(tree.scan_for(firmware, &Target::any()), vec![(0, "x86 prologue")]);
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+/// Represents the instruction set architectures a signature can target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Arch {
+	/// x86, in 16, 32 or 64-bit mode.
+	X86,
+	/// ARM, including Thumb and AArch64.
+	Arm,
+	/// MIPS.
+	Mips,
+	/// PowerPC.
+	PowerPc,
+	/// RISC-V.
+	RiscV
+}
+
+impl Display for Arch {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Arch::X86 => write!(f, "x86"),
+			Arch::Arm => write!(f, "ARM"),
+			Arch::Mips => write!(f, "MIPS"),
+			Arch::PowerPc => write!(f, "PowerPC"),
+			Arch::RiscV => write!(f, "RISC-V")
+		}
+	}
+}
+
+/// Represents the operating systems a signature can target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Os {
+	/// Windows.
+	Windows,
+	/// Linux, other than Android.
+	Linux,
+	/// macOS.
+	MacOs,
+	/// Android.
+	Android,
+	/// iOS.
+	Ios,
+	/// No operating system, as in firmware running on bare metal.
+	None
+}
+
+impl Display for Os {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Os::Windows => write!(f, "Windows"),
+			Os::Linux => write!(f, "Linux"),
+			Os::MacOs => write!(f, "macOS"),
+			Os::Android => write!(f, "Android"),
+			Os::Ios => write!(f, "iOS"),
+			Os::None => write!(f, "bare metal")
+		}
+	}
+}
+
+/// Represents the file types a signature can target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum FileType {
+	/// A Portable Executable, as used on Windows.
+	Pe,
+	/// An Executable and Linkable Format file.
+	Elf,
+	/// A Mach-O file, as used on macOS and iOS.
+	MachO,
+	/// A raw firmware or memory image without an executable format.
+	Raw
+}
+
+impl Display for FileType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			FileType::Pe => write!(f, "PE"),
+			FileType::Elf => write!(f, "ELF"),
+			FileType::MachO => write!(f, "Mach-O"),
+			FileType::Raw => write!(f, "raw image")
+		}
+	}
+}
+
+/// Represents the kind of input a signature applies to, or the kind of input being scanned. Fields
+/// left as `None` are open.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Target {
+	/// The instruction set architecture.
+	pub arch: Option<Arch>,
+	/// The word size in bits, such as 32 or 64.
+	pub bitness: Option<u8>,
+	/// The operating system.
+	pub os: Option<Os>,
+	/// The file type.
+	pub file_type: Option<FileType>
+}
+
+impl Target {
+	/// Create a new `Target` with every field open.
+	pub fn any() -> Self {
+		Target::default()
+	}
+
+	/// Set the instruction set architecture.
+	pub fn arch(mut self, arch: Arch) -> Self {
+		self.arch = Some(arch);
+		self
+	}
+
+	/// Set the word size in bits.
+	pub fn bitness(mut self, bitness: u8) -> Self {
+		self.bitness = Some(bitness);
+		self
+	}
+
+	/// Set the operating system.
+	pub fn os(mut self, os: Os) -> Self {
+		self.os = Some(os);
+		self
+	}
+
+	/// Set the file type.
+	pub fn file_type(mut self, file_type: FileType) -> Self {
+		self.file_type = Some(file_type);
+		self
+	}
+
+	/// Check whether the two targets can describe the same input: every field set on both sides
+	/// must agree.
+	/// ```rust
+	/// use dectree_rs::target::{Arch, Os, Target};
+	///
+	/// let rule = Target::any().arch(Arch::X86).bitness(32);
+	/// assert!(rule.is_compatible(&Target::any().arch(Arch::X86).os(Os::Windows)));
+	/// assert!(!rule.is_compatible(&Target::any().bitness(64)));
+	/// ```
+	pub fn is_compatible(&self, other: &Target) -> bool {
+		fn agree<V>(first: &Option<V>, second: &Option<V>) -> bool where V: PartialEq {
+			match (first, second) {
+				(Some(first), Some(second)) => first == second,
+				_ => true
+			}
+		}
+		agree(&self.arch, &other.arch)
+			&& agree(&self.bitness, &other.bitness)
+			&& agree(&self.os, &other.os)
+			&& agree(&self.file_type, &other.file_type)
+	}
+}
+
+impl Display for Target {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let mut fields = Vec::new();
+		if let Some(arch) = self.arch {
+			fields.push(arch.to_string());
+		}
+		if let Some(bitness) = self.bitness {
+			fields.push(format!("{bitness}-bit"));
+		}
+		if let Some(os) = self.os {
+			fields.push(os.to_string());
+		}
+		if let Some(file_type) = self.file_type {
+			fields.push(file_type.to_string());
+		}
+		match fields.is_empty() {
+			true => write!(f, "any"),
+			false => write!(f, "{}", fields.join(" "))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Arch, FileType, Os, Target};
+
+	#[test]
+	fn test_target() {
+		let rule = Target::any().arch(Arch::Arm).os(Os::None);
+		assert!(rule.is_compatible(&Target::any()));
+		assert!(rule.is_compatible(&Target::any().arch(Arch::Arm).bitness(32).file_type(FileType::Raw)));
+		assert!(!rule.is_compatible(&Target::any().os(Os::Linux)));
+		assert_eq!(rule.to_string(), "ARM bare metal");
+		assert_eq!(Target::any().bitness(64).file_type(FileType::Elf).to_string(), "64-bit ELF");
+		assert_eq!(Target::any().to_string(), "any");
+	}
+}